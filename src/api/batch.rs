@@ -0,0 +1,241 @@
+//! OData `$batch` requests
+//!
+//! Several operations are sent as one `multipart/mixed` POST and their
+//! responses come back as parts of a single multipart body, in the same order:
+//!
+//! ```text
+//! --batch_1
+//! Content-Type: application/http
+//! Content-Transfer-Encoding: binary
+//!
+//! GET https://org.crm.dynamics.com/api/data/v9.2/teams(...)/teamroles_association HTTP/1.1
+//! Accept: application/json
+//!
+//! --batch_1--
+//! ```
+//!
+//! Only GET operations are supported for now. Writes will need to be wrapped
+//! in change sets, which are nested multipart bodies; the response parser
+//! already flattens those. They will also need to opt out of retrying, since
+//! a replayed change set could be applied twice.
+
+use super::DataverseClient;
+use super::error::DataverseError;
+use anyhow::{Context, Result, bail};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most operations Dataverse accepts in one batch
+const MAX_BATCH_SIZE: usize = 1000;
+
+struct Operation {
+    method: Method,
+    endpoint: String,
+}
+
+/// Builder for a `$batch` call, created with `DataverseClient::batch`
+pub struct BatchRequest<'a> {
+    client: &'a DataverseClient,
+    operations: Vec<Operation>,
+}
+
+/// Response to one operation in a batch
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl BatchResponse {
+    /// Deserialize the JSON body, or return the operation's error
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        if !self.status.is_success() {
+            return Err(DataverseError::from_response(self.status, &self.body).into());
+        }
+        serde_json::from_str(&self.body).context("Failed to parse JSON response")
+    }
+}
+
+impl DataverseClient {
+    /// Start a `$batch` call
+    pub fn batch(&self) -> BatchRequest<'_> {
+        BatchRequest {
+            client: self,
+            operations: Vec::new(),
+        }
+    }
+}
+
+impl BatchRequest<'_> {
+    /// Add a GET request for an endpoint relative to the API root, or an
+    /// absolute URL such as an `@odata.nextLink`
+    pub fn get(mut self, endpoint: impl Into<String>) -> Self {
+        self.operations.push(Operation {
+            method: Method::GET,
+            endpoint: endpoint.into(),
+        });
+        self
+    }
+
+    /// Send the operations and return their responses in the order they were
+    /// added
+    ///
+    /// A failed operation does not stop the others; its error is returned by
+    /// `BatchResponse::json`. More than 1000 operations are split over
+    /// several calls, and no call is made when there are none. Throttled calls
+    /// are sent again in full, which is only safe while batches carry reads.
+    pub async fn send(self) -> Result<Vec<BatchResponse>> {
+        let api_url = self.client.api_url();
+        let mut responses = Vec::with_capacity(self.operations.len());
+
+        for chunk in self.operations.chunks(MAX_BATCH_SIZE) {
+            let boundary = new_boundary();
+            let body = serialize(chunk, &api_url, &boundary);
+            let response = self.client.post_batch(&boundary, body).await?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let text = response.text().await.context("Failed to read batch response")?;
+
+            let parsed = parse_response(&content_type, &text)?;
+            if parsed.len() != chunk.len() {
+                bail!("Batch returned {} responses for {} requests", parsed.len(), chunk.len());
+            }
+            responses.extend(parsed);
+        }
+
+        Ok(responses)
+    }
+}
+
+fn new_boundary() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("batch_{:x}", nanos)
+}
+
+/// Write the operations as a `multipart/mixed` body
+fn serialize(operations: &[Operation], api_url: &str, boundary: &str) -> String {
+    let mut body = String::new();
+    for operation in operations {
+        let url = if operation.endpoint.starts_with("http") {
+            operation.endpoint.clone()
+        } else {
+            format!("{}/{}", api_url, operation.endpoint.trim_start_matches('/'))
+        };
+        body.push_str(&format!("--{}\r\n", boundary));
+        body.push_str("Content-Type: application/http\r\n");
+        body.push_str("Content-Transfer-Encoding: binary\r\n\r\n");
+        // The request line is not sent through an URL parser, so spaces in
+        // filters have to be encoded here
+        body.push_str(&format!("{} {} HTTP/1.1\r\n", operation.method, url.replace(' ', "%20")));
+        body.push_str("Accept: application/json\r\n");
+        body.push_str("Prefer: odata.include-annotations=\"*\"\r\n\r\n");
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body
+}
+
+/// Read the boundary parameter of a `multipart/mixed` content type
+fn boundary_of(content_type: &str) -> Option<&str> {
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|b| b.trim_matches('"'))
+        .next()
+}
+
+/// Split a `multipart/mixed` response into the responses it contains
+///
+/// Change set parts are nested multipart bodies and are flattened in order.
+fn parse_response(content_type: &str, body: &str) -> Result<Vec<BatchResponse>> {
+    let boundary = boundary_of(content_type)
+        .with_context(|| format!("Batch response has no boundary: '{}'", content_type))?;
+    let body = body.replace("\r\n", "\n");
+    let delimiter = format!("--{}", boundary);
+    let mut responses = Vec::new();
+
+    // The first segment is the preamble and a segment starting with "--" is
+    // the epilogue after the closing delimiter
+    for part in body.split(delimiter.as_str()).skip(1) {
+        if part.starts_with("--") {
+            break;
+        }
+        let (headers, message) = part
+            .trim_start_matches('\n')
+            .split_once("\n\n")
+            .context("Batch response part has no body")?;
+        let part_type = headers
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-type").then(|| value.trim())
+            })
+            .unwrap_or_default();
+        if part_type.starts_with("multipart/mixed") {
+            responses.extend(parse_response(part_type, message)?);
+            continue;
+        }
+
+        let (status_line, rest) = message.split_once('\n').unwrap_or((message, ""));
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse::<u16>().ok())
+            .and_then(|code| StatusCode::from_u16(code).ok())
+            .with_context(|| format!("Invalid status line in batch response: '{}'", status_line))?;
+        let body = rest.split_once("\n\n").map(|(_, body)| body).unwrap_or_default();
+        responses.push(BatchResponse {
+            status,
+            body: body.trim_end().to_string(),
+        });
+    }
+
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_and_parse_batch() {
+        let operations = [
+            Operation { method: Method::GET, endpoint: "teams(1)/teamroles_association?$filter=ismanaged eq false".to_string() },
+            Operation { method: Method::GET, endpoint: "https://org.crm.dynamics.com/api/data/v9.2/teams(2)".to_string() },
+        ];
+        let body = serialize(&operations, "https://org.crm.dynamics.com/api/data/v9.2", "batch_1");
+        assert!(body.starts_with("--batch_1\r\nContent-Type: application/http\r\n"));
+        assert!(body.contains(
+            "GET https://org.crm.dynamics.com/api/data/v9.2/teams(1)/teamroles_association?$filter=ismanaged%20eq%20false HTTP/1.1\r\n"
+        ));
+        assert!(body.contains("GET https://org.crm.dynamics.com/api/data/v9.2/teams(2) HTTP/1.1\r\n"));
+        assert!(body.ends_with("\r\n--batch_1--\r\n"));
+
+        let response = "--batchresponse_a\r\nContent-Type: application/http\r\nContent-Transfer-Encoding: binary\r\n\r\n\
+                        HTTP/1.1 200 OK\r\nContent-Type: application/json; odata.metadata=minimal\r\nOData-Version: 4.0\r\n\r\n\
+                        {\"value\":[{\"name\":\"Sales\"}]}\r\n\
+                        --batchresponse_a\r\nContent-Type: multipart/mixed; boundary=changesetresponse_b\r\n\r\n\
+                        --changesetresponse_b\r\nContent-Type: application/http\r\n\r\n\
+                        HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\n\r\n\
+                        {\"error\":{\"code\":\"0x80040217\",\"message\":\"team With Id = 2 Does Not Exist\"}}\r\n\
+                        --changesetresponse_b--\r\n\
+                        --batchresponse_a--\r\n";
+        let responses = parse_response("multipart/mixed; boundary=batchresponse_a", response).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].status, StatusCode::OK);
+        let value: serde_json::Value = responses[0].json().unwrap();
+        assert_eq!(value["value"][0]["name"], "Sales");
+
+        let err = responses[1].json::<serde_json::Value>().unwrap_err();
+        let err = err.downcast_ref::<DataverseError>().unwrap();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        assert_eq!(err.code.as_deref(), Some("0x80040217"));
+    }
+}
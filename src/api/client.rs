@@ -1,22 +1,66 @@
 //! Dataverse Web API client
 
+use super::error::DataverseError;
+use super::retry::{self, RetryPolicy};
 use crate::auth::AzureAuthenticator;
-use anyhow::{Context, Result};
-use reqwest::{Client, Response};
+use crate::snapshot::Snapshot;
+use anyhow::{Context, Result, bail};
+use azure_core::credentials::AccessToken;
+use azure_core::date::OffsetDateTime;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Refresh the access token this long before it expires
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Keep showing the throttle indicator this long after the last wait ended
+const THROTTLE_INDICATOR_LINGER: Duration = Duration::from_secs(30);
+
+/// Most requests a single action keeps in flight at once
+///
+/// Well below the Dataverse limit of 52 concurrent requests per user, so
+/// other tasks and other tools are not throttled.
+pub const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// Where requests are answered
+enum Backend {
+    /// A live environment, signed in with the authenticator
+    Live(Arc<AzureAuthenticator>),
+    /// A read-only snapshot that only answers metadata requests
+    Snapshot(Arc<Snapshot>),
+}
 
 /// HTTP client for Dataverse Web API
 pub struct DataverseClient {
     http_client: Client,
-    authenticator: Arc<AzureAuthenticator>,
-    cached_token: RwLock<Option<String>>,
+    backend: Backend,
+    cached_token: Mutex<Option<AccessToken>>,
+    retry_policy: RetryPolicy,
+    /// When the most recent wait for a 429 ends
+    throttled_until: std::sync::Mutex<Option<Instant>>,
+}
+
+/// Whether a token can still be used without refreshing
+fn is_fresh(token: &AccessToken, now: OffsetDateTime) -> bool {
+    token.expires_on - TOKEN_REFRESH_MARGIN > now
 }
 
 impl DataverseClient {
     /// Create a new Dataverse client
     pub fn new(authenticator: Arc<AzureAuthenticator>) -> Self {
+        Self::with_backend(Backend::Live(authenticator))
+    }
+
+    /// Create an offline client that answers metadata requests from a
+    /// snapshot; every other request fails
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        Self::with_backend(Backend::Snapshot(Arc::new(snapshot)))
+    }
+
+    fn with_backend(backend: Backend) -> Self {
         let http_client = Client::builder()
             .user_agent("Rynamo/0.1.0")
             .build()
@@ -24,28 +68,113 @@ impl DataverseClient {
 
         Self {
             http_client,
-            authenticator,
-            cached_token: RwLock::new(None),
+            backend,
+            cached_token: Mutex::new(None),
+            retry_policy: RetryPolicy::default(),
+            throttled_until: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Use a different retry policy
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// The snapshot this client browses, when offline
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        match &self.backend {
+            Backend::Live(_) => None,
+            Backend::Snapshot(snapshot) => Some(snapshot),
+        }
+    }
+
+    fn authenticator(&self) -> Result<&AzureAuthenticator> {
+        match &self.backend {
+            Backend::Live(authenticator) => Ok(authenticator),
+            Backend::Snapshot(_) => bail!("Not available offline: the snapshot only contains metadata"),
         }
     }
 
-    /// Get or refresh the authentication token
+    /// Whether service protection limits slowed down a recent request
+    pub fn is_throttled(&self) -> bool {
+        let until = *self.throttled_until.lock().unwrap();
+        until.is_some_and(|until| until + THROTTLE_INDICATOR_LINGER > Instant::now())
+    }
+
+    /// Send a request, retrying throttled and transient failures
+    ///
+    /// `build` is called again for every attempt since a sent request cannot
+    /// be reused. The last response is returned once retries run out.
+    async fn send_with_retry(&self, build: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let response = build().send().await?;
+            let status = response.status();
+            if !RetryPolicy::is_retryable(status) || attempt >= self.retry_policy.max_retries {
+                return Ok(response);
+            }
+
+            let delay = self.retry_policy.delay(attempt, retry::retry_after(&response));
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                *self.throttled_until.lock().unwrap() = Some(Instant::now() + delay);
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a request with the cached token, retrying once with a new token
+    /// when the server rejects it
+    ///
+    /// A token can be revoked before it expires, for example after signing in
+    /// to the Azure CLI as another account.
+    async fn send_authorized(&self, build: impl Fn(&str) -> RequestBuilder) -> Result<Response> {
+        let token = self.get_token().await?;
+        let response = self.send_with_retry(|| build(&token)).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        self.invalidate_token(&token).await?;
+        let token = self.get_token().await?;
+        Ok(self.send_with_retry(|| build(&token)).await?)
+    }
+
+    /// Forget a token the server rejected, unless another request already
+    /// replaced it
+    async fn invalidate_token(&self, rejected: &str) -> Result<()> {
+        let mut cached = self.cached_token.lock().await;
+        if cached.as_ref().is_some_and(|t| t.token.secret() == rejected) {
+            *cached = None;
+            self.authenticator()?.clear_cache().await?;
+        }
+        Ok(())
+    }
+
+    /// Get the cached token, refreshing it shortly before it expires
+    ///
+    /// The lock is held while refreshing, so concurrent requests wait for a
+    /// single refresh instead of each asking the authenticator.
     async fn get_token(&self) -> Result<String> {
-        // For simplicity, always get a fresh token
-        // In production, we'd cache and check expiry
-        let token = self.authenticator.get_token().await?;
-        *self.cached_token.write().await = Some(token.clone());
-        Ok(token)
+        let mut cached = self.cached_token.lock().await;
+        if let Some(token) = cached.as_ref().filter(|t| is_fresh(t, OffsetDateTime::now_utc())) {
+            return Ok(token.token.secret().to_string());
+        }
+
+        let token = self.authenticator()?.get_access_token().await?;
+        let secret = token.token.secret().to_string();
+        *cached = Some(token);
+        Ok(secret)
     }
 
     /// Get the base API URL
-    fn api_url(&self) -> String {
-        format!("{}/api/data/v9.2", self.authenticator.environment_url())
+    pub(super) fn api_url(&self) -> String {
+        format!("{}/api/data/v9.2", self.environment_url())
     }
 
     /// Make an authenticated GET request
     pub async fn get(&self, endpoint: &str) -> Result<Response> {
-        let token = self.get_token().await?;
         let url = if endpoint.starts_with("http") {
             endpoint.to_string()
         } else {
@@ -53,21 +182,53 @@ impl DataverseClient {
         };
 
         let response = self
-            .http_client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("Accept", "application/json")
-            .header("OData-MaxVersion", "4.0")
-            .header("OData-Version", "4.0")
-            .header("Prefer", "odata.include-annotations=\"*\"")
-            .send()
+            .send_authorized(|token| {
+                self.http_client
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header("Accept", "application/json")
+                    .header("OData-MaxVersion", "4.0")
+                    .header("OData-Version", "4.0")
+                    .header("Prefer", "odata.include-annotations=\"*\"")
+            })
             .await
             .context("Failed to send request to Dataverse")?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("API request failed with status {}: {}", status, body);
+            return Err(DataverseError::from_response(status, &body).into());
+        }
+
+        Ok(response)
+    }
+
+    /// POST a `multipart/mixed` body to the `$batch` endpoint
+    ///
+    /// The whole body is sent again when throttled, so batches must only
+    /// carry reads until writes get a non-retrying path.
+    pub(super) async fn post_batch(&self, boundary: &str, body: String) -> Result<Response> {
+        let url = format!("{}/$batch", self.api_url());
+
+        let response = self
+            .send_authorized(|token| {
+                self.http_client
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header("Content-Type", format!("multipart/mixed; boundary={}", boundary))
+                    .header("Accept", "application/json")
+                    .header("OData-MaxVersion", "4.0")
+                    .header("OData-Version", "4.0")
+                    .header("Prefer", "odata.continue-on-error")
+                    .body(body.clone())
+            })
+            .await
+            .context("Failed to send batch request to Dataverse")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(DataverseError::from_response(status, &body).into());
         }
 
         Ok(response)
@@ -85,7 +246,10 @@ impl DataverseClient {
 
     /// Get the environment URL
     pub fn environment_url(&self) -> String {
-        self.authenticator.environment_url()
+        match &self.backend {
+            Backend::Live(authenticator) => authenticator.environment_url(),
+            Backend::Snapshot(snapshot) => snapshot.environment.clone(),
+        }
     }
 
     /// Discover available Dataverse environments using the Global Discovery Service
@@ -95,21 +259,22 @@ impl DataverseClient {
         // Scope for the discovery service
         let scope = "https://globaldisco.crm.dynamics.com/.default";
 
-        let token = self.authenticator.get_token_for_scope(scope).await?;
+        let token = self.authenticator()?.get_token_for_scope(scope).await?;
 
         let response = self
-            .http_client
-            .get(disco_url)
-            .header("Authorization", format!("Bearer {}", token))
-            .header("Accept", "application/json")
-            .send()
+            .send_with_retry(|| {
+                self.http_client
+                    .get(disco_url)
+                    .header("Authorization", format!("Bearer {}", token))
+                    .header("Accept", "application/json")
+            })
             .await
             .context("Failed to send request to Global Discovery Service")?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Discovery request failed with status {}: {}", status, body);
+            return Err(DataverseError::from_response(status, &body).into());
         }
 
         let data: crate::models::DiscoveryResponse = response
@@ -128,14 +293,34 @@ impl DataverseClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("FetchXML query failed with status {}: {}", status, body);
+            return Err(DataverseError::from_response(status, &body).into());
         }
 
         let body = response.text().await?;
         let json: serde_json::Value = serde_json::from_str(&body)
             .context("Failed to parse FetchXML response as JSON")?;
-        
-        Ok(crate::models::QueryResult::from_json(&json))
+
+        let mut result = crate::models::QueryResult::from_json(&json);
+        let more = json
+            .get("@Microsoft.Dynamics.CRM.morerecords")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if more {
+            // Without a cookie Dataverse still pages correctly, just more slowly
+            let (page, paging_cookie) = json
+                .get("@Microsoft.Dynamics.CRM.fetchxmlpagingcookie")
+                .and_then(|v| v.as_str())
+                .and_then(crate::fetchxml::parse_paging_cookie)
+                .unwrap_or_else(|| (crate::fetchxml::page_number(fetch_xml) + 1, None));
+            result.fetch_paging = Some(crate::models::FetchXmlPaging {
+                entity_set_name: entity_set_name.to_string(),
+                fetch_xml: fetch_xml.to_string(),
+                page,
+                paging_cookie,
+            });
+        }
+
+        Ok(result)
     }
 
     /// Get count of records where an attribute is not null
@@ -146,7 +331,7 @@ impl DataverseClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("Count query failed with status {}: {}", status, body);
+            return Err(DataverseError::from_response(status, &body).into());
         }
 
         let body = response.text().await?;
@@ -154,3 +339,16 @@ impl DataverseClient {
         Ok(count)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_refreshed_before_expiry() {
+        let now = OffsetDateTime::now_utc();
+        let token = |expires_in: Duration| AccessToken::new("token", now + expires_in);
+        assert!(is_fresh(&token(Duration::from_secs(30 * 60)), now));
+        assert!(!is_fresh(&token(Duration::from_secs(60)), now));
+    }
+}
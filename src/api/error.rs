@@ -0,0 +1,138 @@
+//! Errors returned by the Dataverse Web API
+//!
+//! Failed requests carry an OData error payload:
+//! `{"error": {"code": "0x80040217", "message": "...", "innererror": {...}}}`.
+//! It is parsed into `DataverseError`, whose `Display` is a short message for
+//! the status bar; the full payload is kept for the error details popup.
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// A failed Dataverse request
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{} ({})", self.summary(), self.status)]
+pub struct DataverseError {
+    pub status: StatusCode,
+    /// Dataverse error code, e.g. `0x80040217`
+    pub code: Option<String>,
+    pub message: String,
+    /// Inner exception type, message and stack trace, when the server sends them
+    pub inner: Option<String>,
+}
+
+/// The common errors that get a friendlier message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataverseErrorKind {
+    PrivilegeMissing,
+    RecordNotFound,
+    InvalidProperty,
+    Throttled,
+    Unauthorized,
+    Other,
+}
+
+#[derive(Deserialize)]
+struct ErrorPayload {
+    error: ODataError,
+}
+
+#[derive(Deserialize)]
+struct ODataError {
+    code: Option<String>,
+    message: Option<String>,
+    #[serde(rename = "innererror")]
+    inner_error: Option<InnerError>,
+}
+
+#[derive(Deserialize)]
+struct InnerError {
+    message: Option<String>,
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    #[serde(rename = "stacktrace")]
+    stack_trace: Option<String>,
+}
+
+impl DataverseError {
+    /// Parse an error response body, keeping the raw body if it is not OData
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        match serde_json::from_str::<ErrorPayload>(body) {
+            Ok(payload) => {
+                let inner = payload.error.inner_error.map(|inner| {
+                    [inner.error_type, inner.message, inner.stack_trace]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                });
+                Self {
+                    status,
+                    code: payload.error.code.filter(|c| !c.is_empty()),
+                    message: payload.error.message.unwrap_or_default(),
+                    inner: inner.filter(|i| !i.is_empty()),
+                }
+            }
+            Err(_) => Self {
+                status,
+                code: None,
+                message: body.trim().to_string(),
+                inner: None,
+            },
+        }
+    }
+
+    pub fn kind(&self) -> DataverseErrorKind {
+        match self.code.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            Some("0x80040220") | Some("0x80042f09") | Some("0x8004f507") => DataverseErrorKind::PrivilegeMissing,
+            Some("0x80040217") => DataverseErrorKind::RecordNotFound,
+            Some("0x80060888") | Some("0x80041103") => DataverseErrorKind::InvalidProperty,
+            Some("0x80072321") | Some("0x80072322") | Some("0x80072326") => DataverseErrorKind::Throttled,
+            _ => match self.status {
+                StatusCode::UNAUTHORIZED => DataverseErrorKind::Unauthorized,
+                StatusCode::FORBIDDEN => DataverseErrorKind::PrivilegeMissing,
+                StatusCode::NOT_FOUND => DataverseErrorKind::RecordNotFound,
+                StatusCode::TOO_MANY_REQUESTS => DataverseErrorKind::Throttled,
+                _ => DataverseErrorKind::Other,
+            },
+        }
+    }
+
+    /// Short, readable description of the error
+    pub fn summary(&self) -> String {
+        let first_line = self.message.lines().next().unwrap_or_default();
+        match self.kind() {
+            DataverseErrorKind::PrivilegeMissing => {
+                "Missing privilege: your security roles do not allow this".to_string()
+            }
+            DataverseErrorKind::RecordNotFound => format!("Not found: {}", first_line),
+            DataverseErrorKind::InvalidProperty => format!("Invalid column or property: {}", first_line),
+            DataverseErrorKind::Throttled => "Service protection limit exceeded; try again shortly".to_string(),
+            DataverseErrorKind::Unauthorized => "Not signed in or the access token was rejected".to_string(),
+            DataverseErrorKind::Other if first_line.is_empty() => "Request failed".to_string(),
+            DataverseErrorKind::Other => first_line.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_odata_error_payload() {
+        let body = r#"{"error":{"code":"0x80040217","message":"account With Id = 123 Does Not Exist",
+            "innererror":{"message":"Does Not Exist","type":"System.ServiceModel.FaultException","stacktrace":"at Foo()"}}}"#;
+        let error = DataverseError::from_response(StatusCode::NOT_FOUND, body);
+        assert_eq!(error.code.as_deref(), Some("0x80040217"));
+        assert_eq!(error.kind(), DataverseErrorKind::RecordNotFound);
+        assert_eq!(error.to_string(), "Not found: account With Id = 123 Does Not Exist (404 Not Found)");
+        assert_eq!(
+            error.inner.as_deref(),
+            Some("System.ServiceModel.FaultException\nDoes Not Exist\nat Foo()")
+        );
+
+        let error = DataverseError::from_response(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>");
+        assert_eq!(error.kind(), DataverseErrorKind::Other);
+        assert_eq!(error.message, "<html>Bad gateway</html>");
+    }
+}
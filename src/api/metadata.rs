@@ -1,16 +1,53 @@
 //! Entity and attribute metadata API
 
 use super::DataverseClient;
-use crate::models::{AttributeMetadata, EntityMetadata, RelationshipMetadata, OptionSetMetadata};
+use crate::models::{AttributeMetadata, AttributeOptionSet, EntityMetadata, MetadataChanges, RelationshipMetadata, OptionSetMetadata};
 use crate::models::odata::ODataResponse;
 use anyhow::Result;
+use serde_json::json;
+
+/// Entity properties kept in the metadata cache, matching `get_entities`
+const CACHED_ENTITY_PROPERTIES: [&str; 11] = [
+    "LogicalName", "DisplayName", "SchemaName", "Description", "PrimaryIdAttribute", "PrimaryNameAttribute",
+    "EntitySetName", "IsCustomEntity", "IsManaged", "ObjectTypeCode", "Attributes",
+];
+
+/// Attribute types whose options are read by `get_choice_options`
+const CHOICE_ATTRIBUTE_TYPES: [&str; 4] = [
+    "PicklistAttributeMetadata",
+    "MultiSelectPicklistAttributeMetadata",
+    "StateAttributeMetadata",
+    "StatusAttributeMetadata",
+];
+
+/// Attribute types with a length or value range, and the properties that
+/// hold it, read by `get_attribute_limits`
+const LIMITED_ATTRIBUTE_TYPES: [(&str, &str); 6] = [
+    ("StringAttributeMetadata", "MaxLength"),
+    ("MemoAttributeMetadata", "MaxLength"),
+    ("IntegerAttributeMetadata", "MinValue,MaxValue"),
+    ("DecimalAttributeMetadata", "MinValue,MaxValue"),
+    ("DoubleAttributeMetadata", "MinValue,MaxValue"),
+    ("MoneyAttributeMetadata", "MinValue,MaxValue"),
+];
+
+/// Attribute properties kept in the metadata cache, matching `get_entity_attributes`
+const CACHED_ATTRIBUTE_PROPERTIES: [&str; 14] = [
+    "LogicalName", "DisplayName", "SchemaName", "AttributeType", "AttributeTypeName", "RequiredLevel",
+    "IsCustomAttribute", "IsPrimaryId", "IsPrimaryName", "Description", "MaxLength", "MinValue", "MaxValue",
+    "AttributeOf",
+];
 
 impl DataverseClient {
-    /// Get all global option sets
+    /// Get all global option sets with their options
+    ///
+    /// `Options` only exists on the derived `OptionSetMetadata` type, so it
+    /// cannot be named in `$select` and all properties are returned.
     pub async fn get_global_option_sets(&self) -> Result<Vec<OptionSetMetadata>> {
-        let response: ODataResponse<OptionSetMetadata> = self
-            .get_json("GlobalOptionSetDefinitions?$select=Name,DisplayName,Description,IsGlobal,OptionSetType,MetadataId")
-            .await?;
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.global_option_sets.clone());
+        }
+        let response: ODataResponse<OptionSetMetadata> = self.get_json("GlobalOptionSetDefinitions").await?;
         Ok(response.value)
     }
 
@@ -25,6 +62,9 @@ impl DataverseClient {
 
     /// Get all entity definitions
     pub async fn get_entities(&self) -> Result<Vec<EntityMetadata>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entities.iter().map(|e| e.metadata.clone()).collect());
+        }
         let response: ODataResponse<EntityMetadata> = self
             .get_json("EntityDefinitions?$select=LogicalName,DisplayName,SchemaName,Description,PrimaryIdAttribute,PrimaryNameAttribute,EntitySetName,IsCustomEntity,IsManaged,ObjectTypeCode,MetadataId")
             .await?;
@@ -33,6 +73,9 @@ impl DataverseClient {
 
     /// Get a specific entity by logical name
     pub async fn get_entity(&self, logical_name: &str) -> Result<EntityMetadata> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entity(logical_name)?.metadata.clone());
+        }
         let endpoint = format!(
             "EntityDefinitions(LogicalName='{}')?$select=LogicalName,DisplayName,SchemaName,Description,PrimaryIdAttribute,PrimaryNameAttribute,EntitySetName,IsCustomEntity,IsManaged,ObjectTypeCode,MetadataId",
             logical_name
@@ -42,8 +85,11 @@ impl DataverseClient {
 
     /// Get attributes for an entity
     pub async fn get_entity_attributes(&self, logical_name: &str) -> Result<Vec<AttributeMetadata>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entity(logical_name)?.attributes.clone());
+        }
         let endpoint = format!(
-            "EntityDefinitions(LogicalName='{}')/Attributes?$select=LogicalName,DisplayName,SchemaName,AttributeType,AttributeTypeName,RequiredLevel,IsCustomAttribute,IsPrimaryId,IsPrimaryName,Description,MetadataId",
+            "EntityDefinitions(LogicalName='{}')/Attributes?$select=LogicalName,DisplayName,SchemaName,AttributeType,AttributeTypeName,RequiredLevel,IsCustomAttribute,IsPrimaryId,IsPrimaryName,Description,AttributeOf,MetadataId",
             logical_name
         );
         let response: ODataResponse<AttributeMetadata> = self.get_json(&endpoint).await?;
@@ -52,8 +98,11 @@ impl DataverseClient {
 
     /// Get relationships for an entity (1:N)
     pub async fn get_entity_one_to_many(&self, logical_name: &str) -> Result<Vec<RelationshipMetadata>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entity(logical_name)?.one_to_many.clone());
+        }
         let endpoint = format!(
-            "EntityDefinitions(LogicalName='{}')/OneToManyRelationships?$select=SchemaName,ReferencingEntity,ReferencingAttribute,ReferencedEntity,ReferencedAttribute",
+            "EntityDefinitions(LogicalName='{}')/OneToManyRelationships?$select=SchemaName,ReferencingEntity,ReferencingAttribute,ReferencedEntity,ReferencedAttribute,ReferencingEntityNavigationPropertyName,ReferencedEntityNavigationPropertyName",
             logical_name
         );
         let response: ODataResponse<RelationshipMetadata> = self.get_json(&endpoint).await?;
@@ -62,8 +111,11 @@ impl DataverseClient {
 
     /// Get relationships for an entity (N:1)
     pub async fn get_entity_many_to_one(&self, logical_name: &str) -> Result<Vec<RelationshipMetadata>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entity(logical_name)?.many_to_one.clone());
+        }
         let endpoint = format!(
-            "EntityDefinitions(LogicalName='{}')/ManyToOneRelationships?$select=SchemaName,ReferencingEntity,ReferencingAttribute,ReferencedEntity,ReferencedAttribute",
+            "EntityDefinitions(LogicalName='{}')/ManyToOneRelationships?$select=SchemaName,ReferencingEntity,ReferencingAttribute,ReferencedEntity,ReferencedAttribute,ReferencingEntityNavigationPropertyName,ReferencedEntityNavigationPropertyName",
             logical_name
         );
         let response: ODataResponse<RelationshipMetadata> = self.get_json(&endpoint).await?;
@@ -72,6 +124,9 @@ impl DataverseClient {
 
     /// Get N:N relationships for an entity
     pub async fn get_entity_many_to_many(&self, logical_name: &str) -> Result<Vec<RelationshipMetadata>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.entity(logical_name)?.many_to_many.clone());
+        }
         let endpoint = format!(
             "EntityDefinitions(LogicalName='{}')/ManyToManyRelationships?$select=SchemaName,Entity1LogicalName,Entity2LogicalName,IntersectEntityName",
             logical_name
@@ -79,4 +134,98 @@ impl DataverseClient {
         let response: ODataResponse<RelationshipMetadata> = self.get_json(&endpoint).await?;
         Ok(response.value)
     }
+
+    /// Get entities and their attributes changed since `client_version_stamp`
+    ///
+    /// Without a version stamp every entity and attribute is returned.
+    pub async fn retrieve_metadata_changes(&self, client_version_stamp: Option<&str>) -> Result<MetadataChanges> {
+        let query = json!({
+            "Criteria": { "FilterOperator": "And", "Conditions": [] },
+            "Properties": { "AllProperties": false, "PropertyNames": CACHED_ENTITY_PROPERTIES },
+            "AttributeQuery": {
+                "Criteria": { "FilterOperator": "And", "Conditions": [] },
+                "Properties": { "AllProperties": false, "PropertyNames": CACHED_ATTRIBUTE_PROPERTIES },
+            },
+        });
+        let query = urlencoding::encode(&query.to_string()).into_owned();
+        let endpoint = match client_version_stamp {
+            Some(stamp) => format!(
+                "RetrieveMetadataChanges(Query=@q,ClientVersionStamp=@v,DeletedMetadataFilters=@d)?@q={}&@v='{}'&@d=Microsoft.Dynamics.CRM.DeletedMetadataFilters'Entity,Attribute'",
+                query,
+                urlencoding::encode(stamp)
+            ),
+            None => format!("RetrieveMetadataChanges(Query=@q)?@q={}", query),
+        };
+        self.get_json(&endpoint).await
+    }
+
+    /// Get the options of every choice, status and state column of several
+    /// entities in one `$batch` call, in the order of `logical_names`
+    pub async fn get_choice_options(&self, logical_names: &[String]) -> Result<Vec<Vec<AttributeOptionSet>>> {
+        if let Some(snapshot) = self.snapshot() {
+            return logical_names
+                .iter()
+                .map(|name| Ok(snapshot.entity(name)?.option_sets.clone()))
+                .collect();
+        }
+
+        let mut batch = self.batch();
+        for name in logical_names {
+            for cast in CHOICE_ATTRIBUTE_TYPES {
+                batch = batch.get(format!(
+                    "EntityDefinitions(LogicalName='{}')/Attributes/Microsoft.Dynamics.CRM.{}?$select=LogicalName&$expand=OptionSet($select=Name,DisplayName,Description,IsGlobal,OptionSetType,MetadataId,Options)",
+                    name, cast
+                ));
+            }
+        }
+        let responses = batch.send().await?;
+
+        responses
+            .chunks(CHOICE_ATTRIBUTE_TYPES.len())
+            .map(|chunk| {
+                let mut option_sets = Vec::new();
+                for response in chunk {
+                    option_sets.extend(response.json::<ODataResponse<AttributeOptionSet>>()?.value);
+                }
+                Ok(option_sets)
+            })
+            .collect()
+    }
+
+    /// Get the maximum length or value range of the columns of several
+    /// entities in one `$batch` call, in the order of `logical_names`
+    ///
+    /// These properties only exist on derived attribute types, so
+    /// `get_entity_attributes` cannot select them. Only the metadata id,
+    /// logical name and limits of the returned attributes are set.
+    pub async fn get_attribute_limits(&self, logical_names: &[String]) -> Result<Vec<Vec<AttributeMetadata>>> {
+        if let Some(snapshot) = self.snapshot() {
+            return logical_names
+                .iter()
+                .map(|name| Ok(snapshot.entity(name)?.attributes.clone()))
+                .collect();
+        }
+
+        let mut batch = self.batch();
+        for name in logical_names {
+            for (cast, properties) in LIMITED_ATTRIBUTE_TYPES {
+                batch = batch.get(format!(
+                    "EntityDefinitions(LogicalName='{}')/Attributes/Microsoft.Dynamics.CRM.{}?$select=MetadataId,LogicalName,{}",
+                    name, cast, properties
+                ));
+            }
+        }
+        let responses = batch.send().await?;
+
+        responses
+            .chunks(LIMITED_ATTRIBUTE_TYPES.len())
+            .map(|chunk| {
+                let mut attributes = Vec::new();
+                for response in chunk {
+                    attributes.extend(response.json::<ODataResponse<AttributeMetadata>>()?.value);
+                }
+                Ok(attributes)
+            })
+            .collect()
+    }
 }
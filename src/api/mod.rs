@@ -1,8 +1,14 @@
+pub mod batch;
 pub mod client;
 pub mod users;
 pub mod data;
+pub mod error;
 pub mod metadata;
+pub mod retry;
 pub mod solutions;
 pub mod system_jobs;
+pub mod views;
 
-pub use client::DataverseClient;
+pub use client::{DataverseClient, MAX_CONCURRENT_REQUESTS};
+pub use error::{DataverseError, DataverseErrorKind};
+pub use retry::RetryPolicy;
@@ -0,0 +1,97 @@
+//! Retry policy for transient failures and service protection limits
+//!
+//! Dataverse answers 429 with a `Retry-After` header when a user exceeds the
+//! service protection limits, and the gateway occasionally returns 502, 503 or
+//! 504. These are retried with exponential backoff, waiting at least as long
+//! as the server asks.
+
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often and how long to wait before retrying a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for every later one
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the backoff when the server gives no `Retry-After`
+    pub max_backoff_ms: u64,
+    /// Upper bound for a `Retry-After` given by the server
+    pub max_retry_after_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60_000,
+            max_retry_after_ms: 300_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a response with this status should be retried
+    pub fn is_retryable(status: StatusCode) -> bool {
+        matches!(
+            status,
+            StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+        )
+    }
+
+    /// Wait before retry number `attempt` (zero-based)
+    ///
+    /// A `Retry-After` from the server wins over `max_backoff_ms`, since
+    /// retrying earlier only extends the throttling, but is capped at
+    /// `max_retry_after_ms` so a bogus header cannot stall the client.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(Duration::from_millis(self.max_retry_after_ms));
+        }
+        let backoff = self.initial_backoff_ms.saturating_mul(1u64 << attempt.min(20));
+        Duration::from_millis(backoff.min(self.max_backoff_ms))
+    }
+}
+
+/// The `Retry-After` header of a response, in seconds
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_honours_retry_after() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 3000,
+            max_retry_after_ms: 600_000,
+        };
+        assert_eq!(policy.delay(0, None), Duration::from_millis(500));
+        assert_eq!(policy.delay(2, None), Duration::from_millis(2000));
+        assert_eq!(policy.delay(4, None), Duration::from_millis(3000));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(120))), Duration::from_secs(120));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(86_400))), Duration::from_secs(600));
+        assert!(RetryPolicy::is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!RetryPolicy::is_retryable(StatusCode::NOT_FOUND));
+    }
+}
@@ -17,14 +17,20 @@ impl DataverseClient {
     }
     /// Get all solutions
     pub async fn get_solutions(&self) -> Result<Vec<Solution>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.solutions.iter().map(|s| s.solution.clone()).collect());
+        }
         let response: ODataResponse<Solution> = self
-            .get_json("solutions?$select=solutionid,uniquename,friendlyname,version,ismanaged,publisherid,description,installedon&$orderby=friendlyname")
+            .get_json("solutions?$select=solutionid,uniquename,friendlyname,version,ismanaged,publisherid,description,installedon,isvisible&$orderby=friendlyname")
             .await?;
         Ok(response.value)
     }
 
     /// Get components in a solution
     pub async fn get_solution_components(&self, solution_id: &str) -> Result<Vec<SolutionComponent>> {
+        if let Some(snapshot) = self.snapshot() {
+            return Ok(snapshot.solution_components(solution_id));
+        }
         let endpoint = format!(
             "solutioncomponents?$filter=_solutionid_value eq {}&$select=componenttype,objectid,solutioncomponentid,rootcomponentbehavior",
             solution_id
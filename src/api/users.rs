@@ -44,11 +44,29 @@ impl DataverseClient {
 
     /// Get security roles assigned to a team
     pub async fn get_team_roles(&self, team_id: &str) -> Result<Vec<SecurityRole>> {
-        let endpoint = format!(
-            "teams({})/teamroles_association?$select=roleid,name,ismanaged&$expand=businessunitid($select=businessunitid,name)",
-            team_id
-        );
-        let response: ODataResponse<SecurityRole> = self.get_json(&endpoint).await?;
+        let response: ODataResponse<SecurityRole> = self.get_json(&team_roles_endpoint(team_id)).await?;
         Ok(response.value)
     }
+
+    /// Get the security roles of several teams in one `$batch` call
+    ///
+    /// Roles are returned in the order of `team_ids`; a team whose roles
+    /// could not be read gets its error instead.
+    pub async fn get_roles_of_teams(&self, team_ids: &[String]) -> Result<Vec<Result<Vec<SecurityRole>>>> {
+        let batch = team_ids
+            .iter()
+            .fold(self.batch(), |batch, team_id| batch.get(team_roles_endpoint(team_id)));
+        let responses = batch.send().await?;
+        Ok(responses
+            .iter()
+            .map(|response| Ok(response.json::<ODataResponse<SecurityRole>>()?.value))
+            .collect())
+    }
+}
+
+fn team_roles_endpoint(team_id: &str) -> String {
+    format!(
+        "teams({})/teamroles_association?$select=roleid,name,ismanaged&$expand=businessunitid($select=businessunitid,name)",
+        team_id
+    )
 }
@@ -0,0 +1,36 @@
+//! System and personal view API endpoints
+
+use super::DataverseClient;
+use crate::models::EntityView;
+use crate::models::odata::ODataResponse;
+use anyhow::Result;
+
+/// View types that can run as-is: public, advanced find and associated views.
+/// Quick find and lookup views carry `{0}` search placeholders in their FetchXML.
+const VIEW_QUERY_TYPES: &str = "querytype eq 0 or querytype eq 1 or querytype eq 2";
+
+impl DataverseClient {
+    /// Get the active system views (savedquery) for an entity
+    pub async fn get_system_views(&self, logical_name: &str) -> Result<Vec<EntityView>> {
+        let endpoint = format!(
+            "savedqueries?$select=name,fetchxml,layoutxml,querytype,isdefault&$filter=returnedtypecode eq '{}' and statecode eq 0 and ({})&$orderby=name",
+            logical_name, VIEW_QUERY_TYPES
+        );
+        let response: ODataResponse<EntityView> = self.get_json(&endpoint).await?;
+        Ok(response.value)
+    }
+
+    /// Get the personal views (userquery) for an entity that the caller can read
+    pub async fn get_personal_views(&self, logical_name: &str) -> Result<Vec<EntityView>> {
+        let endpoint = format!(
+            "userqueries?$select=name,fetchxml,layoutxml,querytype&$filter=returnedtypecode eq '{}' and statecode eq 0&$orderby=name",
+            logical_name
+        );
+        let response: ODataResponse<EntityView> = self.get_json(&endpoint).await?;
+        Ok(response
+            .value
+            .into_iter()
+            .map(|view| EntityView { personal: true, ..view })
+            .collect())
+    }
+}
@@ -1,13 +1,16 @@
-//! Azure CLI credential provider for Dataverse authentication
+//! Azure credential provider for Dataverse authentication
 
+use super::credentials::{AuthMethod, AuthSettings};
 use anyhow::{Context, Result};
-use azure_core::credentials::TokenCredential;
-use azure_identity::AzureCliCredential;
+use azure_core::credentials::{AccessToken, TokenCredential};
 use std::sync::{Arc, RwLock};
 
-/// Authenticator that uses Azure CLI credentials to access Dataverse
+/// Authenticator that uses Azure credentials to access Dataverse
+///
+/// Uses the Azure CLI by default; other methods are chosen with `AuthSettings`.
 pub struct AzureAuthenticator {
-    credential: Arc<AzureCliCredential>,
+    credential: Arc<dyn TokenCredential>,
+    method: AuthMethod,
     environment_url: RwLock<String>,
 }
 
@@ -16,38 +19,54 @@ impl AzureAuthenticator {
     ///
     /// # Arguments
     /// * `environment_url` - The Dataverse environment URL (e.g., "https://org.crm.dynamics.com")
-    pub async fn new(environment_url: impl Into<String>) -> Result<Self> {
+    /// * `settings` - The authentication method and its parameters
+    pub fn with_settings(environment_url: impl Into<String>, settings: &AuthSettings) -> Result<Self> {
         let environment_url = environment_url.into();
         let environment_url = environment_url.trim_end_matches('/').to_string();
 
-        let credential = AzureCliCredential::new()
-            .context("Failed to create Azure CLI credential")?;
-
         Ok(Self {
-            credential,
+            credential: settings.credential()?,
+            method: settings.method,
             environment_url: RwLock::new(environment_url),
         })
     }
 
     /// Get an access token for the Dataverse API
     pub async fn get_token(&self) -> Result<String> {
-        let env_url = self.environment_url.read().unwrap();
-        let scope = format!("{}/.default", *env_url);
-        self.get_token_for_scope(&scope).await
+        let token = self.get_access_token().await?;
+        Ok(token.token.secret().to_string())
+    }
+
+    /// Get an access token for the Dataverse API together with its expiry
+    pub async fn get_access_token(&self) -> Result<AccessToken> {
+        let scope = format!("{}/.default", self.environment_url());
+        self.get_access_token_for_scope(&scope).await
     }
 
     /// Get an access token for a specific scope
     pub async fn get_token_for_scope(&self, scope: &str) -> Result<String> {
-        let token = self
-            .credential
+        let token = self.get_access_token_for_scope(scope).await?;
+        Ok(token.token.secret().to_string())
+    }
+
+    async fn get_access_token_for_scope(&self, scope: &str) -> Result<AccessToken> {
+        let hint = match self.method {
+            AuthMethod::AzureCli => " from Azure CLI. Make sure you're logged in with 'az login'",
+            _ => "",
+        };
+        self.credential
             .get_token(&[scope])
             .await
-            .context(format!(
-                "Failed to get token for scope '{}' from Azure CLI. Make sure you're logged in with 'az login'",
-                scope
-            ))?;
+            .context(format!("Failed to get token for scope '{}'{}", scope, hint))
+    }
 
-        Ok(token.token.secret().to_string())
+    /// Forget tokens the credential has cached, so the next request asks
+    /// for a new one
+    pub async fn clear_cache(&self) -> Result<()> {
+        self.credential
+            .clear_cache()
+            .await
+            .context("Failed to clear cached credentials")
     }
 
     /// Get the environment URL
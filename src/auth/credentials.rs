@@ -0,0 +1,368 @@
+//! Credentials for the supported authentication methods
+//!
+//! Every method is exposed as an `azure_core` `TokenCredential`, so
+//! `AzureAuthenticator` does not need to know which one is in use. Secrets are
+//! never written to the config file; they come from the command line or the
+//! standard `AZURE_*` environment variables.
+
+use anyhow::{Context, Result};
+use azure_core::credentials::{AccessToken, TokenCredential};
+use azure_core::date::OffsetDateTime;
+use azure_core::error::{Error, ErrorKind};
+use azure_identity::{AzureCliCredential, ClientCertificateCredential, TokenCredentialOptions};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+
+const AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// Receiver of device code sign-in instructions while the terminal UI owns
+/// the terminal; without one they are printed to stderr
+///
+/// `Some` carries the instructions when a sign-in starts and `None` follows
+/// once it has finished, failed or been cancelled.
+static DEVICE_CODE_PROMPTS: std::sync::Mutex<Option<UnboundedSender<Option<String>>>> = std::sync::Mutex::new(None);
+
+/// Send device code sign-in instructions to `prompts` instead of stderr, or
+/// back to stderr with `None`
+pub fn set_device_code_prompts(prompts: Option<UnboundedSender<Option<String>>>) {
+    *DEVICE_CODE_PROMPTS.lock().unwrap() = prompts;
+}
+
+/// Withdraws the sign-in instructions when the sign-in ends, including when
+/// its task is aborted
+struct PromptGuard(Option<UnboundedSender<Option<String>>>);
+
+impl Drop for PromptGuard {
+    fn drop(&mut self) {
+        if let Some(prompts) = &self.0 {
+            let _ = prompts.send(None);
+        }
+    }
+}
+
+/// Public client used for device code sign-in when no client ID is configured
+///
+/// This is the application Microsoft registers for Dataverse samples and tools.
+const DEFAULT_PUBLIC_CLIENT_ID: &str = "51f81489-12ee-4a9e-aaae-a2591f45987d";
+
+/// How Rynamo signs in to Dataverse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    /// Use the signed-in Azure CLI account
+    #[default]
+    AzureCli,
+    /// Service principal with a client secret
+    ClientSecret,
+    /// Service principal with a PKCS#12 (.pfx) certificate
+    Certificate,
+    /// Interactive sign-in with a code entered in a browser
+    DeviceCode,
+    /// Service principal read from `AZURE_TENANT_ID`, `AZURE_CLIENT_ID` and
+    /// `AZURE_CLIENT_SECRET` or `AZURE_CLIENT_CERTIFICATE_PATH`
+    Environment,
+}
+
+/// Authentication settings for one environment
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    pub method: AuthMethod,
+    pub tenant_id: Option<String>,
+    pub client_id: Option<String>,
+    pub certificate_path: Option<PathBuf>,
+    /// Client secret for this session only; falls back to `AZURE_CLIENT_SECRET`
+    #[serde(skip)]
+    pub client_secret: Option<String>,
+    /// Certificate password for this session only; falls back to
+    /// `AZURE_CLIENT_CERTIFICATE_PASSWORD`
+    #[serde(skip)]
+    pub certificate_password: Option<String>,
+}
+
+impl AuthSettings {
+    /// Build the credential for these settings
+    pub fn credential(&self) -> Result<Arc<dyn TokenCredential>> {
+        match self.method {
+            AuthMethod::AzureCli => {
+                let credential = AzureCliCredential::new().context("Failed to create Azure CLI credential")?;
+                Ok(credential)
+            }
+            AuthMethod::ClientSecret => {
+                let secret = match &self.client_secret {
+                    Some(secret) => secret.clone(),
+                    None => env_var("AZURE_CLIENT_SECRET")?,
+                };
+                Ok(Arc::new(ClientSecretCredential {
+                    tenant_id: required(&self.tenant_id, "--tenant-id")?,
+                    client_id: required(&self.client_id, "--client-id")?,
+                    client_secret: secret,
+                    http_client: reqwest::Client::new(),
+                }))
+            }
+            AuthMethod::Certificate => {
+                let path = self
+                    .certificate_path
+                    .clone()
+                    .context("Certificate authentication requires --certificate")?;
+                let password = match &self.certificate_password {
+                    Some(password) => password.clone(),
+                    None => std::env::var("AZURE_CLIENT_CERTIFICATE_PASSWORD").unwrap_or_default(),
+                };
+                certificate_credential(
+                    required(&self.tenant_id, "--tenant-id")?,
+                    required(&self.client_id, "--client-id")?,
+                    path,
+                    password,
+                )
+            }
+            AuthMethod::DeviceCode => Ok(Arc::new(DeviceCodeCredential {
+                tenant_id: self.tenant_id.clone().unwrap_or_else(|| "organizations".to_string()),
+                client_id: self.client_id.clone().unwrap_or_else(|| DEFAULT_PUBLIC_CLIENT_ID.to_string()),
+                http_client: reqwest::Client::new(),
+                refresh_token: Mutex::new(None),
+            })),
+            AuthMethod::Environment => {
+                let tenant_id = env_var("AZURE_TENANT_ID")?;
+                let client_id = env_var("AZURE_CLIENT_ID")?;
+                if let Ok(client_secret) = std::env::var("AZURE_CLIENT_SECRET") {
+                    return Ok(Arc::new(ClientSecretCredential {
+                        tenant_id,
+                        client_id,
+                        client_secret,
+                        http_client: reqwest::Client::new(),
+                    }));
+                }
+                let path = env_var("AZURE_CLIENT_CERTIFICATE_PATH")
+                    .context("Set AZURE_CLIENT_SECRET or AZURE_CLIENT_CERTIFICATE_PATH")?;
+                let password = std::env::var("AZURE_CLIENT_CERTIFICATE_PASSWORD").unwrap_or_default();
+                certificate_credential(tenant_id, client_id, path.into(), password)
+            }
+        }
+    }
+}
+
+fn required(value: &Option<String>, flag: &str) -> Result<String> {
+    value
+        .clone()
+        .with_context(|| format!("This authentication method requires {}", flag))
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("Environment variable {} is not set", name))
+}
+
+fn certificate_credential(
+    tenant_id: String,
+    client_id: String,
+    path: PathBuf,
+    password: String,
+) -> Result<Arc<dyn TokenCredential>> {
+    let der = std::fs::read(&path).with_context(|| format!("Failed to read certificate {}", path.display()))?;
+    let credential = ClientCertificateCredential::new(
+        tenant_id,
+        client_id,
+        azure_core::base64::encode(der),
+        password,
+        TokenCredentialOptions::default(),
+    )
+    .context("Failed to create certificate credential")?;
+    Ok(credential)
+}
+
+/// Token endpoint response, successful or not
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+impl TokenResponse {
+    fn into_access_token(self) -> azure_core::Result<AccessToken> {
+        match self.access_token {
+            Some(token) => {
+                let expires_in = Duration::from_secs(self.expires_in.unwrap_or(3600));
+                Ok(AccessToken::new(token, OffsetDateTime::now_utc() + expires_in))
+            }
+            None => Err(Error::message(
+                ErrorKind::Credential,
+                self.error_description
+                    .or(self.error)
+                    .unwrap_or_else(|| "Token response did not contain an access token".to_string()),
+            )),
+        }
+    }
+}
+
+async fn post_token_request(
+    http_client: &reqwest::Client,
+    url: &str,
+    form: &[(&str, &str)],
+) -> azure_core::Result<TokenResponse> {
+    let response = http_client
+        .post(url)
+        .form(form)
+        .send()
+        .await
+        .map_err(|e| Error::new(ErrorKind::Credential, e))?;
+    response.json().await.map_err(|e| Error::new(ErrorKind::Credential, e))
+}
+
+/// Service principal authenticated with a client secret
+///
+/// `azure_identity` dropped its own `ClientSecretCredential` before 0.22, so
+/// the client credentials grant is requested here directly.
+struct ClientSecretCredential {
+    tenant_id: String,
+    client_id: String,
+    client_secret: String,
+    http_client: reqwest::Client,
+}
+
+impl std::fmt::Debug for ClientSecretCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientSecretCredential")
+            .field("tenant_id", &self.tenant_id)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenCredential for ClientSecretCredential {
+    async fn get_token(&self, scopes: &[&str]) -> azure_core::Result<AccessToken> {
+        let url = format!("{}/{}/oauth2/v2.0/token", AUTHORITY_HOST, self.tenant_id);
+        let scope = scopes.join(" ");
+        let form = [
+            ("grant_type", "client_credentials"),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+            ("scope", scope.as_str()),
+        ];
+        post_token_request(&self.http_client, &url, &form).await?.into_access_token()
+    }
+
+    async fn clear_cache(&self) -> azure_core::Result<()> {
+        Ok(())
+    }
+}
+
+/// Device code response from the authorization endpoint
+#[derive(Debug, Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    message: String,
+    interval: u64,
+    expires_in: u64,
+}
+
+/// Interactive sign-in using the OAuth device code flow
+///
+/// The sign-in instructions are printed to stderr, or shown by the terminal
+/// UI while it runs (see `set_device_code_prompts`). The refresh token is kept
+/// so that later tokens, including for other scopes, are issued silently.
+struct DeviceCodeCredential {
+    tenant_id: String,
+    client_id: String,
+    http_client: reqwest::Client,
+    refresh_token: Mutex<Option<String>>,
+}
+
+impl std::fmt::Debug for DeviceCodeCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceCodeCredential")
+            .field("tenant_id", &self.tenant_id)
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DeviceCodeCredential {
+    fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}/oauth2/v2.0/{}", AUTHORITY_HOST, self.tenant_id, path)
+    }
+
+    async fn sign_in(&self, scope: &str) -> azure_core::Result<TokenResponse> {
+        let device: DeviceCodeResponse = self
+            .http_client
+            .post(self.endpoint("devicecode"))
+            .form(&[("client_id", self.client_id.as_str()), ("scope", scope)])
+            .send()
+            .await
+            .map_err(|e| Error::new(ErrorKind::Credential, e))?
+            .json()
+            .await
+            .map_err(|e| Error::new(ErrorKind::Credential, e))?;
+        let prompts = DEVICE_CODE_PROMPTS.lock().unwrap().clone();
+        match &prompts {
+            Some(prompts) => prompts
+                .send(Some(device.message))
+                .map_err(|_| Error::message(ErrorKind::Credential, "Device code sign-in cannot be shown"))?,
+            None => eprintln!("{}", device.message),
+        }
+        let _prompt = PromptGuard(prompts);
+
+        let url = self.endpoint("token");
+        let deadline = std::time::Instant::now() + Duration::from_secs(device.expires_in);
+        let mut interval = Duration::from_secs(device.interval.max(1));
+        while std::time::Instant::now() < deadline {
+            tokio::time::sleep(interval).await;
+            let form = [
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("client_id", self.client_id.as_str()),
+                ("device_code", device.device_code.as_str()),
+            ];
+            let response = post_token_request(&self.http_client, &url, &form).await?;
+            match response.error.as_deref() {
+                Some("authorization_pending") => {}
+                Some("slow_down") => interval += Duration::from_secs(5),
+                _ => return Ok(response),
+            }
+        }
+        Err(Error::message(ErrorKind::Credential, "Device code sign-in timed out"))
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenCredential for DeviceCodeCredential {
+    async fn get_token(&self, scopes: &[&str]) -> azure_core::Result<AccessToken> {
+        // offline_access asks for a refresh token alongside the access token
+        let scope = format!("{} offline_access", scopes.join(" "));
+        let mut refresh_token = self.refresh_token.lock().await;
+
+        let mut response = None;
+        if let Some(token) = refresh_token.as_deref() {
+            let form = [
+                ("grant_type", "refresh_token"),
+                ("client_id", self.client_id.as_str()),
+                ("refresh_token", token),
+                ("scope", scope.as_str()),
+            ];
+            let refreshed = post_token_request(&self.http_client, &self.endpoint("token"), &form).await?;
+            if refreshed.access_token.is_some() {
+                response = Some(refreshed);
+            }
+        }
+        let response = match response {
+            Some(response) => response,
+            None => self.sign_in(&scope).await?,
+        };
+
+        if response.refresh_token.is_some() {
+            refresh_token.clone_from(&response.refresh_token);
+        }
+        response.into_access_token()
+    }
+
+    async fn clear_cache(&self) -> azure_core::Result<()> {
+        *self.refresh_token.lock().await = None;
+        Ok(())
+    }
+}
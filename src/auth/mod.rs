@@ -1,7 +1,10 @@
-//! Auth module for Azure CLI authentication
+//! Auth module for Azure authentication
 //!
-//! Provides token acquisition using Azure CLI credentials for Dataverse API access.
+//! Provides token acquisition for Dataverse API access using Azure CLI,
+//! service principal, certificate, device code or environment credentials.
 
 mod azure_cli;
+mod credentials;
 
 pub use azure_cli::AzureAuthenticator;
+pub use credentials::{AuthMethod, AuthSettings, set_device_code_prompts};
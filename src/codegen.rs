@@ -0,0 +1,511 @@
+//! Typed models generated from entity metadata
+//!
+//! Each entity becomes a Rust struct, TypeScript interface or C# class with a
+//! property per column, named as in Web API responses. Lookups are read from
+//! their `_<column>_value` property and list the navigation properties used to
+//! bind or expand them. Choice columns get an enum of their options.
+
+use crate::models::{AttributeMetadata, AttributeOptionSet, OptionSetValue};
+use crate::snapshot::EntitySnapshot;
+use std::collections::BTreeMap;
+
+/// Language of generated models
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Language {
+    Rust,
+    #[value(name = "typescript")]
+    TypeScript,
+    #[value(name = "csharp")]
+    CSharp,
+}
+
+/// Value type of a column in Web API responses
+#[derive(Debug, Clone, PartialEq)]
+enum FieldType {
+    Text,
+    Integer,
+    BigInt,
+    Decimal,
+    Double,
+    Boolean,
+    DateTime,
+    Guid,
+    /// Id of the referenced record
+    Lookup,
+    /// Value of the named enum
+    Choice(String),
+    /// Comma-separated values of the named enum
+    Choices(String),
+}
+
+impl FieldType {
+    fn rust(&self) -> &str {
+        match self {
+            FieldType::Text | FieldType::DateTime | FieldType::Guid | FieldType::Lookup | FieldType::Choices(_) => "String",
+            FieldType::Integer => "i32",
+            FieldType::BigInt => "i64",
+            FieldType::Decimal | FieldType::Double => "f64",
+            FieldType::Boolean => "bool",
+            FieldType::Choice(name) => name,
+        }
+    }
+
+    fn typescript(&self) -> &str {
+        match self {
+            FieldType::Text | FieldType::DateTime | FieldType::Guid | FieldType::Lookup | FieldType::Choices(_) => "string",
+            FieldType::Integer | FieldType::BigInt | FieldType::Decimal | FieldType::Double => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::Choice(name) => name,
+        }
+    }
+
+    fn csharp(&self) -> &str {
+        match self {
+            FieldType::Text | FieldType::Choices(_) => "string",
+            FieldType::Integer => "int",
+            FieldType::BigInt => "long",
+            FieldType::Decimal => "decimal",
+            FieldType::Double => "double",
+            FieldType::Boolean => "bool",
+            FieldType::DateTime => "DateTimeOffset",
+            FieldType::Guid | FieldType::Lookup => "Guid",
+            FieldType::Choice(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    logical_name: String,
+    /// Name in Web API responses
+    property: String,
+    /// PascalCase name, from the schema name
+    name: String,
+    description: String,
+    field_type: FieldType,
+}
+
+/// Navigation property of a lookup
+#[derive(Debug, Clone)]
+struct Navigation {
+    property: String,
+    /// PascalCase name, from the lookup column
+    name: String,
+    attribute: String,
+    target: String,
+}
+
+#[derive(Debug, Clone)]
+struct Model {
+    logical_name: String,
+    entity_set_name: Option<String>,
+    name: String,
+    description: String,
+    fields: Vec<Field>,
+    navigation: Vec<Navigation>,
+}
+
+#[derive(Debug, Clone)]
+struct ChoiceEnum {
+    description: String,
+    /// Variant names and values
+    options: Vec<(String, i32)>,
+}
+
+/// Models and choice enums of a set of entities
+#[derive(Debug, Clone)]
+pub struct Codegen {
+    environment: String,
+    models: Vec<Model>,
+    enums: BTreeMap<String, ChoiceEnum>,
+}
+
+impl Codegen {
+    pub fn new(environment: &str, entities: &[EntitySnapshot]) -> Self {
+        let mut enums = BTreeMap::new();
+        let models = entities.iter().map(|entity| model(entity, &mut enums)).collect();
+        Self { environment: environment.to_string(), models, enums }
+    }
+
+    /// Render models for one language; `namespace` is used for C#
+    pub fn render(&self, language: Language, namespace: &str) -> String {
+        match language {
+            Language::Rust => self.rust(),
+            Language::TypeScript => self.typescript(),
+            Language::CSharp => self.csharp(namespace),
+        }
+    }
+
+    fn header(&self) -> String {
+        format!("Generated by `rynamo codegen` from {}; edits are lost when it is run again.", self.environment)
+    }
+
+    fn rust(&self) -> String {
+        let mut out = format!("//! {}\n\nuse serde::{{Deserialize, Serialize}};\n", self.header());
+        if !self.enums.is_empty() {
+            out.push_str("use serde_repr::{Deserialize_repr, Serialize_repr};\n");
+        }
+        for model in &self.models {
+            out.push_str(&format!("\n/// {}\n", model.description));
+            out.push_str("#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]\n");
+            out.push_str(&format!("pub struct {} {{\n", model.name));
+            for field in &model.fields {
+                let name = rust_field(&field.logical_name);
+                out.push_str(&format!("    /// {}\n", field.description));
+                if name.trim_start_matches("r#") == field.property {
+                    out.push_str("    #[serde(skip_serializing_if = \"Option::is_none\")]\n");
+                } else {
+                    out.push_str(&format!(
+                        "    #[serde(rename = \"{}\", skip_serializing_if = \"Option::is_none\")]\n",
+                        field.property
+                    ));
+                }
+                out.push_str(&format!("    pub {}: Option<{}>,\n", name, field.field_type.rust()));
+            }
+            out.push_str("}\n");
+
+            out.push_str(&format!("\nimpl {} {{\n", model.name));
+            out.push_str(&format!("    pub const LOGICAL_NAME: &'static str = \"{}\";\n", model.logical_name));
+            if let Some(entity_set_name) = &model.entity_set_name {
+                out.push_str(&format!("    pub const ENTITY_SET_NAME: &'static str = \"{}\";\n", entity_set_name));
+            }
+            for nav in &model.navigation {
+                out.push_str(&format!("    /// Navigation property of `{}` to `{}`\n", nav.attribute, nav.target));
+                out.push_str(&format!(
+                    "    pub const NAV_{}: &'static str = \"{}\";\n",
+                    nav.property.to_uppercase(),
+                    nav.property
+                ));
+            }
+            out.push_str("}\n");
+        }
+        for (name, choice) in &self.enums {
+            out.push_str(&format!("\n/// {}\n", choice.description));
+            out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]\n#[repr(i32)]\n");
+            out.push_str(&format!("pub enum {} {{\n", name));
+            for (variant, value) in &choice.options {
+                out.push_str(&format!("    {} = {},\n", variant, value));
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    fn typescript(&self) -> String {
+        let mut out = format!("// {}\n", self.header());
+        for model in &self.models {
+            out.push_str(&format!("\n/** {} */\nexport interface {} {{\n", ts_comment(&model.description), model.name));
+            for field in &model.fields {
+                out.push_str(&format!("  /** {} */\n", ts_comment(&field.description)));
+                out.push_str(&format!("  {}?: {} | null;\n", field.property, field.field_type.typescript()));
+            }
+            out.push_str("}\n");
+
+            out.push_str(&format!(
+                "\nexport const {}Metadata = {{\n  logicalName: \"{}\",\n",
+                model.name, model.logical_name
+            ));
+            if let Some(entity_set_name) = &model.entity_set_name {
+                out.push_str(&format!("  entitySetName: \"{}\",\n", entity_set_name));
+            }
+            out.push_str("  navigation: {\n");
+            for nav in &model.navigation {
+                out.push_str(&format!("    {}: \"{}\",\n", nav.property, nav.property));
+            }
+            out.push_str("  },\n} as const;\n");
+        }
+        for (name, choice) in &self.enums {
+            out.push_str(&format!("\n/** {} */\nexport enum {} {{\n", ts_comment(&choice.description), name));
+            for (variant, value) in &choice.options {
+                out.push_str(&format!("  {} = {},\n", variant, value));
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    fn csharp(&self, namespace: &str) -> String {
+        let mut out = format!(
+            "// <auto-generated>\n// {}\n// </auto-generated>\n\nusing System;\nusing System.Text.Json.Serialization;\n\nnamespace {};\n",
+            self.header(),
+            namespace
+        );
+        for model in &self.models {
+            out.push_str(&format!("\n/// <summary>{}</summary>\n", xml_comment(&model.description)));
+            out.push_str(&format!("public class {}\n{{\n", model.name));
+            out.push_str(&format!("    public const string EntityLogicalName = \"{}\";\n", model.logical_name));
+            if let Some(entity_set_name) = &model.entity_set_name {
+                out.push_str(&format!("    public const string EntitySetName = \"{}\";\n", entity_set_name));
+            }
+            for field in &model.fields {
+                // A member cannot share the name of its class or of the members generated here
+                let name = if [model.name.as_str(), "EntityLogicalName", "EntitySetName", "Navigation"].contains(&field.name.as_str()) {
+                    format!("{}Value", field.name)
+                } else {
+                    field.name.clone()
+                };
+                out.push_str(&format!("\n    /// <summary>{}</summary>\n", xml_comment(&field.description)));
+                out.push_str(&format!("    [JsonPropertyName(\"{}\")]\n", field.property));
+                out.push_str(&format!("    public {}? {} {{ get; set; }}\n", field.field_type.csharp(), name));
+            }
+            if !model.navigation.is_empty() {
+                out.push_str("\n    /// <summary>Navigation properties of the lookups</summary>\n");
+                out.push_str("    public static class Navigation\n    {\n");
+                for nav in &model.navigation {
+                    out.push_str(&format!(
+                        "        /// <summary>Navigation property of <c>{}</c> to <c>{}</c></summary>\n",
+                        nav.attribute, nav.target
+                    ));
+                    out.push_str(&format!("        public const string {} = \"{}\";\n", nav.name, nav.property));
+                }
+                out.push_str("    }\n");
+            }
+            out.push_str("}\n");
+        }
+        for (name, choice) in &self.enums {
+            out.push_str(&format!("\n/// <summary>{}</summary>\npublic enum {}\n{{\n", xml_comment(&choice.description), name));
+            for (variant, value) in &choice.options {
+                out.push_str(&format!("    {} = {},\n", variant, value));
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+}
+
+fn model(entity: &EntitySnapshot, enums: &mut BTreeMap<String, ChoiceEnum>) -> Model {
+    let metadata = &entity.metadata;
+    let name = pascal_case(metadata.schema_name.as_deref().unwrap_or(&metadata.logical_name));
+
+    let mut navigation: Vec<Navigation> = entity
+        .many_to_one
+        .iter()
+        .filter(|r| r.referencing_entity.as_deref() == Some(metadata.logical_name.as_str()))
+        .filter_map(|r| {
+            let property = r.referencing_navigation_property.clone()?;
+            let attribute = r.referencing_attribute.clone()?;
+            let column = entity.attributes.iter().find(|a| a.logical_name == attribute);
+            let column_name = column.map(attribute_name).unwrap_or_else(|| pascal_case(&attribute));
+            // Polymorphic lookups have one navigation property per target,
+            // such as `parentcustomerid_account`
+            let name = match property.to_lowercase().strip_prefix(&attribute) {
+                Some(suffix) => format!("{}{}", column_name, pascal_case(suffix)),
+                None => pascal_case(&property),
+            };
+            Some(Navigation { property, name, attribute, target: r.referenced_entity.clone().unwrap_or_default() })
+        })
+        .collect();
+    navigation.sort_by(|a, b| a.property.cmp(&b.property));
+
+    let fields = entity
+        .attributes
+        .iter()
+        .filter_map(|attribute| {
+            let choices = entity.option_sets.iter().find(|o| o.logical_name == attribute.logical_name);
+            let field_type = field_type(attribute, &name, choices, enums)?;
+            let mut description = attribute.get_display_name();
+            match &field_type {
+                FieldType::Lookup => {
+                    let targets: Vec<String> = navigation
+                        .iter()
+                        .filter(|n| n.attribute == attribute.logical_name)
+                        .map(|n| format!("`{}`", n.target))
+                        .collect();
+                    if !targets.is_empty() {
+                        description.push_str(&format!(", a lookup to {}", targets.join(", ")));
+                    }
+                }
+                FieldType::Choices(choice) => description.push_str(&format!(", comma-separated `{}` values", choice)),
+                _ => {}
+            }
+            let property = match field_type {
+                FieldType::Lookup => format!("_{}_value", attribute.logical_name),
+                _ => attribute.logical_name.clone(),
+            };
+            Some(Field {
+                logical_name: attribute.logical_name.clone(),
+                property,
+                name: attribute_name(attribute),
+                description: single_line(&description),
+                field_type,
+            })
+        })
+        .collect();
+
+    Model {
+        logical_name: metadata.logical_name.clone(),
+        entity_set_name: metadata.entity_set_name.clone(),
+        description: single_line(&format!("{} (`{}`)", metadata.get_display_name(), metadata.logical_name)),
+        name,
+        fields,
+        navigation,
+    }
+}
+
+/// Type of a column, registering the enum of a choice column
+///
+/// Columns without a value in Web API responses, such as images, files and
+/// virtual columns, have none. Neither have the names and entity types of
+/// lookups, which are returned as annotations.
+fn field_type(
+    attribute: &AttributeMetadata,
+    model_name: &str,
+    choices: Option<&AttributeOptionSet>,
+    enums: &mut BTreeMap<String, ChoiceEnum>,
+) -> Option<FieldType> {
+    let type_name = attribute.get_type_name();
+    let field_type = match type_name.strip_suffix("Type").unwrap_or(&type_name) {
+        "String" | "Memo" | "EntityName" if attribute.attribute_of.is_some() => return None,
+        "String" | "Memo" | "EntityName" => FieldType::Text,
+        "Integer" => FieldType::Integer,
+        "BigInt" => FieldType::BigInt,
+        "Decimal" | "Money" => FieldType::Decimal,
+        "Double" => FieldType::Double,
+        "Boolean" => FieldType::Boolean,
+        "DateTime" => FieldType::DateTime,
+        "Uniqueidentifier" => FieldType::Guid,
+        "Lookup" | "Customer" | "Owner" => FieldType::Lookup,
+        kind @ ("Picklist" | "State" | "Status" | "MultiSelectPicklist") => {
+            let Some(option_set) = choices.and_then(|c| c.option_set.as_ref()) else {
+                return Some(if kind == "MultiSelectPicklist" { FieldType::Text } else { FieldType::Integer });
+            };
+            // Global choices are shared by their columns
+            let name = if option_set.is_global == Some(true) {
+                pascal_case(&option_set.name)
+            } else {
+                format!("{}{}", model_name, attribute_name(attribute))
+            };
+            enums.entry(name.clone()).or_insert_with(|| ChoiceEnum {
+                description: single_line(&option_set.get_display_name()),
+                options: variants(option_set.options.as_deref().unwrap_or_default()),
+            });
+            if kind == "MultiSelectPicklist" { FieldType::Choices(name) } else { FieldType::Choice(name) }
+        }
+        _ => return None,
+    };
+    Some(field_type)
+}
+
+/// Variant names of the options of a choice, unique within it
+fn variants(options: &[OptionSetValue]) -> Vec<(String, i32)> {
+    let mut variants: Vec<(String, i32)> = Vec::new();
+    for option in options {
+        let mut name = pascal_case(&option.get_label());
+        if name.is_empty() {
+            name = format!("Value{}", option.value.unsigned_abs());
+        } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert(0, '_');
+        }
+        if variants.iter().any(|(existing, _)| *existing == name) {
+            name = format!("{}{}", name, option.value.unsigned_abs());
+        }
+        variants.push((name, option.value));
+    }
+    variants
+}
+
+fn attribute_name(attribute: &AttributeMetadata) -> String {
+    pascal_case(attribute.schema_name.as_deref().unwrap_or(&attribute.logical_name))
+}
+
+/// Join the ASCII letters and digits of a name into PascalCase words
+fn pascal_case(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or_default();
+            std::iter::once(first).chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+/// Rust field name of a logical name, which is already snake_case
+fn rust_field(logical_name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else", "enum",
+        "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "macro", "match", "mod",
+        "move", "mut", "override", "priv", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    if KEYWORDS.contains(&logical_name) {
+        format!("r#{}", logical_name)
+    } else if ["crate", "self", "super"].contains(&logical_name) {
+        // These cannot be raw identifiers
+        format!("{}_", logical_name)
+    } else {
+        logical_name.to_string()
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn ts_comment(text: &str) -> String {
+    text.replace("*/", "* /")
+}
+
+fn xml_comment(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_models_with_lookups_and_choices() {
+        let entity: EntitySnapshot = serde_json::from_value(json!({
+            "metadata": { "MetadataId": "e1", "LogicalName": "contact", "SchemaName": "Contact", "EntitySetName": "contacts",
+                          "DisplayName": { "UserLocalizedLabel": { "Label": "Contact" } } },
+            "attributes": [
+                { "MetadataId": "a1", "LogicalName": "contactid", "SchemaName": "ContactId", "AttributeType": "Uniqueidentifier" },
+                { "MetadataId": "a2", "LogicalName": "parentcustomerid", "SchemaName": "ParentCustomerId", "AttributeType": "Customer",
+                  "DisplayName": { "UserLocalizedLabel": { "Label": "Company Name" } } },
+                { "MetadataId": "a3", "LogicalName": "parentcustomeridname", "AttributeType": "String", "AttributeOf": "parentcustomerid" },
+                { "MetadataId": "a4", "LogicalName": "preferredcontactmethodcode", "SchemaName": "PreferredContactMethodCode",
+                  "AttributeType": "Picklist" },
+                { "MetadataId": "a5", "LogicalName": "type", "SchemaName": "Type", "AttributeType": "Integer" },
+                { "MetadataId": "a6", "LogicalName": "entityimage", "AttributeType": "Virtual",
+                  "AttributeTypeName": { "Value": "ImageType" } },
+            ],
+            "one_to_many": [],
+            "many_to_one": [
+                { "SchemaName": "contact_customer_accounts", "ReferencedEntity": "account", "ReferencingEntity": "contact",
+                  "ReferencingAttribute": "parentcustomerid", "ReferencingEntityNavigationPropertyName": "parentcustomerid_account" },
+            ],
+            "many_to_many": [],
+            "option_sets": [{ "LogicalName": "preferredcontactmethodcode", "OptionSet": { "MetadataId": "o1", "Name": "contact_preferredcontactmethodcode",
+                "IsGlobal": false, "DisplayName": { "UserLocalizedLabel": { "Label": "Preferred Method" } },
+                "Options": [
+                    { "Value": 1, "Label": { "UserLocalizedLabel": { "Label": "Any" } } },
+                    { "Value": 2, "Label": { "UserLocalizedLabel": { "Label": "E-mail" } } },
+                    { "Value": 3, "Label": { "UserLocalizedLabel": { "Label": "e mail" } } },
+                ] } }],
+        }))
+        .unwrap();
+        let codegen = Codegen::new("https://org.crm.dynamics.com", &[entity]);
+
+        let rust = codegen.render(Language::Rust, "");
+        assert!(rust.contains(
+            "    #[serde(rename = \"_parentcustomerid_value\", skip_serializing_if = \"Option::is_none\")]\n    pub parentcustomerid: Option<String>,\n"
+        ));
+        assert!(rust.contains("    pub preferredcontactmethodcode: Option<ContactPreferredContactMethodCode>,\n"));
+        assert!(rust.contains("    pub r#type: Option<i32>,\n"));
+        assert!(rust.contains("    pub const NAV_PARENTCUSTOMERID_ACCOUNT: &'static str = \"parentcustomerid_account\";\n"));
+        assert!(rust.contains("pub enum ContactPreferredContactMethodCode {\n    Any = 1,\n    EMail = 2,\n    EMail3 = 3,\n}\n"));
+        assert!(!rust.contains("parentcustomeridname"));
+        assert!(!rust.contains("entityimage"));
+
+        let typescript = codegen.render(Language::TypeScript, "");
+        assert!(typescript.contains("  /** Company Name, a lookup to `account` */\n  _parentcustomerid_value?: string | null;\n"));
+        assert!(typescript.contains("    parentcustomerid_account: \"parentcustomerid_account\",\n"));
+
+        let csharp = codegen.render(Language::CSharp, "Crm.Models");
+        assert!(csharp.contains("namespace Crm.Models;\n"));
+        assert!(csharp.contains("    [JsonPropertyName(\"_parentcustomerid_value\")]\n    public Guid? ParentCustomerId { get; set; }\n"));
+        assert!(csharp.contains("        public const string ParentCustomerIdAccount = \"parentcustomerid_account\";\n"));
+    }
+}
@@ -1,12 +1,98 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use anyhow::{Context, Result};
+use crate::api::RetryPolicy;
+use crate::auth::AuthSettings;
+use crate::erd::ErdSettings;
+use crate::ui::KeyBindings;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
-    pub environments: Vec<String>,
+    /// Known environments; plain URLs from older config files are read as
+    /// profiles with default settings
+    #[serde(deserialize_with = "deserialize_profiles")]
+    pub environments: Vec<EnvironmentProfile>,
     pub current_env: Option<String>,
+    /// Retries for throttled and transient failures
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Depth and exclusions of relationship diagrams
+    #[serde(default)]
+    pub erd: ErdSettings,
+}
+
+/// Colour tag for an environment, e.g. red for production
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvColor {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+/// Settings for one Dataverse environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentProfile {
+    pub url: String,
+    /// Friendly name shown instead of the URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<EnvColor>,
+    /// Key binding style used when `--vim` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_bindings: Option<KeyBindings>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+    #[serde(default)]
+    pub auth: AuthSettings,
+}
+
+impl EnvironmentProfile {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            name: None,
+            color: None,
+            key_bindings: None,
+            read_only: false,
+            auth: AuthSettings::default(),
+        }
+    }
+
+    /// Friendly name, or the URL when none is set
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.url)
+    }
+
+    fn matches(&self, url: &str) -> bool {
+        self.url.trim_end_matches('/') == url.trim_end_matches('/')
+    }
+}
+
+fn deserialize_profiles<'de, D>(deserializer: D) -> Result<Vec<EnvironmentProfile>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Url(String),
+        Profile(EnvironmentProfile),
+    }
+
+    let entries = Vec::<Entry>::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Url(url) => EnvironmentProfile::new(url),
+            Entry::Profile(profile) => profile,
+        })
+        .collect())
 }
 
 impl Config {
@@ -32,17 +118,71 @@ impl Config {
         Ok(())
     }
 
-    fn config_path() -> Result<PathBuf> {
+    /// The rynamo directory inside the platform config directory
+    pub fn config_dir() -> Result<PathBuf> {
         let mut path = dirs::config_dir().context("Could not find config directory")?;
         path.push("rynamo");
+        Ok(path)
+    }
+
+    fn config_path() -> Result<PathBuf> {
+        let mut path = Self::config_dir()?;
         path.push("config.toml");
         Ok(path)
     }
 
+    /// Profile for an environment URL, ignoring any trailing slash
+    pub fn profile(&self, url: &str) -> Option<&EnvironmentProfile> {
+        self.environments.iter().find(|p| p.matches(url))
+    }
+
+    pub fn profile_mut(&mut self, url: &str) -> Option<&mut EnvironmentProfile> {
+        self.environments.iter_mut().find(|p| p.matches(url))
+    }
+
+    /// Profile of the current environment
+    pub fn current_profile(&self) -> Option<&EnvironmentProfile> {
+        self.current_env.as_deref().and_then(|url| self.profile(url))
+    }
+
+    /// Authentication settings to use for an environment
+    pub fn auth_for(&self, url: &str) -> AuthSettings {
+        self.profile(url).map(|p| p.auth.clone()).unwrap_or_default()
+    }
+
     pub fn add_environment(&mut self, url: String) {
-        if !self.environments.contains(&url) {
-            self.environments.push(url.clone());
+        if self.profile(&url).is_none() {
+            self.environments.push(EnvironmentProfile::new(url.clone()));
         }
         self.current_env = Some(url);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_plain_urls_and_profiles() {
+        let config: Config = toml::from_str(
+            r#"
+            environments = [
+                "https://dev.crm4.dynamics.com",
+                { url = "https://prod.crm4.dynamics.com", name = "Production", color = "red", read_only = true, auth = { method = "device-code" } },
+            ]
+            current_env = "https://prod.crm4.dynamics.com/"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.environments[0], EnvironmentProfile::new("https://dev.crm4.dynamics.com"));
+        let prod = config.current_profile().unwrap();
+        assert_eq!(prod.display_name(), "Production");
+        assert_eq!(prod.color, Some(EnvColor::Red));
+        assert!(prod.read_only);
+        assert_eq!(prod.auth.method, crate::auth::AuthMethod::DeviceCode);
+
+        let saved: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        assert_eq!(saved.environments, config.environments);
+    }
+}
@@ -0,0 +1,381 @@
+//! Data dictionary documents
+//!
+//! A data dictionary describes the columns, choice options and relationships
+//! of entities, with an index that groups the entities by the solutions that
+//! contain them. It is written as Markdown or as a standalone HTML page.
+
+use crate::api::{DataverseClient, MAX_CONCURRENT_REQUESTS};
+use crate::models::{AttributeMetadata, ComponentType, OptionSetMetadata, Solution};
+use crate::snapshot::{EntitySnapshot, Snapshot};
+use anyhow::{Context, Result};
+use futures::{StreamExt, TryStreamExt};
+use std::collections::HashSet;
+
+/// Output format of a data dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DictionaryFormat {
+    Markdown,
+    Html,
+}
+
+const COLUMN_HEADERS: [&str; 8] = ["Column", "Display name", "Type", "Length", "Min", "Max", "Required", "Description"];
+const RELATIONSHIP_HEADERS: [&str; 4] = ["Relationship", "Type", "Related entity", "Lookup column"];
+
+/// Entities of one solution, by logical name
+#[derive(Debug, Clone)]
+pub struct SolutionGroup {
+    pub solution: Solution,
+    pub entities: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataDictionary {
+    pub environment: String,
+    pub groups: Vec<SolutionGroup>,
+    /// Entities in no solution of the index
+    pub ungrouped: Vec<String>,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+impl DataDictionary {
+    /// Read the entities of one solution, given by unique name, or of the
+    /// whole environment
+    pub async fn collect(client: &DataverseClient, solution: Option<&str>) -> Result<Self> {
+        let mut solutions = client.get_solutions().await.context("Failed to load solutions")?;
+        if let Some(unique_name) = solution {
+            solutions.retain(|s| s.unique_name.eq_ignore_ascii_case(unique_name));
+            if solutions.is_empty() {
+                anyhow::bail!("Solution '{}' not found", unique_name);
+            }
+        } else {
+            solutions.retain(Solution::is_user_solution);
+        }
+        let components: Vec<_> = futures::stream::iter(solutions)
+            .map(|solution| async move {
+                let components = client
+                    .get_solution_components(&solution.solution_id)
+                    .await
+                    .with_context(|| format!("Failed to load components of {}", solution.unique_name))?;
+                anyhow::Ok((solution, components))
+            })
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .try_collect()
+            .await?;
+
+        let mut entities = client.get_entities().await.context("Failed to load entities")?;
+        let name_of = |metadata_id: &str| {
+            entities
+                .iter()
+                .find(|e| e.metadata_id == metadata_id)
+                .map(|e| e.logical_name.clone())
+        };
+        let mut groups: Vec<SolutionGroup> = components
+            .into_iter()
+            .map(|(solution, components)| {
+                let mut names: Vec<String> = components
+                    .iter()
+                    .filter(|c| c.get_component_type() == ComponentType::Entity)
+                    .filter_map(|c| name_of(c.object_id.as_deref()?))
+                    .collect();
+                names.sort();
+                names.dedup();
+                SolutionGroup { solution, entities: names }
+            })
+            .filter(|group| !group.entities.is_empty())
+            .collect();
+        groups.sort_by_key(|g| g.solution.get_display_name().to_lowercase());
+
+        let grouped: HashSet<&str> = groups.iter().flat_map(|g| g.entities.iter().map(String::as_str)).collect();
+        let ungrouped = match solution {
+            Some(_) => Vec::new(),
+            None => entities
+                .iter()
+                .filter(|e| !grouped.contains(e.logical_name.as_str()))
+                .map(|e| e.logical_name.clone())
+                .collect(),
+        };
+        if solution.is_some() {
+            entities.retain(|e| grouped.contains(e.logical_name.as_str()));
+        }
+
+        Ok(Self {
+            environment: client.environment_url(),
+            groups,
+            ungrouped,
+            entities: Snapshot::capture_entities(client, entities).await?,
+        })
+    }
+
+    pub fn render(&self, format: DictionaryFormat) -> String {
+        match format {
+            DictionaryFormat::Markdown => self.to_markdown(),
+            DictionaryFormat::Html => self.to_html(),
+        }
+    }
+
+    /// Index sections: each solution with its entities, then the rest
+    fn index(&self) -> Vec<(String, &[String])> {
+        let mut sections: Vec<(String, &[String])> = self
+            .groups
+            .iter()
+            .map(|g| {
+                let version = g.solution.version.as_deref().map(|v| format!(" {}", v)).unwrap_or_default();
+                (format!("{} ({}{})", g.solution.get_display_name(), g.solution.unique_name, version), g.entities.as_slice())
+            })
+            .collect();
+        if !self.ungrouped.is_empty() {
+            sections.push(("Not in a solution".to_string(), self.ungrouped.as_slice()));
+        }
+        sections
+    }
+
+    fn entity_title(&self, logical_name: &str) -> String {
+        match self.entities.iter().find(|e| e.metadata.logical_name == logical_name) {
+            Some(entity) => entity.metadata.get_display_name(),
+            None => logical_name.to_string(),
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!("# Data dictionary\n\nEnvironment: {}\n\n## Contents\n", self.environment);
+        for (title, entities) in self.index() {
+            out.push_str(&format!("\n### {}\n\n", title));
+            for name in entities {
+                out.push_str(&format!("- [{}](#{}) `{}`\n", self.entity_title(name), name, name));
+            }
+        }
+
+        out.push_str("\n## Entities\n");
+        for entity in &self.entities {
+            let metadata = &entity.metadata;
+            out.push_str(&format!(
+                "\n<a id=\"{}\"></a>\n\n### {} (`{}`)\n\n",
+                metadata.logical_name,
+                metadata.get_display_name(),
+                metadata.logical_name
+            ));
+            let description = metadata.get_description();
+            if !description.is_empty() {
+                out.push_str(&format!("{}\n\n", description));
+            }
+            out.push_str(&markdown_table(&COLUMN_HEADERS, &column_rows(entity)));
+
+            let choices = choice_rows(entity);
+            if !choices.is_empty() {
+                out.push_str("\n#### Choices\n\n");
+                for (column, options) in choices {
+                    out.push_str(&format!("- `{}`: {}\n", column, options.join(", ")));
+                }
+            }
+
+            let relationships = relationship_rows(entity);
+            if !relationships.is_empty() {
+                out.push_str("\n#### Relationships\n\n");
+                out.push_str(&markdown_table(&RELATIONSHIP_HEADERS, &relationships));
+            }
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Data dictionary</title>\n<style>\n{}</style>\n</head>\n<body>\n<nav>\n<h1>Data dictionary</h1>\n<p>{}</p>\n",
+            HTML_STYLE,
+            escape_html(&self.environment)
+        );
+        for (title, entities) in self.index() {
+            out.push_str(&format!("<h2>{}</h2>\n<ul>\n", escape_html(&title)));
+            for name in entities {
+                out.push_str(&format!(
+                    "<li><a href=\"#{}\">{}</a> <code>{}</code></li>\n",
+                    escape_html(name),
+                    escape_html(&self.entity_title(name)),
+                    escape_html(name)
+                ));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</nav>\n<main>\n");
+
+        for entity in &self.entities {
+            let metadata = &entity.metadata;
+            out.push_str(&format!(
+                "<section id=\"{}\">\n<h2>{} <code>{}</code></h2>\n",
+                escape_html(&metadata.logical_name),
+                escape_html(&metadata.get_display_name()),
+                escape_html(&metadata.logical_name)
+            ));
+            let description = metadata.get_description();
+            if !description.is_empty() {
+                out.push_str(&format!("<p>{}</p>\n", escape_html(&description)));
+            }
+            out.push_str(&html_table(&COLUMN_HEADERS, &column_rows(entity)));
+
+            let choices = choice_rows(entity);
+            if !choices.is_empty() {
+                out.push_str("<h3>Choices</h3>\n<dl>\n");
+                for (column, options) in choices {
+                    out.push_str(&format!(
+                        "<dt><code>{}</code></dt><dd>{}</dd>\n",
+                        escape_html(&column),
+                        escape_html(&options.join(", "))
+                    ));
+                }
+                out.push_str("</dl>\n");
+            }
+
+            let relationships = relationship_rows(entity);
+            if !relationships.is_empty() {
+                out.push_str("<h3>Relationships</h3>\n");
+                out.push_str(&html_table(&RELATIONSHIP_HEADERS, &relationships));
+            }
+            out.push_str("</section>\n");
+        }
+        out.push_str("</main>\n</body>\n</html>\n");
+        out
+    }
+}
+
+const HTML_STYLE: &str = "body { display: flex; margin: 0; font-family: system-ui, sans-serif; font-size: 14px; }
+nav { width: 280px; height: 100vh; overflow-y: auto; position: sticky; top: 0; padding: 0 16px; background: #f4f4f6; }
+nav h2 { font-size: 13px; margin-top: 20px; }
+nav ul { list-style: none; padding: 0; }
+main { flex: 1; padding: 0 24px; }
+section { border-bottom: 1px solid #ddd; padding-bottom: 16px; }
+table { border-collapse: collapse; margin: 8px 0; }
+th, td { border: 1px solid #ddd; padding: 4px 8px; text-align: left; vertical-align: top; }
+th { background: #f4f4f6; }
+code { color: #555; }
+";
+
+fn or_empty<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+fn column_rows(entity: &EntitySnapshot) -> Vec<Vec<String>> {
+    entity
+        .attributes
+        .iter()
+        .map(|a: &AttributeMetadata| {
+            vec![
+                a.logical_name.clone(),
+                a.get_display_name(),
+                a.get_type_name(),
+                or_empty(&a.max_length),
+                or_empty(&a.min_value),
+                or_empty(&a.max_value),
+                a.required_level.as_ref().map(|r| r.value.clone()).unwrap_or_default(),
+                a.description.as_ref().map(|d| d.get_label()).unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+/// Options of each choice column as `value label` texts
+fn choice_rows(entity: &EntitySnapshot) -> Vec<(String, Vec<String>)> {
+    entity
+        .option_sets
+        .iter()
+        .filter_map(|o| {
+            let options = o.option_set.as_ref().and_then(|s: &OptionSetMetadata| s.options.as_ref())?;
+            let options = options.iter().map(|v| format!("{} {}", v.value, v.get_label())).collect();
+            Some((o.logical_name.clone(), options))
+        })
+        .collect()
+}
+
+fn relationship_rows(entity: &EntitySnapshot) -> Vec<Vec<String>> {
+    let name = &entity.metadata.logical_name;
+    let mut rows = Vec::new();
+    for (kind, relationships) in [("1:N", &entity.one_to_many), ("N:1", &entity.many_to_one), ("N:N", &entity.many_to_many)] {
+        for r in relationships {
+            rows.push(vec![
+                r.get_name(),
+                kind.to_string(),
+                r.get_related_entity(name).unwrap_or_else(|| name.clone()),
+                or_empty(&r.referencing_attribute),
+            ]);
+        }
+    }
+    rows
+}
+
+fn markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace(['\r', '\n'], " ");
+    let mut out = format!("| {} |\n|{}\n", headers.join(" | "), "---|".repeat(headers.len()));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|c| cell(c)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+fn html_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut out = String::from("<table>\n<tr>");
+    for header in headers {
+        out.push_str(&format!("<th>{}</th>", header));
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for cell in row {
+            out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render_markdown_and_html() {
+        let entity: EntitySnapshot = serde_json::from_value(json!({
+            "metadata": { "MetadataId": "e1", "LogicalName": "account",
+                          "DisplayName": { "UserLocalizedLabel": { "Label": "Account" } } },
+            "attributes": [
+                { "MetadataId": "a1", "LogicalName": "name", "AttributeType": "String", "MaxLength": 160,
+                  "RequiredLevel": { "Value": "ApplicationRequired" },
+                  "Description": { "UserLocalizedLabel": { "Label": "Company | trading name" } } },
+            ],
+            "one_to_many": [{ "SchemaName": "contact_customer_accounts", "ReferencedEntity": "account",
+                              "ReferencingEntity": "contact", "ReferencingAttribute": "parentcustomerid" }],
+            "many_to_one": [],
+            "many_to_many": [],
+            "option_sets": [{ "LogicalName": "industrycode", "OptionSet": { "MetadataId": "o1", "Name": "industry",
+                "Options": [{ "Value": 1, "Label": { "UserLocalizedLabel": { "Label": "R&D" } } }] } }],
+        }))
+        .unwrap();
+        let solution: Solution = serde_json::from_value(json!({
+            "solutionid": "s1", "uniquename": "core", "friendlyname": "Core", "version": "1.0.0.0",
+        }))
+        .unwrap();
+        let dictionary = DataDictionary {
+            environment: "https://org.crm.dynamics.com".to_string(),
+            groups: vec![SolutionGroup { solution, entities: vec!["account".to_string()] }],
+            ungrouped: Vec::new(),
+            entities: vec![entity],
+        };
+
+        let markdown = dictionary.render(DictionaryFormat::Markdown);
+        assert!(markdown.contains("\n### Core (core 1.0.0.0)\n\n- [Account](#account) `account`\n"));
+        assert!(markdown.contains("| name | name | String | 160 |  |  | ApplicationRequired | Company \\| trading name |\n"));
+        assert!(markdown.contains("- `industrycode`: 1 R&D\n"));
+        assert!(markdown.contains("| contact_customer_accounts | 1:N | contact | parentcustomerid |\n"));
+
+        let html = dictionary.render(DictionaryFormat::Html);
+        assert!(html.contains("<section id=\"account\">\n<h2>Account <code>account</code></h2>\n"));
+        assert!(html.contains("<dt><code>industrycode</code></dt><dd>1 R&amp;D</dd>\n"));
+    }
+}
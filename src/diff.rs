@@ -0,0 +1,476 @@
+//! Schema comparison between environments and snapshots
+//!
+//! Both sides are read into a `Snapshot` and matched by name: entities, their
+//! columns, relationships and choice options, and global option sets. An
+//! item is "added" when it only exists on the right-hand side.
+
+use crate::models::{AttributeMetadata, OptionSetMetadata, RelationshipMetadata};
+use crate::snapshot::{EntitySnapshot, Snapshot};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// The other side of a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompareSource {
+    Environment(String),
+    Snapshot(PathBuf),
+}
+
+impl CompareSource {
+    /// A snapshot if `value` is an existing file, otherwise an environment URL
+    pub fn parse(value: &str) -> Self {
+        let path = PathBuf::from(value);
+        if path.is_file() {
+            Self::Snapshot(path)
+        } else {
+            Self::Environment(value.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl Change {
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Added => '+',
+            Self::Removed => '-',
+            Self::Changed => '~',
+        }
+    }
+}
+
+/// A column, relationship or option set that differs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemDiff {
+    pub name: String,
+    pub change: Change,
+    /// What changed, for changed items
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+}
+
+/// An entity that differs
+///
+/// Columns and relationships are only compared for entities on both sides.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntityDiff {
+    pub name: String,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<ItemDiff>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub relationships: Vec<ItemDiff>,
+}
+
+/// Differences between two schemas
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaDiff {
+    pub left: String,
+    pub right: String,
+    pub entities: Vec<EntityDiff>,
+    pub option_sets: Vec<ItemDiff>,
+}
+
+/// One line of the diff view
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRow {
+    pub depth: usize,
+    /// `None` for section headings and change details
+    pub change: Option<Change>,
+    pub text: String,
+}
+
+impl SchemaDiff {
+    pub fn compare(left: &Snapshot, right: &Snapshot) -> Self {
+        let entities = pair_up(&left.entities, &right.entities, |e| &e.metadata.logical_name)
+            .into_iter()
+            .filter_map(|(name, l, r)| match (l, r) {
+                (Some(_), None) => Some(EntityDiff::new(name, Change::Removed)),
+                (None, Some(_)) => Some(EntityDiff::new(name, Change::Added)),
+                (Some(l), Some(r)) => compare_entities(l, r),
+                (None, None) => None,
+            })
+            .collect();
+
+        let option_sets = pair_up(&left.global_option_sets, &right.global_option_sets, |o| &o.name)
+            .into_iter()
+            .filter_map(|(name, l, r)| compare_items(name, l, r, |l, r| option_details(Some(l), Some(r))))
+            .collect();
+
+        Self {
+            left: left.environment.clone(),
+            right: right.environment.clone(),
+            entities,
+            option_sets,
+        }
+    }
+
+    /// Number of entities, columns, relationships and option sets that differ
+    pub fn change_count(&self) -> usize {
+        let entities: usize = self
+            .entities
+            .iter()
+            .map(|e| 1 + e.columns.len() + e.relationships.len())
+            .sum();
+        entities + self.option_sets.len()
+    }
+
+    /// Lines of the diff view: entities and their changes, then option sets
+    pub fn rows(&self) -> Vec<DiffRow> {
+        let mut rows = Vec::new();
+        let heading = |text: &str| DiffRow { depth: 0, change: None, text: text.to_string() };
+        let detail_rows = |depth: usize, details: &[String]| {
+            details
+                .iter()
+                .map(move |d| DiffRow { depth, change: None, text: d.clone() })
+                .collect::<Vec<_>>()
+        };
+
+        if !self.entities.is_empty() {
+            rows.push(heading("Entities"));
+        }
+        for entity in &self.entities {
+            rows.push(DiffRow { depth: 1, change: Some(entity.change), text: entity.name.clone() });
+            rows.extend(detail_rows(2, &entity.details));
+            for (kind, items) in [("column", &entity.columns), ("relationship", &entity.relationships)] {
+                for item in items {
+                    rows.push(DiffRow { depth: 2, change: Some(item.change), text: format!("{} {}", kind, item.name) });
+                    rows.extend(detail_rows(3, &item.details));
+                }
+            }
+        }
+
+        if !self.option_sets.is_empty() {
+            rows.push(heading("Global option sets"));
+        }
+        for option_set in &self.option_sets {
+            rows.push(DiffRow { depth: 1, change: Some(option_set.change), text: option_set.name.clone() });
+            rows.extend(detail_rows(2, &option_set.details));
+        }
+        rows
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# Schema diff\n\n`{}` → `{}`\n", self.left, self.right);
+        if self.entities.is_empty() && self.option_sets.is_empty() {
+            out.push_str("\nNo differences.\n");
+            return out;
+        }
+
+        if !self.entities.is_empty() {
+            out.push_str("\n## Entities\n");
+        }
+        for entity in &self.entities {
+            out.push_str(&format!("\n### {} `{}` ({})\n\n", entity.change.symbol(), entity.name, label(entity.change)));
+            for detail in &entity.details {
+                out.push_str(&format!("- {}\n", detail));
+            }
+            for (kind, items) in [("column", &entity.columns), ("relationship", &entity.relationships)] {
+                for item in items {
+                    push_item(&mut out, kind, item);
+                }
+            }
+        }
+
+        if !self.option_sets.is_empty() {
+            out.push_str("\n## Global option sets\n\n");
+        }
+        for option_set in &self.option_sets {
+            push_item(&mut out, "option set", option_set);
+        }
+        out
+    }
+}
+
+impl EntityDiff {
+    fn new(name: &str, change: Change) -> Self {
+        Self {
+            name: name.to_string(),
+            change,
+            details: Vec::new(),
+            columns: Vec::new(),
+            relationships: Vec::new(),
+        }
+    }
+}
+
+fn label(change: Change) -> &'static str {
+    match change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Changed => "changed",
+    }
+}
+
+fn push_item(out: &mut String, kind: &str, item: &ItemDiff) {
+    out.push_str(&format!("- {} {} `{}` {}\n", item.change.symbol(), kind, item.name, label(item.change)));
+    for detail in &item.details {
+        out.push_str(&format!("  - {}\n", detail));
+    }
+}
+
+/// Match items of both sides by name, sorted by name
+fn pair_up<'a, T>(
+    left: &'a [T],
+    right: &'a [T],
+    name: impl Fn(&'a T) -> &'a str,
+) -> Vec<(&'a str, Option<&'a T>, Option<&'a T>)> {
+    let mut pairs: BTreeMap<&str, (Option<&T>, Option<&T>)> = BTreeMap::new();
+    for item in left {
+        pairs.entry(name(item)).or_default().0 = Some(item);
+    }
+    for item in right {
+        pairs.entry(name(item)).or_default().1 = Some(item);
+    }
+    pairs.into_iter().map(|(name, (l, r))| (name, l, r)).collect()
+}
+
+/// Diff an item that may be missing on either side
+fn compare_items<T>(
+    name: &str,
+    left: Option<&T>,
+    right: Option<&T>,
+    details: impl FnOnce(&T, &T) -> Vec<String>,
+) -> Option<ItemDiff> {
+    let (change, details) = match (left, right) {
+        (Some(_), None) => (Change::Removed, Vec::new()),
+        (None, Some(_)) => (Change::Added, Vec::new()),
+        (Some(l), Some(r)) => (Change::Changed, details(l, r)),
+        (None, None) => return None,
+    };
+    if change == Change::Changed && details.is_empty() {
+        return None;
+    }
+    Some(ItemDiff { name: name.to_string(), change, details })
+}
+
+/// Record `label: left → right` when the values differ
+fn compare_value<T: PartialEq + Display>(details: &mut Vec<String>, label: &str, left: T, right: T) {
+    if left != right {
+        details.push(format!("{}: {} → {}", label, left, right));
+    }
+}
+
+fn or_none<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or_else(|| "none".to_string(), T::to_string)
+}
+
+fn compare_entities(left: &EntitySnapshot, right: &EntitySnapshot) -> Option<EntityDiff> {
+    let (l, r) = (&left.metadata, &right.metadata);
+    let mut diff = EntityDiff::new(&l.logical_name, Change::Changed);
+    compare_value(&mut diff.details, "display name", l.get_display_name(), r.get_display_name());
+    compare_value(&mut diff.details, "entity set", or_none(&l.entity_set_name), or_none(&r.entity_set_name));
+    compare_value(
+        &mut diff.details,
+        "primary name column",
+        or_none(&l.primary_name_attribute),
+        or_none(&r.primary_name_attribute),
+    );
+
+    let options = |entity: &'_ EntitySnapshot| -> BTreeMap<String, OptionSetMetadata> {
+        entity
+            .option_sets
+            .iter()
+            .filter_map(|o| Some((o.logical_name.clone(), o.option_set.clone()?)))
+            .collect()
+    };
+    let (left_options, right_options) = (options(left), options(right));
+    diff.columns = pair_up(&left.attributes, &right.attributes, |a| &a.logical_name)
+        .into_iter()
+        .filter_map(|(name, l, r)| {
+            compare_items(name, l, r, |l, r| {
+                column_details(l, r, left_options.get(name), right_options.get(name))
+            })
+        })
+        .collect();
+
+    let relationships = |entity: &'_ EntitySnapshot| -> Vec<RelationshipMetadata> {
+        let mut all: Vec<RelationshipMetadata> = entity
+            .one_to_many
+            .iter()
+            .chain(&entity.many_to_one)
+            .chain(&entity.many_to_many)
+            .cloned()
+            .collect();
+        // Self-referencing relationships are listed as both 1:N and N:1
+        all.sort_by(|a, b| a.schema_name.cmp(&b.schema_name));
+        all.dedup_by(|a, b| a.schema_name == b.schema_name);
+        all
+    };
+    let (left_relationships, right_relationships) = (relationships(left), relationships(right));
+    diff.relationships = pair_up(&left_relationships, &right_relationships, |r| {
+        r.schema_name.as_deref().unwrap_or_default()
+    })
+    .into_iter()
+    .filter_map(|(name, l, r)| {
+        compare_items(name, l, r, |l, r| {
+            let mut details = Vec::new();
+            compare_value(&mut details, "relationship", relationship_ends(l), relationship_ends(r));
+            details
+        })
+    })
+    .collect();
+
+    if diff.details.is_empty() && diff.columns.is_empty() && diff.relationships.is_empty() {
+        return None;
+    }
+    Some(diff)
+}
+
+/// Entities and columns a relationship connects, e.g. `contact.parentcustomerid → account.accountid`
+fn relationship_ends(relationship: &RelationshipMetadata) -> String {
+    match (&relationship.entity1_logical_name, &relationship.entity2_logical_name) {
+        (Some(entity1), Some(entity2)) => format!("{} ↔ {}", entity1, entity2),
+        _ => format!(
+            "{}.{} → {}.{}",
+            or_none(&relationship.referencing_entity),
+            or_none(&relationship.referencing_attribute),
+            or_none(&relationship.referenced_entity),
+            or_none(&relationship.referenced_attribute)
+        ),
+    }
+}
+
+fn column_details(
+    left: &AttributeMetadata,
+    right: &AttributeMetadata,
+    left_options: Option<&OptionSetMetadata>,
+    right_options: Option<&OptionSetMetadata>,
+) -> Vec<String> {
+    let required_level = |a: &AttributeMetadata| {
+        a.required_level.as_ref().map_or_else(|| "None".to_string(), |r| r.value.clone())
+    };
+    let mut details = Vec::new();
+    compare_value(&mut details, "type", left.get_type_name(), right.get_type_name());
+    compare_value(&mut details, "required level", required_level(left), required_level(right));
+    compare_value(&mut details, "display name", left.get_display_name(), right.get_display_name());
+    compare_value(&mut details, "max length", or_none(&left.max_length), or_none(&right.max_length));
+    compare_value(&mut details, "min value", or_none(&left.min_value), or_none(&right.min_value));
+    compare_value(&mut details, "max value", or_none(&left.max_value), or_none(&right.max_value));
+
+    // Options of global option sets are compared with the option set itself
+    let is_global = |o: Option<&OptionSetMetadata>| o.and_then(|o| o.is_global).unwrap_or(false);
+    if !is_global(left_options) && !is_global(right_options) {
+        details.extend(option_details(left_options, right_options));
+    }
+    details
+}
+
+/// Added and removed options and changed labels
+fn option_details(left: Option<&OptionSetMetadata>, right: Option<&OptionSetMetadata>) -> Vec<String> {
+    let labels = |option_set: Option<&OptionSetMetadata>| -> BTreeMap<i32, String> {
+        option_set
+            .and_then(|o| o.options.as_ref())
+            .map(|options| options.iter().map(|o| (o.value, o.get_label())).collect())
+            .unwrap_or_default()
+    };
+    let (left, right) = (labels(left), labels(right));
+
+    let mut details = Vec::new();
+    for (value, label) in &left {
+        match right.get(value) {
+            None => details.push(format!("option {} '{}' removed", value, label)),
+            Some(new_label) if new_label != label => {
+                details.push(format!("option {} label: '{}' → '{}'", value, label, new_label))
+            }
+            Some(_) => {}
+        }
+    }
+    for (value, label) in &right {
+        if !left.contains_key(value) {
+            details.push(format!("option {} '{}' added", value, label));
+        }
+    }
+    details.sort_by_key(|d| d.split(' ').nth(1).and_then(|v| v.parse::<i32>().ok()));
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot(environment: &str, entities: serde_json::Value) -> Snapshot {
+        serde_json::from_value(json!({
+            "version": 1,
+            "environment": environment,
+            "entities": entities,
+            "global_option_sets": [],
+            "solutions": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare_columns_and_options() {
+        let entity = |columns: serde_json::Value, options: serde_json::Value| {
+            json!({
+                "metadata": { "MetadataId": "e1", "LogicalName": "account" },
+                "attributes": columns,
+                "one_to_many": [], "many_to_one": [], "many_to_many": [],
+                "option_sets": [{ "LogicalName": "industrycode", "OptionSet": {
+                    "MetadataId": "o1", "Name": "account_industrycode", "IsGlobal": false, "Options": options,
+                }}],
+            })
+        };
+        let label = |text: &str| json!({ "UserLocalizedLabel": { "Label": text } });
+        let dev = snapshot("https://dev.crm.dynamics.com", json!([
+            entity(
+                json!([
+                    { "MetadataId": "a1", "LogicalName": "name", "AttributeType": "String", "MaxLength": 100 },
+                    { "MetadataId": "a2", "LogicalName": "industrycode", "AttributeType": "Picklist" },
+                    { "MetadataId": "a3", "LogicalName": "new_region", "AttributeType": "String",
+                      "RequiredLevel": { "Value": "ApplicationRequired" } },
+                ]),
+                json!([{ "Value": 1, "Label": label("Retail") }, { "Value": 2, "Label": label("Banking") }]),
+            ),
+            { "metadata": { "MetadataId": "e2", "LogicalName": "new_project" },
+              "attributes": [], "one_to_many": [], "many_to_one": [], "many_to_many": [] },
+        ]));
+        let prod = snapshot("https://prod.crm.dynamics.com", json!([entity(
+            json!([
+                { "MetadataId": "a1", "LogicalName": "name", "AttributeType": "String", "MaxLength": 160 },
+                { "MetadataId": "a2", "LogicalName": "industrycode", "AttributeType": "Picklist" },
+            ]),
+            json!([{ "Value": 1, "Label": label("Retail trade") }, { "Value": 3, "Label": label("Energy") }]),
+        )]));
+
+        let diff = SchemaDiff::compare(&prod, &dev);
+        assert_eq!(diff.change_count(), 5);
+        let account = &diff.entities[0];
+        assert_eq!(account.change, Change::Changed);
+        assert_eq!(
+            account.columns[0],
+            ItemDiff {
+                name: "industrycode".to_string(),
+                change: Change::Changed,
+                details: vec![
+                    "option 1 label: 'Retail trade' → 'Retail'".to_string(),
+                    "option 2 'Banking' added".to_string(),
+                    "option 3 'Energy' removed".to_string(),
+                ],
+            }
+        );
+        assert_eq!(account.columns[1].details, ["max length: 160 → 100"]);
+        assert_eq!(account.columns[2].change, Change::Added);
+        assert_eq!(diff.entities[1].change, Change::Added);
+
+        let rows = diff.rows();
+        assert_eq!(rows[0].text, "Entities");
+        assert_eq!(rows[2], DiffRow { depth: 2, change: Some(Change::Changed), text: "column industrycode".to_string() });
+        let markdown = diff.to_markdown();
+        assert!(markdown.contains("\n### + `new_project` (added)\n"));
+        assert!(markdown.contains("- ~ column `name` changed\n  - max length: 160 → 100\n"));
+    }
+}
@@ -0,0 +1,374 @@
+//! Entity relationship diagrams
+//!
+//! `Erd::collect` walks the relationships of a set of entities outward and
+//! keeps every entity within the configured depth. Diagrams show the primary
+//! key, the primary name and the lookup columns of the drawn relationships,
+//! and are written as Mermaid `erDiagram`, Graphviz DOT or PlantUML.
+
+use crate::api::{DataverseClient, MAX_CONCURRENT_REQUESTS};
+use crate::models::{AttributeMetadata, ComponentType, RelationshipMetadata};
+use anyhow::{Context, Result};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Output format of a diagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ErdFormat {
+    Mermaid,
+    Dot,
+    #[value(name = "plantuml")]
+    PlantUml,
+}
+
+impl ErdFormat {
+    pub const ALL: [ErdFormat; 3] = [Self::Mermaid, Self::Dot, Self::PlantUml];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Mermaid => "mmd",
+            Self::Dot => "dot",
+            Self::PlantUml => "puml",
+        }
+    }
+}
+
+/// Diagram settings, the `[erd]` section of the config file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErdSettings {
+    /// Relationships to follow from the starting entities; 0 draws only
+    /// the relationships between them
+    pub depth: usize,
+    /// Entities not walked into unless a diagram starts from them, because
+    /// nearly every table relates to them
+    pub exclude: Vec<String>,
+}
+
+impl Default for ErdSettings {
+    fn default() -> Self {
+        Self {
+            depth: 1,
+            exclude: ["businessunit", "organization", "systemuser", "team", "transactioncurrency"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Whether a column is a key of its entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Primary,
+    Foreign,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErdColumn {
+    pub name: String,
+    pub type_name: String,
+    pub key: Option<Key>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErdEntity {
+    pub logical_name: String,
+    /// Primary key, primary name and lookup columns
+    pub columns: Vec<ErdColumn>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cardinality {
+    OneToMany,
+    ManyToMany,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErdRelationship {
+    pub schema_name: String,
+    /// Referenced entity, or the first entity of a many-to-many relationship
+    pub from: String,
+    /// Referencing entity, or the second entity of a many-to-many relationship
+    pub to: String,
+    pub cardinality: Cardinality,
+}
+
+/// Entities and the relationships between them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Erd {
+    pub entities: Vec<ErdEntity>,
+    pub relationships: Vec<ErdRelationship>,
+}
+
+impl Erd {
+    /// Load the entities within `settings.depth` relationships of `roots`
+    pub async fn collect(client: &DataverseClient, roots: &[String], settings: &ErdSettings) -> Result<Self> {
+        let excluded: HashSet<&str> = settings.exclude.iter().map(String::as_str).collect();
+        let mut seen: BTreeSet<String> = roots.iter().cloned().collect();
+        let mut frontier: Vec<String> = seen.iter().cloned().collect();
+        let mut attributes = BTreeMap::new();
+        let mut relationships = Vec::new();
+
+        for level in 0..=settings.depth {
+            let loaded: Vec<_> = futures::stream::iter(frontier)
+                .map(|name| async move {
+                    let (attributes, one_to_many, many_to_one, many_to_many) = tokio::try_join!(
+                        client.get_entity_attributes(&name),
+                        client.get_entity_one_to_many(&name),
+                        client.get_entity_many_to_one(&name),
+                        client.get_entity_many_to_many(&name),
+                    )
+                    .with_context(|| format!("Failed to load metadata of {}", name))?;
+                    let related = [one_to_many, many_to_one, many_to_many].concat();
+                    anyhow::Ok((name, attributes, related))
+                })
+                .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+                .try_collect()
+                .await?;
+
+            frontier = Vec::new();
+            for (name, entity_attributes, related) in loaded {
+                if level < settings.depth {
+                    for relationship in &related {
+                        let Some(other) = relationship.get_related_entity(&name) else {
+                            continue;
+                        };
+                        if !excluded.contains(other.as_str()) && seen.insert(other.clone()) {
+                            frontier.push(other);
+                        }
+                    }
+                }
+                attributes.insert(name, entity_attributes);
+                relationships.extend(related);
+            }
+        }
+
+        Ok(Self::from_parts(attributes, relationships))
+    }
+
+    /// Build the diagram from the attributes of its entities and their
+    /// relationships, dropping relationships to entities outside it
+    fn from_parts(attributes: BTreeMap<String, Vec<AttributeMetadata>>, relationships: Vec<RelationshipMetadata>) -> Self {
+        let mut drawn: BTreeMap<String, (ErdRelationship, Option<String>)> = BTreeMap::new();
+        for relationship in relationships {
+            let Some(schema_name) = relationship.schema_name.clone() else {
+                continue;
+            };
+            let (from, to, cardinality, lookup) = match relationship {
+                RelationshipMetadata {
+                    entity1_logical_name: Some(entity1),
+                    entity2_logical_name: Some(entity2),
+                    ..
+                } => (entity1, entity2, Cardinality::ManyToMany, None),
+                RelationshipMetadata {
+                    referenced_entity: Some(referenced),
+                    referencing_entity: Some(referencing),
+                    referencing_attribute,
+                    ..
+                } => (referenced, referencing, Cardinality::OneToMany, referencing_attribute),
+                _ => continue,
+            };
+            if attributes.contains_key(&from) && attributes.contains_key(&to) {
+                let relationship = ErdRelationship { schema_name: schema_name.clone(), from, to, cardinality };
+                drawn.insert(schema_name, (relationship, lookup));
+            }
+        }
+
+        let lookups: HashSet<(&str, &str)> = drawn
+            .values()
+            .filter_map(|(r, lookup)| Some((r.to.as_str(), lookup.as_deref()?)))
+            .collect();
+        let entities = attributes
+            .iter()
+            .map(|(name, attributes)| {
+                let mut columns: Vec<ErdColumn> = attributes
+                    .iter()
+                    .filter_map(|a| {
+                        let key = if a.is_primary_id == Some(true) {
+                            Some(Key::Primary)
+                        } else if lookups.contains(&(name.as_str(), a.logical_name.as_str())) {
+                            Some(Key::Foreign)
+                        } else if a.is_primary_name == Some(true) {
+                            None
+                        } else {
+                            return None;
+                        };
+                        Some(ErdColumn {
+                            name: a.logical_name.clone(),
+                            type_name: a.attribute_type.clone().unwrap_or_else(|| "Unknown".to_string()),
+                            key,
+                        })
+                    })
+                    .collect();
+                // Primary key first, then the primary name, then lookups
+                columns.sort_by_key(|c| (c.key != Some(Key::Primary), c.key.is_some(), c.name.clone()));
+                ErdEntity { logical_name: name.clone(), columns }
+            })
+            .collect();
+
+        Self {
+            entities,
+            relationships: drawn.into_values().map(|(r, _)| r).collect(),
+        }
+    }
+
+    pub fn render(&self, format: ErdFormat) -> String {
+        match format {
+            ErdFormat::Mermaid => self.to_mermaid(),
+            ErdFormat::Dot => self.to_dot(),
+            ErdFormat::PlantUml => self.to_plantuml(),
+        }
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut out = String::from("erDiagram\n");
+        for entity in &self.entities {
+            out.push_str(&format!("    {} {{\n", entity.logical_name));
+            for column in &entity.columns {
+                let key = match column.key {
+                    Some(Key::Primary) => " PK",
+                    Some(Key::Foreign) => " FK",
+                    None => "",
+                };
+                out.push_str(&format!("        {} {}{}\n", column.type_name, column.name, key));
+            }
+            out.push_str("    }\n");
+        }
+        for r in &self.relationships {
+            let line = match r.cardinality {
+                Cardinality::OneToMany => "||--o{",
+                Cardinality::ManyToMany => "}o--o{",
+            };
+            out.push_str(&format!("    {} {} {} : \"{}\"\n", r.from, line, r.to, r.schema_name));
+        }
+        out
+    }
+
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph erd {\n    rankdir=LR;\n    node [shape=record, fontname=\"Helvetica\"];\n    edge [fontname=\"Helvetica\", fontsize=10, dir=both];\n\n");
+        for entity in &self.entities {
+            let columns: String = entity
+                .columns
+                .iter()
+                .map(|c| {
+                    let key = match c.key {
+                        Some(Key::Primary) => " (PK)",
+                        Some(Key::Foreign) => " (FK)",
+                        None => "",
+                    };
+                    format!("{} : {}{}\\l", c.name, c.type_name, key)
+                })
+                .collect();
+            out.push_str(&format!("    {} [label=\"{{{}|{}}}\"];\n", entity.logical_name, entity.logical_name, columns));
+        }
+        out.push('\n');
+        for r in &self.relationships {
+            let tail = match r.cardinality {
+                Cardinality::OneToMany => "tee",
+                Cardinality::ManyToMany => "crow",
+            };
+            out.push_str(&format!(
+                "    {} -> {} [label=\"{}\", arrowtail={}, arrowhead=crow];\n",
+                r.from, r.to, r.schema_name, tail
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn to_plantuml(&self) -> String {
+        let mut out = String::from("@startuml\nhide circle\nskinparam linetype ortho\n");
+        for entity in &self.entities {
+            out.push_str(&format!("\nentity {} {{\n", entity.logical_name));
+            for column in &entity.columns {
+                match column.key {
+                    Some(Key::Primary) => {
+                        out.push_str(&format!("  * {} : {} <<PK>>\n  --\n", column.name, column.type_name))
+                    }
+                    Some(Key::Foreign) => out.push_str(&format!("  {} : {} <<FK>>\n", column.name, column.type_name)),
+                    None => out.push_str(&format!("  {} : {}\n", column.name, column.type_name)),
+                }
+            }
+            out.push_str("}\n");
+        }
+        out.push('\n');
+        for r in &self.relationships {
+            let line = match r.cardinality {
+                Cardinality::OneToMany => "||--o{",
+                Cardinality::ManyToMany => "}o--o{",
+            };
+            out.push_str(&format!("{} {} {} : {}\n", r.from, line, r.to, r.schema_name));
+        }
+        out.push_str("@enduml\n");
+        out
+    }
+}
+
+/// Logical names of the entities in a solution
+pub async fn solution_entities(client: &DataverseClient, solution_id: &str) -> Result<Vec<String>> {
+    let (components, entities) = tokio::try_join!(client.get_solution_components(solution_id), client.get_entities())?;
+    let ids: HashSet<&str> = components
+        .iter()
+        .filter(|c| c.get_component_type() == ComponentType::Entity)
+        .filter_map(|c| c.object_id.as_deref())
+        .collect();
+    let mut names: Vec<String> = entities
+        .into_iter()
+        .filter(|e| ids.contains(e.metadata_id.as_str()))
+        .map(|e| e.logical_name)
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diagram_keeps_relationships_inside_it() {
+        let attributes: BTreeMap<String, Vec<AttributeMetadata>> = serde_json::from_value(json!({
+            "account": [
+                { "MetadataId": "a1", "LogicalName": "accountid", "AttributeType": "Uniqueidentifier", "IsPrimaryId": true },
+                { "MetadataId": "a2", "LogicalName": "name", "AttributeType": "String", "IsPrimaryName": true },
+                { "MetadataId": "a3", "LogicalName": "revenue", "AttributeType": "Money" },
+            ],
+            "contact": [
+                { "MetadataId": "c1", "LogicalName": "contactid", "AttributeType": "Uniqueidentifier", "IsPrimaryId": true },
+                { "MetadataId": "c2", "LogicalName": "parentcustomerid", "AttributeType": "Customer" },
+                { "MetadataId": "c3", "LogicalName": "owninguser", "AttributeType": "Lookup" },
+            ],
+        }))
+        .unwrap();
+        let relationships: Vec<RelationshipMetadata> = serde_json::from_value(json!([
+            { "SchemaName": "contact_customer_accounts", "ReferencedEntity": "account",
+              "ReferencingEntity": "contact", "ReferencingAttribute": "parentcustomerid" },
+            { "SchemaName": "contact_customer_accounts", "ReferencedEntity": "account",
+              "ReferencingEntity": "contact", "ReferencingAttribute": "parentcustomerid" },
+            { "SchemaName": "user_contact", "ReferencedEntity": "systemuser",
+              "ReferencingEntity": "contact", "ReferencingAttribute": "owninguser" },
+            { "SchemaName": "accountcontact_association", "Entity1LogicalName": "account",
+              "Entity2LogicalName": "contact" },
+        ]))
+        .unwrap();
+
+        let erd = Erd::from_parts(attributes, relationships);
+        assert_eq!(erd.relationships.len(), 2);
+        let contact = &erd.entities[1];
+        assert_eq!(
+            contact.columns.iter().map(|c| (c.name.as_str(), c.key)).collect::<Vec<_>>(),
+            [("contactid", Some(Key::Primary)), ("parentcustomerid", Some(Key::Foreign))]
+        );
+
+        let mermaid = erd.render(ErdFormat::Mermaid);
+        assert!(mermaid.contains("    account {\n        Uniqueidentifier accountid PK\n        String name\n    }\n"));
+        assert!(mermaid.contains("    account ||--o{ contact : \"contact_customer_accounts\"\n"));
+        assert!(mermaid.contains("    account }o--o{ contact : \"accountcontact_association\"\n"));
+        assert!(erd.render(ErdFormat::Dot).contains(
+            "    contact [label=\"{contact|contactid : Uniqueidentifier (PK)\\lparentcustomerid : Customer (FK)\\l}\"];\n"
+        ));
+        assert!(erd.render(ErdFormat::PlantUml).contains("  * accountid : Uniqueidentifier <<PK>>\n  --\n  name : String\n"));
+    }
+}
@@ -0,0 +1,427 @@
+//! FetchXML parsing and pre-flight validation
+//!
+//! The query is parsed with a real XML parser so that malformed documents and
+//! unknown entity or attribute names are reported with a line and column
+//! before anything is sent to Dataverse.
+
+use crate::models::{AttributeMetadata, EntityMetadata, LayoutColumn};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use quick_xml::writer::Writer;
+use std::collections::HashMap;
+
+/// A FetchXML problem with its one-based line and column
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("line {line}, column {column}: {message}")]
+pub struct FetchXmlError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A name used in the document and where it was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameRef {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// An `<entity>` or `<link-entity>` and the attribute names used on it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityNode {
+    pub name: NameRef,
+    pub alias: Option<String>,
+    pub attributes: Vec<NameRef>,
+}
+
+/// The parts of a FetchXML document needed for validation
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchDocument {
+    /// The root `<entity>` followed by every `<link-entity>` in document order
+    pub entities: Vec<EntityNode>,
+}
+
+impl FetchDocument {
+    /// Logical name of the root `<entity>`
+    pub fn root_entity(&self) -> &NameRef {
+        &self.entities[0].name
+    }
+}
+
+/// Convert a byte offset into a one-based line and column
+fn line_col(xml: &str, offset: usize) -> (usize, usize) {
+    let before = &xml[..offset.min(xml.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map(|l| l.chars().count()).unwrap_or(0) + 1;
+    (line, column)
+}
+
+fn error_at(xml: &str, offset: usize, message: impl Into<String>) -> FetchXmlError {
+    let (line, column) = line_col(xml, offset);
+    FetchXmlError {
+        message: message.into(),
+        line,
+        column,
+    }
+}
+
+/// Read an attribute value from an element
+fn attribute(xml: &str, offset: usize, element: &BytesStart, key: &str) -> Result<Option<String>, FetchXmlError> {
+    match element.try_get_attribute(key) {
+        Ok(Some(attr)) => attr
+            .unescape_value()
+            .map(|v| Some(v.into_owned()))
+            .map_err(|e| error_at(xml, offset, e.to_string())),
+        Ok(None) => Ok(None),
+        Err(e) => Err(error_at(xml, offset, e.to_string())),
+    }
+}
+
+/// Parse FetchXML, checking that it is well-formed and shaped like a query
+pub fn parse(xml: &str) -> Result<FetchDocument, FetchXmlError> {
+    let mut reader = Reader::from_str(xml);
+    let mut entities: Vec<EntityNode> = Vec::new();
+    // Open elements with their start offset, and indices of open entity nodes
+    let mut open: Vec<(String, usize)> = Vec::new();
+    let mut entity_stack: Vec<usize> = Vec::new();
+    // Conditions that refer to a link-entity by alias
+    let mut aliased: Vec<(String, NameRef)> = Vec::new();
+
+    loop {
+        let offset = reader.buffer_position() as usize;
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::End(_)) => {
+                let closed = open.pop().map(|(name, _)| name);
+                if matches!(closed.as_deref(), Some("entity" | "link-entity")) {
+                    entity_stack.pop();
+                }
+                continue;
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => continue,
+            Err(e) => return Err(error_at(xml, reader.error_position() as usize, e.to_string())),
+        };
+
+        let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+        let (line, column) = line_col(xml, offset);
+        let name_ref = |value: String| NameRef { name: value, line, column };
+        let parent = open.last().map(|(n, _)| n.as_str());
+
+        match (parent, name.as_str()) {
+            (None, "fetch") => {}
+            (None, other) => {
+                return Err(error_at(xml, offset, format!("Root element must be <fetch>, found <{}>", other)));
+            }
+            (Some("fetch"), "entity") => {
+                if !entities.is_empty() {
+                    return Err(error_at(xml, offset, "Only one <entity> is allowed"));
+                }
+                let entity = attribute(xml, offset, &element, "name")?
+                    .ok_or_else(|| error_at(xml, offset, "<entity> is missing the name attribute"))?;
+                entities.push(EntityNode { name: name_ref(entity), alias: None, attributes: Vec::new() });
+                entity_stack.push(entities.len() - 1);
+            }
+            (Some(_), "entity") => {
+                return Err(error_at(xml, offset, "<entity> must be a direct child of <fetch>"));
+            }
+            (Some("entity") | Some("link-entity"), "link-entity") => {
+                let entity = attribute(xml, offset, &element, "name")?
+                    .ok_or_else(|| error_at(xml, offset, "<link-entity> is missing the name attribute"))?;
+                let mut node = EntityNode {
+                    name: name_ref(entity),
+                    alias: attribute(xml, offset, &element, "alias")?,
+                    attributes: Vec::new(),
+                };
+                if let Some(from) = attribute(xml, offset, &element, "from")? {
+                    node.attributes.push(name_ref(from));
+                }
+                if let (Some(to), Some(&parent)) = (attribute(xml, offset, &element, "to")?, entity_stack.last()) {
+                    entities[parent].attributes.push(name_ref(to));
+                }
+                entities.push(node);
+                entity_stack.push(entities.len() - 1);
+            }
+            (Some(_), "link-entity") => {
+                return Err(error_at(xml, offset, "<link-entity> must be inside <entity> or <link-entity>"));
+            }
+            (_, "attribute") | (_, "order") | (_, "condition") => {
+                let key = if name == "attribute" { "name" } else { "attribute" };
+                let Some(&current) = entity_stack.last() else {
+                    return Err(error_at(xml, offset, format!("<{}> must be inside an <entity>", name)));
+                };
+                if let Some(value) = attribute(xml, offset, &element, key)? {
+                    match attribute(xml, offset, &element, "entityname")? {
+                        Some(alias) if name == "condition" => aliased.push((alias, name_ref(value))),
+                        _ => entities[current].attributes.push(name_ref(value)),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if is_empty {
+            if name == "entity" || name == "link-entity" {
+                entity_stack.pop();
+            }
+        } else {
+            open.push((name, offset));
+        }
+    }
+
+    if let Some((name, offset)) = open.last() {
+        return Err(error_at(xml, *offset, format!("<{}> is never closed", name)));
+    }
+    if entities.is_empty() {
+        return Err(error_at(xml, 0, "Missing <entity name=\"...\"> element"));
+    }
+
+    for (alias, attr) in aliased {
+        let entity = entities
+            .iter_mut()
+            .find(|e| e.alias.as_deref() == Some(alias.as_str()) || e.name.name == alias)
+            .ok_or_else(|| FetchXmlError {
+                message: format!("No <link-entity> with alias '{}'", alias),
+                line: attr.line,
+                column: attr.column,
+            })?;
+        entity.attributes.push(attr);
+    }
+
+    Ok(FetchDocument { entities })
+}
+
+/// Check entity and attribute names against metadata
+///
+/// Attributes are only checked for entities present in `attributes`.
+pub fn validate(
+    doc: &FetchDocument,
+    entities: &[EntityMetadata],
+    attributes: &HashMap<String, Vec<AttributeMetadata>>,
+) -> Result<(), FetchXmlError> {
+    for node in &doc.entities {
+        if !entities.iter().any(|e| e.logical_name == node.name.name) {
+            return Err(FetchXmlError {
+                message: format!("Unknown entity '{}'", node.name.name),
+                line: node.name.line,
+                column: node.name.column,
+            });
+        }
+        let Some(known) = attributes.get(&node.name.name) else {
+            continue;
+        };
+        for attr in &node.attributes {
+            if !known.iter().any(|a| a.logical_name == attr.name) {
+                return Err(FetchXmlError {
+                    message: format!("Unknown attribute '{}' on '{}'", attr.name, node.name.name),
+                    line: attr.line,
+                    column: attr.column,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the grid columns from a view's layoutxml
+///
+/// Cells without a name (such as icon columns) are skipped, and a missing
+/// width falls back to the model-driven app default of 100 pixels.
+pub fn parse_layout(layout_xml: &str) -> Result<Vec<LayoutColumn>, FetchXmlError> {
+    let mut reader = Reader::from_str(layout_xml);
+    let mut columns = Vec::new();
+
+    loop {
+        let offset = reader.buffer_position() as usize;
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"cell" => {
+                let Some(name) = attribute(layout_xml, offset, &e, "name")? else {
+                    continue;
+                };
+                let width = attribute(layout_xml, offset, &e, "width")?
+                    .and_then(|w| w.parse().ok())
+                    .unwrap_or(100);
+                columns.push(LayoutColumn { name, width });
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(error_at(layout_xml, reader.error_position() as usize, e.to_string())),
+        }
+    }
+
+    Ok(columns)
+}
+
+/// Page number requested by the `page` attribute on `<fetch>`, defaulting to 1
+pub fn page_number(xml: &str) -> u32 {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"fetch" => {
+                return attribute(xml, 0, &e, "page")
+                    .ok()
+                    .flatten()
+                    .and_then(|p| p.parse().ok())
+                    .unwrap_or(1);
+            }
+            Ok(Event::Eof) | Err(_) => return 1,
+            _ => {}
+        }
+    }
+}
+
+/// Read the next page number and decoded paging cookie from the
+/// `@Microsoft.Dynamics.CRM.fetchxmlpagingcookie` annotation
+///
+/// The annotation is a `<cookie pagenumber="2" pagingcookie="..."/>` element
+/// whose `pagingcookie` attribute is URL-encoded twice.
+pub fn parse_paging_cookie(annotation: &str) -> Option<(u32, Option<String>)> {
+    let mut reader = Reader::from_str(annotation);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.name().as_ref() == b"cookie" => {
+                let page = attribute(annotation, 0, &e, "pagenumber").ok()??.parse().ok()?;
+                let cookie = attribute(annotation, 0, &e, "pagingcookie")
+                    .ok()
+                    .flatten()
+                    .and_then(|c| urlencoding::decode(&c).ok().map(|c| c.into_owned()))
+                    .and_then(|c| urlencoding::decode(&c).ok().map(|c| c.into_owned()));
+                return Some((page, cookie));
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Rewrite the `page` and `paging-cookie` attributes on `<fetch>`
+pub fn set_paging(xml: &str, page: u32, paging_cookie: Option<&str>) -> Result<String, FetchXmlError> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+    let write_error = |e: std::io::Error| error_at(xml, 0, e.to_string());
+
+    loop {
+        let offset = reader.buffer_position() as usize;
+        let event = reader
+            .read_event()
+            .map_err(|e| error_at(xml, reader.error_position() as usize, e.to_string()))?;
+        let event = match event {
+            Event::Start(e) if e.name().as_ref() == b"fetch" => Event::Start(paged_fetch(xml, offset, &e, page, paging_cookie)?),
+            Event::Empty(e) if e.name().as_ref() == b"fetch" => Event::Empty(paged_fetch(xml, offset, &e, page, paging_cookie)?),
+            Event::Eof => break,
+            other => other,
+        };
+        writer.write_event(event).map_err(write_error)?;
+    }
+
+    String::from_utf8(writer.into_inner()).map_err(|e| error_at(xml, 0, e.to_string()))
+}
+
+/// Copy a `<fetch>` element, replacing its paging attributes
+fn paged_fetch(
+    xml: &str,
+    offset: usize,
+    element: &BytesStart,
+    page: u32,
+    paging_cookie: Option<&str>,
+) -> Result<BytesStart<'static>, FetchXmlError> {
+    let mut fetch = BytesStart::new("fetch");
+    for attr in element.attributes() {
+        let attr = attr.map_err(|e| error_at(xml, offset, e.to_string()))?;
+        if !matches!(attr.key.as_ref(), b"page" | b"paging-cookie") {
+            fetch.push_attribute(attr);
+        }
+    }
+    let page = page.to_string();
+    fetch.push_attribute(("page", page.as_str()));
+    if let Some(cookie) = paging_cookie {
+        fetch.push_attribute(("paging-cookie", cookie));
+    }
+    Ok(fetch.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_reports_line_of_malformed_xml() {
+        let err = parse("<fetch>\n  <entity name=\"account\">\n    <attribute name=\"name\">\n  </entity>\n</fetch>")
+            .unwrap_err();
+        assert_eq!(err.line, 4);
+
+        let err = parse("<fetch>\n  <entity name=\"account\">\n").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn test_validate_names_against_metadata() {
+        let xml = "<fetch>\n  <entity name='account'>\n    <attribute name='name' />\n    \
+                   <link-entity name='contact' from='parentcustomerid' to='accountid' alias='c'>\n    \
+                   </link-entity>\n    <filter><condition entityname='c' attribute='fulname' operator='not-null' /></filter>\n  \
+                   </entity>\n</fetch>";
+        let doc = parse(xml).unwrap();
+        assert_eq!(doc.root_entity().name, "account");
+        assert_eq!(doc.entities.len(), 2);
+
+        let entity = |name: &str| -> EntityMetadata {
+            serde_json::from_value(json!({ "MetadataId": name, "LogicalName": name })).unwrap()
+        };
+        let attr = |name: &str| -> AttributeMetadata {
+            serde_json::from_value(json!({ "MetadataId": name, "LogicalName": name })).unwrap()
+        };
+        let mut attributes = HashMap::new();
+        attributes.insert("account".to_string(), vec![attr("accountid"), attr("name")]);
+        attributes.insert("contact".to_string(), vec![attr("fullname"), attr("parentcustomerid")]);
+
+        let err = validate(&doc, &[entity("account"), entity("contact")], &attributes).unwrap_err();
+        assert_eq!(err.message, "Unknown attribute 'fulname' on 'contact'");
+        assert_eq!(err.line, 6);
+    }
+
+    #[test]
+    fn test_paging_cookie_round_trip() {
+        let annotation = "<cookie pagenumber=\"2\" pagingcookie=\"%253ccookie%2520page%253d%25221%2522%253e%253caccountid%2520last%253d%2522%257b1%257d%2522%2520%252f%253e%253c%252fcookie%253e\" istracking=\"False\" />";
+        let (page, cookie) = parse_paging_cookie(annotation).unwrap();
+        assert_eq!(page, 2);
+        let cookie = cookie.unwrap();
+        assert_eq!(cookie, "<cookie page=\"1\"><accountid last=\"{1}\" /></cookie>");
+
+        let xml = "<fetch count='50' page='1'><entity name='account'><attribute name='name'/></entity></fetch>";
+        assert_eq!(page_number(xml), 1);
+        let paged = set_paging(xml, page, Some(&cookie)).unwrap();
+        assert_eq!(
+            paged,
+            "<fetch count=\"50\" page=\"2\" paging-cookie=\"&lt;cookie page=&quot;1&quot;&gt;&lt;accountid last=&quot;{1}&quot; /&gt;&lt;/cookie&gt;\">\
+             <entity name='account'><attribute name='name'/></entity></fetch>"
+        );
+        assert_eq!(page_number(&paged), 2);
+    }
+
+    #[test]
+    fn test_layout_orders_result_columns() {
+        let layout = parse_layout(
+            "<grid name='resultset' object='2'><row name='result' id='contactid'>\
+             <cell name='fullname' width='300' /><cell name='parentcustomerid' width='150' />\
+             <cell name='a_1.telephone1' /></row></grid>",
+        )
+        .unwrap();
+        assert_eq!(layout[0], LayoutColumn { name: "fullname".to_string(), width: 300 });
+        assert_eq!(layout[2].width, 100);
+
+        let mut result = crate::models::QueryResult::from_json(&json!({ "value": [{
+            "contactid": "1",
+            "fullname": "Ada",
+            "_parentcustomerid_value": "2",
+            "_parentcustomerid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "account",
+            "_parentcustomerid_value@OData.Community.Display.V1.FormattedValue": "Contoso",
+        }]}));
+        result.apply_layout(layout);
+        assert_eq!(result.columns, ["fullname", "parentcustomerid", "a_1.telephone1"]);
+        assert_eq!(result.rows[0], ["Ada", "Contoso", "-"]);
+        assert_eq!(result.lookups[&(0, 1)].logical_name, "account");
+    }
+}
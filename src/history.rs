@@ -0,0 +1,147 @@
+//! Query history persisted across sessions
+//!
+//! Every executed query is appended as one JSON object per line to
+//! `history.jsonl` in the rynamo config directory.
+
+use crate::config::Config;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Number of entries kept when loading the history file
+const MAX_ENTRIES: usize = 1000;
+
+/// Where a query was run from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    /// Guided query builder, recorded as the OData URL
+    Guided,
+    Sql,
+    FetchXml,
+}
+
+impl HistoryKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Guided => "Guided",
+            Self::Sql => "SQL",
+            Self::FetchXml => "FetchXML",
+        }
+    }
+}
+
+/// One executed query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub text: String,
+    pub entity: Option<String>,
+    pub environment: String,
+    pub timestamp: DateTime<Utc>,
+    /// Rows returned by the first page, if the query succeeded
+    pub rows: Option<usize>,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Executed queries, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct QueryHistory {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl QueryHistory {
+    /// Load the history file, skipping lines that cannot be parsed
+    ///
+    /// Rewrites the file with only the newest entries once it grows past
+    /// `MAX_ENTRIES` lines.
+    pub fn load() -> Result<Self> {
+        let path = Self::history_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let mut entries: Vec<HistoryEntry> = content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+        if content.lines().count() > MAX_ENTRIES {
+            let mut kept = String::new();
+            for entry in &entries {
+                kept.push_str(&serde_json::to_string(entry)?);
+                kept.push('\n');
+            }
+            fs::write(path, kept)?;
+        }
+        Ok(Self { entries })
+    }
+
+    /// Add an entry and append it to the history file
+    pub fn record(&mut self, entry: HistoryEntry) -> Result<()> {
+        let path = Self::history_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn history_path() -> Result<PathBuf> {
+        let mut path = Config::config_dir()?;
+        path.push("history.jsonl");
+        Ok(path)
+    }
+
+    /// Distinct query texts of one kind, newest first
+    pub fn texts(&self, kind: HistoryKind) -> Vec<&str> {
+        let mut texts: Vec<&str> = Vec::new();
+        for entry in self.entries.iter().rev().filter(|e| e.kind == kind) {
+            if !texts.contains(&entry.text.as_str()) {
+                texts.push(&entry.text);
+            }
+        }
+        texts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_texts_are_distinct_and_newest_first() {
+        let entry = |kind, text: &str| HistoryEntry {
+            kind,
+            text: text.to_string(),
+            entity: None,
+            environment: "https://org.crm.dynamics.com".to_string(),
+            timestamp: Utc::now(),
+            rows: Some(1),
+            duration_ms: 10,
+            error: None,
+        };
+        let history = QueryHistory {
+            entries: vec![
+                entry(HistoryKind::Sql, "SELECT a"),
+                entry(HistoryKind::FetchXml, "<fetch/>"),
+                entry(HistoryKind::Sql, "SELECT b"),
+                entry(HistoryKind::Sql, "SELECT a"),
+            ],
+        };
+        assert_eq!(history.texts(HistoryKind::Sql), ["SELECT a", "SELECT b"]);
+
+        let line = serde_json::to_string(&history.entries[1]).unwrap();
+        assert_eq!(serde_json::from_str::<HistoryEntry>(&line).unwrap(), history.entries[1]);
+    }
+}
@@ -12,11 +12,21 @@ mod models;
 mod ui;
 mod export;
 mod config;
+mod sql;
+mod fetchxml;
+mod saved_queries;
+mod history;
+mod metadata_cache;
+mod snapshot;
+mod diff;
+mod erd;
+mod dictionary;
+mod codegen;
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -26,7 +36,8 @@ use std::sync::Arc;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::api::DataverseClient;
-use crate::auth::AzureAuthenticator;
+use crate::auth::{AuthMethod, AzureAuthenticator};
+use crate::history::HistoryKind;
 use crate::ui::{App, AppState, InputMode, KeyBindings, View};
 
 /// Rynamo - Dataverse TUI Explorer
@@ -35,13 +46,103 @@ use crate::ui::{App, AppState, InputMode, KeyBindings, View};
 #[command(about = "A terminal UI for exploring Dataverse and Dynamics 365 metadata")]
 #[command(version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Dataverse environment URL (e.g., https://yourorg.crm.dynamics.com)
-    #[arg(short, long, env = "DATAVERSE_URL")]
+    #[arg(short, long, env = "DATAVERSE_URL", global = true)]
     env: Option<String>,
 
     /// Use vim-style keybindings (j/k navigation)
     #[arg(long, default_value = "false")]
     vim: bool,
+
+    /// Authentication method, remembered per environment (default: azure-cli)
+    #[arg(long, value_enum, global = true)]
+    auth: Option<AuthMethod>,
+
+    /// Microsoft Entra tenant ID for service principal or device code sign-in
+    #[arg(long, global = true)]
+    tenant_id: Option<String>,
+
+    /// Application (client) ID for service principal or device code sign-in
+    #[arg(long, global = true)]
+    client_id: Option<String>,
+
+    /// Client secret for --auth client-secret (not saved)
+    #[arg(long, env = "AZURE_CLIENT_SECRET", hide_env_values = true, global = true)]
+    client_secret: Option<String>,
+
+    /// PKCS#12 (.pfx) certificate for --auth certificate
+    #[arg(long, global = true)]
+    certificate: Option<std::path::PathBuf>,
+
+    /// Password for the certificate (not saved)
+    #[arg(long, env = "AZURE_CLIENT_CERTIFICATE_PASSWORD", hide_env_values = true, global = true)]
+    certificate_password: Option<String>,
+
+    /// Browse a metadata snapshot offline instead of connecting
+    #[arg(long, global = true)]
+    snapshot: Option<std::path::PathBuf>,
+
+    /// Compare the schema with another environment URL or snapshot file
+    #[arg(long, value_name = "URL|FILE")]
+    compare: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write the metadata of an environment to a JSON snapshot
+    Snapshot {
+        /// File to write
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+    },
+    /// Write an entity relationship diagram
+    Erd {
+        /// Entity to start from; repeat for several
+        #[arg(long = "entity", required_unless_present = "solution")]
+        entities: Vec<String>,
+        /// Start from the entities of a solution, by unique name
+        #[arg(long)]
+        solution: Option<String>,
+        /// Relationships to follow from the starting entities (default from the config file, or 1)
+        #[arg(long)]
+        depth: Option<usize>,
+        #[arg(short, long, value_enum, default_value = "mermaid")]
+        format: erd::ErdFormat,
+        /// File to write
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+    },
+    /// Generate typed models of entities
+    Codegen {
+        /// Entity to generate; repeat for several
+        #[arg(long = "entity", required_unless_present = "solution")]
+        entities: Vec<String>,
+        /// Generate the entities of a solution, by unique name
+        #[arg(long)]
+        solution: Option<String>,
+        #[arg(short, long, value_enum, default_value = "rust")]
+        language: codegen::Language,
+        /// Namespace of C# models
+        #[arg(long, default_value = "Dataverse.Models")]
+        namespace: String,
+        /// File to write
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+    },
+    /// Write a data dictionary of the columns, choices and relationships of entities
+    Dictionary {
+        /// Only the entities of a solution, by unique name
+        #[arg(long)]
+        solution: Option<String>,
+        #[arg(short, long, value_enum, default_value = "markdown")]
+        format: dictionary::DictionaryFormat,
+        /// File to write
+        #[arg(short, long)]
+        out: std::path::PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -57,10 +158,17 @@ async fn main() -> Result<()> {
     // Load configuration
     let mut config = config::Config::load().unwrap_or_default();
     
+    // A snapshot is browsed without signing in to any environment
+    let snapshot = args.snapshot.as_deref().map(snapshot::Snapshot::load).transpose()?;
+
+    // Subcommands run one-off from scripts and leave the saved config alone
+    let save_config = args.command.is_none();
+
     // Determine the environment to start with
     let start_env = match args.env {
+        _ if snapshot.is_some() => None,
         Some(env) => {
-            if !config.environments.contains(&env) {
+            if save_config && config.profile(&env).is_none() {
                 config.add_environment(env.clone());
                 let _ = config.save();
             }
@@ -68,35 +176,132 @@ async fn main() -> Result<()> {
         }
         None => config.current_env.clone(),
     };
+    if args.command.is_some() && start_env.is_none() && snapshot.is_none() {
+        anyhow::bail!("Give the environment with --env or --snapshot");
+    }
 
-    // Set up authentication - use a dummy for discovery if no env is provided
-    let auth_url = start_env.as_deref().unwrap_or("https://common.crm.dynamics.com");
-    let authenticator = Arc::new(
-        AzureAuthenticator::new(auth_url)
-            .await
-            .context("Failed to create Azure authenticator")?,
-    );
-
-    // If we have an environment, test the connection
-    if let Some(env) = &start_env {
-        eprintln!("Connecting to {}...", env);
-        if let Err(e) = authenticator.test_connection().await {
-             eprintln!("Warning: Failed to connect to {}: {}", env, e);
-        } else {
-             eprintln!("Connected successfully!");
-        }
-    } else {
-        eprintln!("No environment specified. Starting in discovery mode...");
+    // Authentication settings saved for the environment, overridden by flags
+    let mut auth = start_env.as_deref().map(|env| config.auth_for(env)).unwrap_or_default();
+    let auth_flags_given = args.auth.is_some()
+        || args.tenant_id.is_some()
+        || args.client_id.is_some()
+        || args.certificate.is_some();
+    if let Some(method) = args.auth {
+        auth.method = method;
+    }
+    if args.tenant_id.is_some() {
+        auth.tenant_id = args.tenant_id;
     }
+    if args.client_id.is_some() {
+        auth.client_id = args.client_id;
+    }
+    if args.certificate.is_some() {
+        auth.certificate_path = args.certificate;
+    }
+    if let (true, Some(profile)) = (auth_flags_given && save_config, start_env.as_deref().and_then(|env| config.profile_mut(env))) {
+        profile.auth = auth.clone();
+        let _ = config.save();
+    }
+    auth.client_secret = args.client_secret;
+    auth.certificate_password = args.certificate_password;
 
     // Create API client
-    let client = Arc::new(DataverseClient::new(authenticator));
+    let client = match snapshot {
+        Some(snapshot) => DataverseClient::from_snapshot(snapshot),
+        None => {
+            // Set up authentication - use a dummy for discovery if no env is provided
+            let auth_url = start_env.as_deref().unwrap_or("https://common.crm.dynamics.com");
+            let authenticator = Arc::new(
+                AzureAuthenticator::with_settings(auth_url, &auth)
+                    .context("Failed to create Azure authenticator")?,
+            );
+
+            // If we have an environment, test the connection
+            if let Some(env) = &start_env {
+                eprintln!("Connecting to {}...", env);
+                if let Err(e) = authenticator.test_connection().await {
+                     eprintln!("Warning: Failed to connect to {}: {}", env, e);
+                } else {
+                     eprintln!("Connected successfully!");
+                }
+            } else {
+                eprintln!("No environment specified. Starting in discovery mode...");
+            }
+
+            DataverseClient::new(authenticator)
+        }
+    };
+    let client = Arc::new(client.with_retry_policy(config.retry));
+
+    match args.command {
+        Some(Command::Snapshot { out }) => {
+            eprintln!("Reading metadata of {}...", client.environment_url());
+            let snapshot = snapshot::Snapshot::capture(&client).await?;
+            snapshot.save(&out)?;
+            eprintln!("Wrote {} entities and {} solutions to {}", snapshot.entities.len(), snapshot.solutions.len(), out.display());
+            return Ok(());
+        }
+        Some(Command::Erd { mut entities, solution, depth, format, out }) => {
+            if let Some(unique_name) = solution {
+                entities.extend(solution_entity_names(&client, &unique_name).await?);
+            }
+            let mut settings = config.erd.clone();
+            if let Some(depth) = depth {
+                settings.depth = depth;
+            }
+            let diagram = erd::Erd::collect(&client, &entities, &settings).await?;
+            std::fs::write(&out, diagram.render(format))
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            eprintln!(
+                "Wrote {} entities and {} relationships to {}",
+                diagram.entities.len(),
+                diagram.relationships.len(),
+                out.display()
+            );
+            return Ok(());
+        }
+        Some(Command::Codegen { mut entities, solution, language, namespace, out }) => {
+            if let Some(unique_name) = solution {
+                entities.extend(solution_entity_names(&client, &unique_name).await?);
+            }
+            let all = client.get_entities().await?;
+            let mut selected = Vec::new();
+            for name in &entities {
+                let entity = all
+                    .iter()
+                    .find(|e| e.logical_name.eq_ignore_ascii_case(name))
+                    .with_context(|| format!("Entity '{}' not found", name))?;
+                if !selected.iter().any(|s: &models::EntityMetadata| s.logical_name == entity.logical_name) {
+                    selected.push(entity.clone());
+                }
+            }
+            let entities = snapshot::Snapshot::capture_entities(&client, selected).await?;
+            let models = codegen::Codegen::new(&client.environment_url(), &entities);
+            std::fs::write(&out, models.render(language, &namespace))
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            eprintln!("Wrote models of {} entities to {}", entities.len(), out.display());
+            return Ok(());
+        }
+        Some(Command::Dictionary { solution, format, out }) => {
+            eprintln!("Reading metadata of {}...", client.environment_url());
+            let dictionary = dictionary::DataDictionary::collect(&client, solution.as_deref()).await?;
+            std::fs::write(&out, dictionary.render(format))
+                .with_context(|| format!("Failed to write {}", out.display()))?;
+            eprintln!("Wrote {} entities to {}", dictionary.entities.len(), out.display());
+            return Ok(());
+        }
+        None => {}
+    }
 
     // Set up key bindings
     let key_bindings = if args.vim {
         KeyBindings::Vim
     } else {
-        KeyBindings::Arrows
+        start_env
+            .as_deref()
+            .and_then(|env| config.profile(env))
+            .and_then(|profile| profile.key_bindings)
+            .unwrap_or(KeyBindings::Arrows)
     };
 
     // Set up terminal
@@ -109,7 +314,21 @@ async fn main() -> Result<()> {
     // Create app and run
     let mut app = App::new(client, key_bindings);
     app.config = config;
+    app.vim_flag = args.vim;
+
+    // Device code sign-in instructions cannot be printed while the UI owns the terminal
+    let (prompts, prompt_receiver) = tokio::sync::mpsc::unbounded_channel();
+    auth::set_device_code_prompts(Some(prompts));
+    app.device_code_prompts = Some(prompt_receiver);
+    if args.snapshot.is_some() {
+        app.view = View::Entities;
+        app.state = AppState::Loading;
+    }
+    if let Some(other) = args.compare {
+        app.compare_with(diff::CompareSource::parse(&other));
+    }
     let result = run_app(&mut terminal, &mut app).await;
+    auth::set_device_code_prompts(None);
 
     // Restore terminal
     disable_raw_mode()?;
@@ -127,6 +346,16 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Logical names of the entities in a solution, by unique name
+async fn solution_entity_names(client: &DataverseClient, unique_name: &str) -> Result<Vec<String>> {
+    let solutions = client.get_solutions().await?;
+    let solution = solutions
+        .iter()
+        .find(|s| s.unique_name.eq_ignore_ascii_case(unique_name))
+        .with_context(|| format!("Solution '{}' not found", unique_name))?;
+    erd::solution_entities(client, &solution.solution_id).await
+}
+
 /// Main event loop
 async fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
@@ -134,17 +363,20 @@ async fn run_app(
 ) -> Result<()> {
     // Initial data load if we have an environment
     if app.view == View::Entities && app.state == AppState::Loading {
-        app.load_entities().await;
+        app.load_entities();
     }
 
     loop {
         // Render
         terminal.draw(|f| ui::components::render(f, app))?;
 
+        // Store the results of finished background tasks
+        app.apply_task_updates();
+
         // Handle events with timeout
         if app.should_load_more_jobs {
             app.should_load_more_jobs = false;
-            app.load_more_system_jobs().await;
+            app.load_more_system_jobs();
         }
 
         if event::poll(std::time::Duration::from_millis(100))? {
@@ -158,11 +390,22 @@ async fn run_app(
                     app.clear_message();
                 }
 
+                // Esc cancels what the current view is loading; popups and
+                // prompts handle their own Esc
+                if key.code == KeyCode::Esc
+                    && (app.input_mode == InputMode::Normal || app.tasks.is_loading(app.view))
+                    && app.cancel_tasks()
+                {
+                    continue;
+                }
+
                 match app.input_mode {
-                    InputMode::Normal => handle_normal_mode(app, key.code).await?,
-                    InputMode::Search => handle_search_mode(app, key.code).await?,
-                    InputMode::FetchXML => handle_fetchxml_mode(app, key.code).await?,
-                    InputMode::SQLQuery => handle_sql_query_mode(app, key.code).await?,
+                    InputMode::Normal => handle_normal_mode(app, key.code)?,
+                    InputMode::Search => handle_search_mode(app, key.code)?,
+                    InputMode::FetchXML => handle_fetchxml_mode(app, key)?,
+                    InputMode::SQLQuery => handle_sql_query_mode(app, key)?,
+                    InputMode::QueryName => handle_query_name_mode(app, key.code),
+                    InputMode::ErrorDetails => handle_error_details_mode(app, key.code),
                 }
 
                 if app.should_quit {
@@ -175,8 +418,22 @@ async fn run_app(
     Ok(())
 }
 
+/// Handle input while the error details popup is open
+fn handle_error_details_mode(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Up => app.error_details_scroll = app.error_details_scroll.saturating_sub(1),
+        KeyCode::Down => app.error_details_scroll = app.error_details_scroll.saturating_add(1),
+        KeyCode::PageUp => app.error_details_scroll = app.error_details_scroll.saturating_sub(10),
+        KeyCode::PageDown => app.error_details_scroll = app.error_details_scroll.saturating_add(10),
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') | KeyCode::Char('!') => {
+            app.input_mode = InputMode::Normal;
+        }
+        _ => {}
+    }
+}
+
 /// Handle input in normal mode
-async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
+fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
     use crate::ui::EntityTab;
     
     // Global shortcuts
@@ -208,7 +465,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             if app.view != View::Solutions && app.view != View::SolutionDetail {
                 app.view = View::Solutions;
                 if app.solutions.is_empty() {
-                    app.load_solutions().await;
+                    app.load_solutions();
                 }
             }
             return Ok(());
@@ -217,7 +474,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             if app.view != View::Users && app.view != View::UserDetail {
                 app.view = View::Users;
                 if app.users.is_empty() {
-                    app.load_users().await;
+                    app.load_users();
                 }
             }
             return Ok(());
@@ -226,7 +483,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             if app.view != View::OptionSets {
                 app.view = View::OptionSets;
                 if app.global_optionsets.is_empty() {
-                    app.load_global_optionsets().await;
+                    app.load_global_optionsets();
                 }
             }
             return Ok(());
@@ -235,7 +492,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             if app.view != View::SystemJobs && app.view != View::SystemJobDetail {
                 app.view = View::SystemJobs;
                 if app.system_jobs.is_empty() {
-                    app.load_system_jobs(None).await;
+                    app.load_system_jobs(None);
                 }
             }
             return Ok(());
@@ -252,7 +509,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
         }
         KeyCode::Char('D') => {
             if app.view == View::Environments {
-                app.discover_environments().await?;
+                app.discover_environments();
             }
             return Ok(());
         }
@@ -261,14 +518,14 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
                 View::Entities => {
                     if let Some(entity) = app.get_selected_entity() {
                         let metadata_id = entity.metadata_id.clone();
-                        app.load_solution_layers(&metadata_id, 1).await;
+                        app.load_solution_layers(&metadata_id, 1);
                     }
                 }
                 View::EntityDetail => {
                     if app.entity_tab == crate::ui::EntityTab::Attributes {
                         if let Some(attr) = app.get_selected_attribute() {
                             let metadata_id = attr.metadata_id.clone();
-                            app.load_solution_layers(&metadata_id, 2).await;
+                            app.load_solution_layers(&metadata_id, 2);
                         }
                     }
                 }
@@ -276,7 +533,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
                     if let Some(comp) = app.get_selected_component() {
                         let type_code = comp.component_type.unwrap_or(0);
                         let object_id = comp.object_id.as_deref().unwrap_or(&comp.solution_component_id).to_string();
-                        app.load_solution_layers(&object_id, type_code).await;
+                        app.load_solution_layers(&object_id, type_code);
                     }
                 }
                 _ => {}
@@ -288,6 +545,23 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             app.input_mode = crate::ui::InputMode::FetchXML;
             return Ok(());
         }
+        KeyCode::Char('!') => {
+            if app.error_details.is_some() {
+                app.input_mode = InputMode::ErrorDetails;
+            }
+            return Ok(());
+        }
+        KeyCode::Char('S') => {
+            app.view = View::SavedQueries;
+            app.saved_query_index = 0;
+            return Ok(());
+        }
+        KeyCode::Char('H') => {
+            app.view = View::History;
+            app.search_query.clear();
+            app.filter_history();
+            return Ok(());
+        }
         _ => {}
     }
 
@@ -336,7 +610,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
                     }
                     _ => {
                         // Execute query in other modes
-                        app.execute_guided_query().await;
+                        app.execute_guided_query();
                     }
                 }
             }
@@ -369,9 +643,10 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
                         ' ' => app.toggle_query_column(),
                         'a' => app.select_all_columns(),
                         'c' => app.clear_query(),
+                        's' => app.prompt_save_query(),
                         'n' => {
                             if app.query_mode == crate::ui::QueryMode::Results {
-                                app.load_next_page().await;
+                                app.load_next_page();
                             }
                         }
                         'e' => {
@@ -385,7 +660,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
             }
             KeyCode::F(5) => {
                 // Execute query
-                app.execute_guided_query().await;
+                app.execute_guided_query();
             }
             KeyCode::Tab => {
                 // Switch query mode
@@ -418,6 +693,12 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
         return Ok(());
     }
 
+    // Views tab runs the selected view
+    if app.view == View::EntityDetail && app.entity_tab == EntityTab::Views && key == KeyCode::Enter {
+        app.run_entity_view();
+        return Ok(());
+    }
+
     // Enter to select
     if key == KeyCode::Enter {
         match app.view {
@@ -425,67 +706,106 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
                 if let Some(entity) = app.get_selected_entity().cloned() {
                     let logical_name = entity.logical_name.clone();
                     app.enter_entity_detail();
-                    app.load_entity_detail(&logical_name).await;
+                    app.load_entity_detail(&logical_name);
                 }
             }
             View::Users => {
                 if let Some(user) = app.get_selected_user().cloned() {
                     let user_id = user.id.clone();
                     app.enter_user_detail();
-                    app.load_user_detail(&user_id).await;
+                    app.load_user_detail(&user_id);
                 }
             }
             View::Solutions => {
                 if let Some(solution) = app.get_selected_solution().cloned() {
                     let solution_id = solution.solution_id.clone();
                     app.enter_solution_detail();
-                    app.load_solution_detail(&solution_id).await;
+                    app.load_solution_detail(&solution_id);
                 }
             }
             View::OptionSets => {
                 // No action for now
             }
             View::GlobalSearch => {
-                app.enter_search_result().await;
+                app.enter_search_result();
             }
             View::Environments => {
-                if let Some(url) = app.config.environments.get(app.environment_index).cloned() {
-                    let _ = app.switch_environment(&url).await;
+                if let Some(url) = app.config.environments.get(app.environment_index).map(|p| p.url.clone()) {
+                    app.switch_environment(&url);
                 }
             }
             View::SolutionDetail => {
-                app.jump_to_component().await;
+                app.jump_to_component();
             }
             View::RecordDetail => {
-                app.navigate_to_related_record().await;
+                app.navigate_to_related_record();
             }
             View::SystemJobs => {
-                 if !app.filtered_system_jobs.is_empty() {
-                    let index = app.filtered_system_jobs[app.system_job_index];
-                    app.selected_system_job = Some(app.system_jobs[index].clone());
-                    
-                    // Fetch details to get full message
-                    if let Some(job) = &app.selected_system_job {
-                         let id = job.id.clone();
-                         if let Ok(details) = app.client.get_system_job(&id).await {
-                             app.selected_system_job = Some(details);
-                         }
-                    }
-                    
-                    app.view = View::SystemJobDetail;
-                }
+                app.open_system_job();
             }
             View::EnvironmentDiscovery => {
-                let _ = app.add_selected_discovery().await;
+                app.add_selected_discovery();
+            }
+            View::SavedQueries => {
+                app.run_saved_query();
+            }
+            View::History => {
+                app.open_history_entry();
+            }
+            View::RelationshipGraph => {
+                app.graph_enter();
             }
             _ => {}
         }
     }
 
+    // Compare the current environment with the selected one
+    if app.view == View::Environments && key == KeyCode::Char('c') {
+        if let Some(url) = app.config.environments.get(app.environment_index).map(|p| p.url.clone()) {
+            app.compare_with(diff::CompareSource::Environment(url));
+        }
+        return Ok(());
+    }
+
+    // Relationship graph of the selected entity
+    if key == KeyCode::Char('G') && matches!(app.view, View::Entities | View::EntityDetail) {
+        app.open_relationship_graph();
+        return Ok(());
+    }
+
+    if app.view == View::RelationshipGraph {
+        match key {
+            KeyCode::Backspace => app.graph_back(),
+            KeyCode::Char('o') => app.open_graph_center(),
+            _ => {}
+        }
+    }
+
+    // Relationship diagram of the selected entity or the open solution
+    if key == KeyCode::Char('x') && matches!(app.view, View::Entities | View::EntityDetail | View::SolutionDetail) {
+        app.export_erd();
+        return Ok(());
+    }
+
+    if app.view == View::SchemaDiff && key == KeyCode::Char('e') {
+        app.export_schema_diff();
+        return Ok(());
+    }
+
+    // Rename and delete saved queries
+    if app.view == View::SavedQueries {
+        match key {
+            KeyCode::Char('r') => app.prompt_rename_saved_query(),
+            KeyCode::Char('d') => app.delete_saved_query(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
     // Refresh for System Jobs
     if app.view == View::SystemJobs {
         if key == KeyCode::Char('r') || key == KeyCode::Char('R') {
-            app.refresh_system_jobs().await;
+            app.refresh_system_jobs();
             return Ok(());
         }
     }
@@ -502,7 +822,7 @@ async fn handle_normal_mode(app: &mut App, key: KeyCode) -> Result<()> {
 }
 
 /// Handle input in search mode
-async fn handle_search_mode(app: &mut App, key: KeyCode) -> Result<()> {
+fn handle_search_mode(app: &mut App, key: KeyCode) -> Result<()> {
     match key {
         KeyCode::Enter => {
             app.input_mode = InputMode::Normal;
@@ -514,9 +834,10 @@ async fn handle_search_mode(app: &mut App, key: KeyCode) -> Result<()> {
                 View::SolutionDetail => app.filter_solution_components(),
                 View::Users => app.filter_users(),
                 View::OptionSets => app.filter_optionsets(),
-                View::SystemJobs => app.search_system_jobs().await,
+                View::SystemJobs => app.search_system_jobs(),
                 View::EnvironmentDiscovery => app.filter_discovered_environments(),
                 View::GlobalSearch => app.execute_global_search(),
+                View::History => app.filter_history(),
                 _ => {}
             }
         }
@@ -532,9 +853,10 @@ async fn handle_search_mode(app: &mut App, key: KeyCode) -> Result<()> {
                 View::SolutionDetail => app.filter_solution_components(),
                 View::Users => app.filter_users(),
                 View::OptionSets => app.filter_optionsets(),
-                View::SystemJobs => app.load_system_jobs(None).await,
+                View::SystemJobs => app.load_system_jobs(None),
                 View::EnvironmentDiscovery => app.filter_discovered_environments(),
                 View::GlobalSearch => app.execute_global_search(),
+                View::History => app.filter_history(),
                 _ => {}
             }
         }
@@ -550,56 +872,135 @@ async fn handle_search_mode(app: &mut App, key: KeyCode) -> Result<()> {
 }
 
 /// Handle input in FetchXML mode
-async fn handle_fetchxml_mode(app: &mut crate::ui::App, key: KeyCode) -> Result<()> {
-    match key {
-        KeyCode::Enter => {
-            app.execute_fetch_xml_query().await;
-            app.input_mode = crate::ui::InputMode::Normal;
+fn handle_fetchxml_mode(app: &mut crate::ui::App, key: KeyEvent) -> Result<()> {
+    let editor = &mut app.fetchxml_editor;
+    let edited = match key.code {
+        // Only parse errors are known here; the query runs in the background
+        // and reopens the editor itself when it fails
+        KeyCode::F(5) => {
+            app.execute_fetch_xml_query();
+            if app.fetchxml_error.is_none() {
+                app.input_mode = crate::ui::InputMode::Normal;
+            }
+            false
+        }
+        KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => editor.undo(),
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.prompt_save_query();
+            false
+        }
+        // Other shortcuts are not text; Ctrl+Alt together is AltGr on Windows
+        _ if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            && !key.modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            false
         }
         KeyCode::Char(c) => {
-            app.fetchxml_query.insert(app.fetchxml_cursor, c);
-            app.fetchxml_cursor += 1;
+            editor.insert_char(c);
+            true
+        }
+        KeyCode::Enter => {
+            editor.insert_newline();
+            true
+        }
+        KeyCode::Tab => {
+            editor.insert_str("  ");
+            true
         }
         KeyCode::Backspace => {
-            if app.fetchxml_cursor > 0 {
-                app.fetchxml_query.remove(app.fetchxml_cursor - 1);
-                app.fetchxml_cursor -= 1;
-            }
+            editor.backspace();
+            true
+        }
+        KeyCode::Delete => {
+            editor.delete();
+            true
         }
         KeyCode::Left => {
-            if app.fetchxml_cursor > 0 {
-                app.fetchxml_cursor -= 1;
-            }
+            editor.move_left();
+            false
         }
         KeyCode::Right => {
-            if app.fetchxml_cursor < app.fetchxml_query.len() {
-                app.fetchxml_cursor += 1;
+            editor.move_right();
+            false
+        }
+        // Up on the first line and Down on the last line step through history
+        KeyCode::Up | KeyCode::Down
+            if (key.code == KeyCode::Up && editor.cursor().0 == 0)
+                || (key.code == KeyCode::Down && editor.cursor().0 + 1 == editor.lines().len()) =>
+        {
+            let current = editor.text();
+            if let Some(text) = app.recall_history(HistoryKind::FetchXml, &current, key.code == KeyCode::Up) {
+                app.fetchxml_editor.set_text(&text);
+                app.fetchxml_error = None;
             }
+            false
+        }
+        KeyCode::Up => {
+            editor.move_up();
+            false
+        }
+        KeyCode::Down => {
+            editor.move_down();
+            false
+        }
+        KeyCode::Home => {
+            editor.move_home();
+            false
+        }
+        KeyCode::End => {
+            editor.move_end();
+            false
         }
         KeyCode::Esc => {
             app.input_mode = crate::ui::InputMode::Normal;
             app.view = crate::ui::View::Entities; // Default back
+            false
         }
-        _ => {}
+        _ => false,
+    };
+    // Editing dismisses the last validation error and ends history recall
+    if edited {
+        app.fetchxml_error = None;
+        app.history_recall = None;
     }
     Ok(())
 }
 
 /// Handle input in SQL query mode
-async fn handle_sql_query_mode(app: &mut crate::ui::App, key: KeyCode) -> Result<()> {
-    match key {
+fn handle_sql_query_mode(app: &mut crate::ui::App, key: KeyEvent) -> Result<()> {
+    match key.code {
         KeyCode::Enter => {
-            app.execute_sql_query().await;
-            app.input_mode = crate::ui::InputMode::Normal;
+            app.execute_sql_query();
+            // Stay in the editor so syntax errors can be fixed in place
+            if app.sql_error.is_none() {
+                app.input_mode = crate::ui::InputMode::Normal;
+            }
+        }
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.prompt_save_query();
         }
         KeyCode::Char(c) => {
-            app.sql_query.insert(app.sql_cursor, c);
+            let idx = app.sql_byte_index();
+            app.sql_query.insert(idx, c);
             app.sql_cursor += 1;
+            app.sql_error = None;
+            app.history_recall = None;
         }
         KeyCode::Backspace => {
             if app.sql_cursor > 0 {
-                app.sql_query.remove(app.sql_cursor - 1);
                 app.sql_cursor -= 1;
+                let idx = app.sql_byte_index();
+                app.sql_query.remove(idx);
+                app.sql_error = None;
+                app.history_recall = None;
+            }
+        }
+        KeyCode::Up | KeyCode::Down => {
+            let current = app.sql_query.clone();
+            if let Some(text) = app.recall_history(HistoryKind::Sql, &current, key.code == KeyCode::Up) {
+                app.sql_cursor = text.chars().count();
+                app.sql_query = text;
+                app.sql_error = None;
             }
         }
         KeyCode::Left => {
@@ -608,7 +1009,7 @@ async fn handle_sql_query_mode(app: &mut crate::ui::App, key: KeyCode) -> Result
             }
         }
         KeyCode::Right => {
-            if app.sql_cursor < app.sql_query.len() {
+            if app.sql_cursor < app.sql_query.chars().count() {
                 app.sql_cursor += 1;
             }
         }
@@ -619,3 +1020,20 @@ async fn handle_sql_query_mode(app: &mut crate::ui::App, key: KeyCode) -> Result
     }
     Ok(())
 }
+
+/// Handle input in the query name prompt
+fn handle_query_name_mode(app: &mut crate::ui::App, key: KeyCode) {
+    let Some(prompt) = app.query_name_prompt.as_mut() else {
+        app.input_mode = InputMode::Normal;
+        return;
+    };
+    match key {
+        KeyCode::Enter => app.confirm_query_name(),
+        KeyCode::Esc => app.cancel_query_name(),
+        KeyCode::Backspace => {
+            prompt.name.pop();
+        }
+        KeyCode::Char(c) => prompt.name.push(c),
+        _ => {}
+    }
+}
@@ -0,0 +1,211 @@
+//! Entity and attribute metadata cached on disk per environment
+//!
+//! The cache is stored as JSON in `cache/<environment>.json` in the rynamo
+//! config directory, together with the server version stamp of the last
+//! refresh. Refreshing passes that stamp to `RetrieveMetadataChanges`, so
+//! only entities and attributes changed or deleted since then are fetched.
+
+use crate::api::{DataverseClient, DataverseError};
+use crate::config::Config;
+use crate::models::{AttributeMetadata, EntityMetadata, MetadataChanges};
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// Error code returned when a client version stamp is too old to diff against
+const EXPIRED_VERSION_STAMP: &str = "0x80044352";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataCache {
+    /// Server version stamp of the last refresh, `None` until the first one
+    pub version_stamp: Option<String>,
+    /// Entities sorted by logical name
+    pub entities: Vec<EntityMetadata>,
+    /// Attributes by entity logical name, each sorted by logical name
+    pub attributes: HashMap<String, Vec<AttributeMetadata>>,
+}
+
+impl MetadataCache {
+    fn cache_path(environment_url: &str) -> Result<PathBuf> {
+        let host = environment_url
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/');
+        let file_name: String = host
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+            .collect();
+        let mut path = Config::config_dir()?;
+        path.push("cache");
+        path.push(format!("{}.json", file_name));
+        Ok(path)
+    }
+
+    /// Read the cache of an environment, if there is a readable one
+    pub fn load(environment_url: &str) -> Option<Self> {
+        let content = fs::read_to_string(Self::cache_path(environment_url).ok()?).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&self, environment_url: &str) -> Result<()> {
+        let path = Self::cache_path(environment_url)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Fetch metadata changed since the last refresh, returning whether
+    /// anything changed
+    ///
+    /// Without a version stamp, or when the server no longer accepts it,
+    /// all metadata is fetched again.
+    pub async fn refresh(&mut self, client: &DataverseClient) -> Result<bool> {
+        let changes = match client.retrieve_metadata_changes(self.version_stamp.as_deref()).await {
+            Err(e) if is_expired_stamp(&e) => {
+                self.version_stamp = None;
+                client.retrieve_metadata_changes(None).await?
+            }
+            result => result?,
+        };
+        if self.version_stamp.is_none() {
+            self.entities.clear();
+            self.attributes.clear();
+        }
+        Ok(self.apply(changes))
+    }
+
+    /// Merge changes into the cache, returning whether anything changed
+    fn apply(&mut self, changes: MetadataChanges) -> bool {
+        let mut deleted = HashSet::new();
+        collect_strings(&changes.deleted_metadata, &mut deleted);
+        let mut changed = self.version_stamp.is_none() || !changes.entities.is_empty();
+
+        let removed: Vec<String> = self
+            .entities
+            .iter()
+            .filter(|e| deleted.contains(&e.metadata_id))
+            .map(|e| e.logical_name.clone())
+            .collect();
+        changed |= !removed.is_empty();
+        self.entities.retain(|e| !deleted.contains(&e.metadata_id));
+        for name in removed {
+            self.attributes.remove(&name);
+        }
+        for attributes in self.attributes.values_mut() {
+            let before = attributes.len();
+            attributes.retain(|a| !deleted.contains(&a.metadata_id));
+            changed |= attributes.len() != before;
+        }
+
+        for mut item in changes.entities {
+            let attributes = match item.get_mut("Attributes").map(Value::take) {
+                Some(Value::Array(attributes)) => attributes,
+                _ => Vec::new(),
+            };
+            let Some(metadata_id) = item.get("MetadataId").and_then(Value::as_str).map(str::to_string) else {
+                continue;
+            };
+            if let Some(entity) = parse_changed::<EntityMetadata>(item) {
+                match self.entities.iter_mut().find(|e| e.metadata_id == metadata_id) {
+                    Some(cached) => *cached = entity,
+                    None => self.entities.push(entity),
+                }
+            }
+
+            let Some(entity) = self.entities.iter().find(|e| e.metadata_id == metadata_id) else {
+                continue;
+            };
+            let cached = self.attributes.entry(entity.logical_name.clone()).or_default();
+            for attribute in attributes.into_iter().filter_map(parse_changed::<AttributeMetadata>) {
+                match cached.iter_mut().find(|a| a.metadata_id == attribute.metadata_id) {
+                    Some(existing) => *existing = attribute,
+                    None => cached.push(attribute),
+                }
+            }
+            cached.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        }
+
+        self.entities.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        self.version_stamp = Some(changes.server_version_stamp);
+        changed
+    }
+}
+
+/// Parse a metadata item unless `HasChanged` says only its children changed
+fn parse_changed<T: DeserializeOwned>(item: Value) -> Option<T> {
+    if item.get("HasChanged").and_then(Value::as_bool) == Some(false) {
+        return None;
+    }
+    serde_json::from_value(item).ok()
+}
+
+/// Collect every string in `DeletedMetadata`, which includes the deleted ids
+fn collect_strings(value: &Value, strings: &mut HashSet<String>) {
+    match value {
+        Value::String(s) => {
+            strings.insert(s.clone());
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_strings(v, strings)),
+        Value::Object(map) => map.values().for_each(|v| collect_strings(v, strings)),
+        _ => {}
+    }
+}
+
+fn is_expired_stamp(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<DataverseError>()
+        .and_then(|e| e.code.as_deref())
+        .is_some_and(|code| code.eq_ignore_ascii_case(EXPIRED_VERSION_STAMP))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn changes(value: Value) -> MetadataChanges {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_apply_full_then_incremental_changes() {
+        let mut cache = MetadataCache::default();
+        assert!(cache.apply(changes(json!({
+            "ServerVersionStamp": "1",
+            "EntityMetadata": [
+                { "MetadataId": "e2", "LogicalName": "contact", "Attributes": [
+                    { "MetadataId": "a2", "LogicalName": "fullname" },
+                    { "MetadataId": "a3", "LogicalName": "emailaddress1" },
+                ]},
+                { "MetadataId": "e1", "LogicalName": "account", "Attributes": [
+                    { "MetadataId": "a1", "LogicalName": "name" },
+                ]},
+            ],
+        }))));
+        assert_eq!(cache.entities[0].logical_name, "account");
+        assert_eq!(cache.attributes["contact"][0].logical_name, "emailaddress1");
+
+        // Only a child of contact changed, and an attribute of account was deleted
+        assert!(cache.apply(changes(json!({
+            "ServerVersionStamp": "2",
+            "EntityMetadata": [
+                { "MetadataId": "e2", "HasChanged": false, "Attributes": [
+                    { "MetadataId": "a4", "LogicalName": "telephone1", "HasChanged": true },
+                ]},
+            ],
+            "DeletedMetadata": { "Keys": ["Attribute"], "Values": [["a1"]] },
+        }))));
+        assert_eq!(cache.version_stamp.as_deref(), Some("2"));
+        assert_eq!(cache.entities.len(), 2);
+        assert_eq!(cache.attributes["contact"].len(), 3);
+        assert!(cache.attributes["account"].is_empty());
+
+        assert!(!cache.apply(changes(json!({ "ServerVersionStamp": "3", "EntityMetadata": [] }))));
+    }
+}
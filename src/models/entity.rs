@@ -1,9 +1,9 @@
 //! Entity and attribute metadata models
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Localized label structure from Dataverse
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct LocalizedLabel {
     #[serde(rename = "LocalizedLabels")]
     pub localized_labels: Option<Vec<LabelValue>>,
@@ -21,7 +21,7 @@ impl LocalizedLabel {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LabelValue {
     #[serde(rename = "Label")]
     pub label: Option<String>,
@@ -30,7 +30,7 @@ pub struct LabelValue {
 }
 
 /// Entity metadata from EntityDefinitions
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntityMetadata {
     #[serde(rename = "MetadataId")]
     pub metadata_id: String,
@@ -86,7 +86,7 @@ impl EntityMetadata {
 }
 
 /// Attribute metadata
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttributeMetadata {
     #[serde(rename = "MetadataId")]
     pub metadata_id: String,
@@ -129,6 +129,10 @@ pub struct AttributeMetadata {
 
     #[serde(rename = "MaxValue")]
     pub max_value: Option<f64>,
+
+    /// Column this one is derived from, such as the name of a lookup
+    #[serde(rename = "AttributeOf")]
+    pub attribute_of: Option<String>,
 }
 
 impl AttributeMetadata {
@@ -159,20 +163,38 @@ impl AttributeMetadata {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttributeTypeName {
     #[serde(rename = "Value")]
     pub value: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequiredLevel {
     #[serde(rename = "Value")]
     pub value: String,
 }
 
-/// Relationship metadata
+/// Response of the `RetrieveMetadataChanges` function
+///
+/// Entities and attributes are kept as JSON because an item whose
+/// `HasChanged` is `false` only carries its `MetadataId` and changed children.
 #[derive(Debug, Clone, Deserialize)]
+pub struct MetadataChanges {
+    #[serde(rename = "EntityMetadata", default)]
+    pub entities: Vec<serde_json::Value>,
+
+    /// Version stamp to pass as `ClientVersionStamp` on the next call
+    #[serde(rename = "ServerVersionStamp")]
+    pub server_version_stamp: String,
+
+    /// Metadata deleted since the client version stamp
+    #[serde(rename = "DeletedMetadata", default)]
+    pub deleted_metadata: serde_json::Value,
+}
+
+/// Relationship metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelationshipMetadata {
     #[serde(rename = "SchemaName")]
     pub schema_name: Option<String>,
@@ -190,6 +212,14 @@ pub struct RelationshipMetadata {
     #[serde(rename = "ReferencedAttribute")]
     pub referenced_attribute: Option<String>,
 
+    /// Single-valued navigation property of the lookup on the referencing entity
+    #[serde(rename = "ReferencingEntityNavigationPropertyName")]
+    pub referencing_navigation_property: Option<String>,
+
+    /// Collection-valued navigation property on the referenced entity
+    #[serde(rename = "ReferencedEntityNavigationPropertyName")]
+    pub referenced_navigation_property: Option<String>,
+
     // For N:N
     #[serde(rename = "Entity1LogicalName")]
     pub entity1_logical_name: Option<String>,
@@ -236,7 +266,7 @@ impl RelationshipMetadata {
 }
 
 /// Global or local OptionSet metadata
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OptionSetMetadata {
     #[serde(rename = "MetadataId")]
     pub metadata_id: String,
@@ -270,8 +300,18 @@ impl OptionSetMetadata {
     }
 }
 
+/// The options of a choice column
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeOptionSet {
+    #[serde(rename = "LogicalName")]
+    pub logical_name: String,
+
+    #[serde(rename = "OptionSet")]
+    pub option_set: Option<OptionSetMetadata>,
+}
+
 /// A value within an OptionSet
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OptionSetValue {
     #[serde(rename = "Value")]
     pub value: i32,
@@ -5,13 +5,16 @@ pub mod discovery;
 pub mod query;
 pub mod odata;
 pub mod system_jobs;
+pub mod view;
 
 pub use entity::{
-    AttributeMetadata, EntityMetadata, OptionSetMetadata, RelationshipMetadata,
+    AttributeMetadata, AttributeOptionSet, EntityMetadata, MetadataChanges, OptionSetMetadata, OptionSetValue,
+    RelationshipMetadata,
 };
 pub use solution::{Solution, SolutionComponent, ComponentType, SolutionComponentLayer};
 pub use user::{SystemUser, SecurityRole, Team, RoleAssignment, RoleSource};
 pub use discovery::{DiscoveryResponse, DiscoveryInstance};
-pub use query::QueryResult;
+pub use query::{FetchXmlPaging, LayoutColumn, QueryResult};
 // pub use odata::ODataError; // Assuming ODataError is not pub or missing?
 pub use system_jobs::SystemJob;
+pub use view::EntityView;
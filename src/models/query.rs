@@ -18,8 +18,6 @@ pub struct LookupInfo {
 /// A query definition for executing against an entity
 #[derive(Debug, Clone, Default)]
 pub struct QueryDefinition {
-    /// Entity logical name to query
-    pub entity_name: String,
     /// Entity set name (for URL)
     pub entity_set_name: String,
     /// Columns to select (empty = all)
@@ -76,6 +74,28 @@ impl QueryDefinition {
     }
 }
 
+/// State needed to request the next page of a FetchXML query
+#[derive(Debug, Clone, Default)]
+pub struct FetchXmlPaging {
+    /// Entity set the query is executed against
+    pub entity_set_name: String,
+    /// FetchXML of the page that was last loaded
+    pub fetch_xml: String,
+    /// Page number to request next
+    pub page: u32,
+    /// Decoded paging cookie returned with the last page
+    pub paging_cookie: Option<String>,
+}
+
+/// A column from a view's layoutxml
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutColumn {
+    /// Attribute name, or `alias.attribute` for link-entity columns
+    pub name: String,
+    /// Width in pixels as configured in the view
+    pub width: u16,
+}
+
 /// Query result from executing a query
 #[derive(Debug, Clone, Default)]
 pub struct QueryResult {
@@ -89,6 +109,10 @@ pub struct QueryResult {
     pub count: Option<usize>,
     /// Link to next page of results
     pub next_link: Option<String>,
+    /// Paging state when more FetchXML results are available
+    pub fetch_paging: Option<FetchXmlPaging>,
+    /// View layout the columns were arranged by, if any
+    pub layout: Vec<LayoutColumn>,
     /// Error message if query failed
     pub error: Option<String>,
     /// Raw JSON response for inspection
@@ -96,6 +120,85 @@ pub struct QueryResult {
 }
 
 impl QueryResult {
+    /// Whether another page of results can be loaded
+    pub fn has_more(&self) -> bool {
+        self.next_link.is_some() || self.fetch_paging.is_some()
+    }
+
+    /// Append the rows of the next page, keeping the current column order
+    ///
+    /// Pages can return a different set of columns because null values are
+    /// omitted, so rows are remapped by column name and new columns are added
+    /// at the end.
+    pub fn append(&mut self, mut next: QueryResult) {
+        if !self.layout.is_empty() {
+            next.apply_layout(self.layout.clone());
+        }
+        for col in &next.columns {
+            if !self.columns.contains(col) {
+                self.columns.push(col.clone());
+                for row in &mut self.rows {
+                    row.push("-".to_string());
+                }
+            }
+        }
+        let mapping: Vec<usize> = next
+            .columns
+            .iter()
+            .map(|c| self.columns.iter().position(|col| col == c).unwrap_or_default())
+            .collect();
+
+        let offset = self.rows.len();
+        for row in next.rows {
+            let mut mapped = vec!["-".to_string(); self.columns.len()];
+            for (value, &idx) in row.into_iter().zip(&mapping) {
+                mapped[idx] = value;
+            }
+            self.rows.push(mapped);
+        }
+        for ((row, col), lookup) in next.lookups {
+            self.lookups.insert((row + offset, mapping[col]), lookup);
+        }
+
+        self.next_link = next.next_link;
+        self.fetch_paging = next.fetch_paging;
+        self.raw_json = next.raw_json;
+    }
+
+    /// Keep only the layout's columns, in layout order
+    ///
+    /// Lookup cells in a layout name the attribute, while the Web API returns
+    /// them as `_attribute_value`, so both spellings are matched.
+    pub fn apply_layout(&mut self, layout: Vec<LayoutColumn>) {
+        let sources: Vec<Option<usize>> = layout
+            .iter()
+            .map(|cell| {
+                let lookup = format!("_{}_value", cell.name);
+                self.columns.iter().position(|c| *c == cell.name || *c == lookup)
+            })
+            .collect();
+
+        self.rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                sources
+                    .iter()
+                    .map(|source| source.and_then(|i| row.get(i).cloned()).unwrap_or_else(|| "-".to_string()))
+                    .collect()
+            })
+            .collect();
+        self.lookups = std::mem::take(&mut self.lookups)
+            .into_iter()
+            .filter_map(|((row, col), lookup)| {
+                let new_col = sources.iter().position(|&source| source == Some(col))?;
+                Some(((row, new_col), lookup))
+            })
+            .collect();
+        self.columns = layout.iter().map(|cell| cell.name.clone()).collect();
+        self.layout = layout;
+    }
+
     /// Create from JSON response
     pub fn from_json(json: &JsonValue) -> Self {
         let mut result = QueryResult::default();
@@ -117,17 +220,19 @@ impl QueryResult {
             return result;
         }
 
-        // Extract columns from first record
-        if let Some(first) = records.first() {
-            if let JsonValue::Object(obj) = first {
-                result.columns = obj
-                    .keys()
-                    .filter(|k| !k.starts_with('@')) // Skip OData annotations
-                    .cloned()
-                    .collect();
-                result.columns.sort();
+        // Extract columns from all records, since null values (such as columns
+        // from an outer-joined link-entity) are omitted from individual records
+        let mut columns = std::collections::BTreeSet::new();
+        for record in records {
+            if let JsonValue::Object(obj) = record {
+                columns.extend(
+                    obj.keys()
+                        .filter(|k| !k.starts_with('@')) // Skip OData annotations
+                        .cloned(),
+                );
             }
         }
+        result.columns = columns.into_iter().collect();
 
         // Extract rows
         for (row_idx, record) in records.iter().enumerate() {
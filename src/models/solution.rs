@@ -1,9 +1,9 @@
 //! Solution models
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Solution metadata
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Solution {
     #[serde(rename = "solutionid")]
     pub solution_id: String,
@@ -28,6 +28,9 @@ pub struct Solution {
 
     #[serde(rename = "installedon")]
     pub installed_on: Option<String>,
+
+    #[serde(rename = "isvisible", default)]
+    pub is_visible: Option<bool>,
 }
 
 impl Solution {
@@ -37,10 +40,16 @@ impl Solution {
             .clone()
             .unwrap_or_else(|| self.unique_name.clone())
     }
+
+    /// Whether this is a solution a maker would recognise, as opposed to the
+    /// system Default solution or a hidden one such as Active
+    pub fn is_user_solution(&self) -> bool {
+        self.is_visible != Some(false) && self.unique_name != "Default"
+    }
 }
 
 /// Solution component
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolutionComponent {
     #[serde(rename = "solutioncomponentid")]
     pub solution_component_id: String,
@@ -0,0 +1,47 @@
+//! System view (savedquery) and personal view (userquery) models
+
+use serde::Deserialize;
+
+/// A system or personal view defined for an entity
+///
+/// `savedquery` and `userquery` records share the fields used here, so both
+/// deserialize into this type; `personal` is set by the API layer.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EntityView {
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+
+    #[serde(rename = "fetchxml")]
+    pub fetch_xml: Option<String>,
+
+    #[serde(rename = "layoutxml")]
+    pub layout_xml: Option<String>,
+
+    #[serde(rename = "querytype")]
+    pub query_type: Option<i32>,
+
+    #[serde(rename = "isdefault", default)]
+    pub is_default: Option<bool>,
+
+    /// Whether this is a personal view (userquery)
+    #[serde(skip)]
+    pub personal: bool,
+}
+
+impl EntityView {
+    pub fn get_display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("(unnamed)")
+    }
+
+    /// Label for the `querytype` value
+    pub fn query_type_label(&self) -> &'static str {
+        match self.query_type {
+            Some(0) => "Public",
+            Some(1) => "Advanced Find",
+            Some(2) => "Associated",
+            Some(4) => "Quick Find",
+            Some(64) => "Lookup",
+            _ => "Other",
+        }
+    }
+}
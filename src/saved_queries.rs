@@ -0,0 +1,181 @@
+//! Named queries saved per environment
+//!
+//! Stored next to `config.toml` as `saved_queries.toml`. Each query records
+//! the environment URL it was saved in, so the picker only offers queries
+//! that make sense for the current connection.
+
+use crate::config::Config;
+use crate::ui::FilterCondition;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// The query text or guided builder state being saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SavedQueryBody {
+    /// Guided query builder selections
+    Guided {
+        columns: Vec<String>,
+        filters: Vec<FilterCondition>,
+        order_by: Option<String>,
+        order_desc: bool,
+        top: Option<usize>,
+    },
+    /// SQL console text
+    Sql { sql: String },
+    /// FetchXML console text
+    FetchXml { fetch_xml: String },
+}
+
+impl SavedQueryBody {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Guided { .. } => "Guided",
+            Self::Sql { .. } => "SQL",
+            Self::FetchXml { .. } => "FetchXML",
+        }
+    }
+}
+
+/// A named query
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedQuery {
+    pub name: String,
+    /// Environment URL the query belongs to
+    pub environment: String,
+    /// Entity the query was built on, used to reopen the Query tab
+    pub entity: Option<String>,
+    pub query: SavedQueryBody,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedQueryStore {
+    #[serde(default)]
+    pub queries: Vec<SavedQuery>,
+}
+
+impl SavedQueryStore {
+    pub fn load() -> Result<Self> {
+        let path = Self::store_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::store_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn store_path() -> Result<PathBuf> {
+        let mut path = Config::config_dir()?;
+        path.push("saved_queries.toml");
+        Ok(path)
+    }
+
+    /// Indices of the queries saved for an environment, sorted by name
+    pub fn for_environment(&self, environment: &str) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.queries.len())
+            .filter(|&i| self.queries[i].environment == environment)
+            .collect();
+        indices.sort_by_key(|&i| self.queries[i].name.to_lowercase());
+        indices
+    }
+
+    /// Add a query, replacing one with the same name in the same environment
+    pub fn upsert(&mut self, query: SavedQuery) {
+        match self
+            .queries
+            .iter_mut()
+            .find(|q| q.environment == query.environment && q.name == query.name)
+        {
+            Some(existing) => *existing = query,
+            None => self.queries.push(query),
+        }
+    }
+
+    /// Rename a query, replacing any other query in its environment with that name
+    pub fn rename(&mut self, index: usize, name: &str) {
+        let environment = self.queries[index].environment.clone();
+        let duplicate = self
+            .queries
+            .iter()
+            .position(|q| q.environment == environment && q.name == name)
+            .filter(|&other| other != index);
+        let index = match duplicate {
+            Some(other) => {
+                self.queries.remove(other);
+                if other < index { index - 1 } else { index }
+            }
+            None => index,
+        };
+        self.queries[index].name = name.to_string();
+    }
+
+    pub fn remove(&mut self, index: usize) -> SavedQuery {
+        self.queries.remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_round_trip_and_environment_scope() {
+        let query = |name: &str, environment: &str, query: SavedQueryBody| SavedQuery {
+            name: name.to_string(),
+            environment: environment.to_string(),
+            entity: Some("account".to_string()),
+            query,
+        };
+        let mut store = SavedQueryStore::default();
+        store.upsert(query(
+            "Open accounts",
+            "https://a.crm.dynamics.com",
+            SavedQueryBody::Guided {
+                columns: vec!["name".to_string()],
+                filters: vec![FilterCondition {
+                    attribute_name: "statecode".to_string(),
+                    operator: Default::default(),
+                    value: "0".to_string(),
+                }],
+                order_by: Some("name".to_string()),
+                order_desc: false,
+                top: Some(50),
+            },
+        ));
+        store.upsert(query("big", "https://a.crm.dynamics.com", SavedQueryBody::Sql { sql: "SELECT 1".into() }));
+        store.upsert(query("big", "https://b.crm.dynamics.com", SavedQueryBody::Sql { sql: "SELECT 2".into() }));
+        store.upsert(query(
+            "big",
+            "https://a.crm.dynamics.com",
+            SavedQueryBody::FetchXml { fetch_xml: "<fetch>\n  <entity name='account'/>\n</fetch>".into() },
+        ));
+
+        let loaded: SavedQueryStore = toml::from_str(&toml::to_string_pretty(&store).unwrap()).unwrap();
+        assert_eq!(loaded.queries, store.queries);
+
+        let names: Vec<&str> = loaded
+            .for_environment("https://a.crm.dynamics.com")
+            .into_iter()
+            .map(|i| loaded.queries[i].name.as_str())
+            .collect();
+        assert_eq!(names, ["big", "Open accounts"]);
+        assert_eq!(loaded.queries[1].query.label(), "FetchXML");
+
+        store.rename(0, "big");
+        assert_eq!(store.for_environment("https://a.crm.dynamics.com").len(), 1);
+        assert_eq!(store.queries.len(), 2);
+    }
+}
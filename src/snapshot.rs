@@ -0,0 +1,247 @@
+//! Metadata snapshots
+//!
+//! A snapshot is a JSON document with the entities, attributes,
+//! relationships, global option sets and solutions of one environment. With
+//! `--snapshot` the client answers metadata requests from a snapshot instead
+//! of the Web API, so it can be browsed without network access or signing in.
+//!
+//! `rynamo snapshot` writes one. Every list is sorted, so snapshots of an
+//! unchanged environment are identical and can be tracked in git.
+
+use crate::api::{DataverseClient, MAX_CONCURRENT_REQUESTS};
+use crate::metadata_cache::MetadataCache;
+use crate::models::{
+    AttributeMetadata, AttributeOptionSet, EntityMetadata, OptionSetMetadata, RelationshipMetadata, Solution,
+    SolutionComponent,
+};
+use anyhow::{Context, Result, bail};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Format version written to new snapshots
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Metadata of one environment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// URL of the environment the snapshot was taken from
+    pub environment: String,
+    pub entities: Vec<EntitySnapshot>,
+    pub global_option_sets: Vec<OptionSetMetadata>,
+    pub solutions: Vec<SolutionSnapshot>,
+}
+
+/// An entity with its attributes and relationships
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub metadata: EntityMetadata,
+    pub attributes: Vec<AttributeMetadata>,
+    pub one_to_many: Vec<RelationshipMetadata>,
+    pub many_to_one: Vec<RelationshipMetadata>,
+    pub many_to_many: Vec<RelationshipMetadata>,
+    /// Options of the choice, status and state columns
+    #[serde(default)]
+    pub option_sets: Vec<AttributeOptionSet>,
+}
+
+/// A solution with its components
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolutionSnapshot {
+    pub solution: Solution,
+    pub components: Vec<SolutionComponent>,
+}
+
+impl Snapshot {
+    /// Read the metadata of the client's environment
+    pub async fn capture(client: &DataverseClient) -> Result<Self> {
+        let entities = client.get_entities().await.context("Failed to load entities")?;
+        let entities = Self::capture_entities(client, entities).await?;
+
+        let mut global_option_sets = client
+            .get_global_option_sets()
+            .await
+            .context("Failed to load global option sets")?;
+        global_option_sets.sort_by(|a, b| a.name.cmp(&b.name));
+        global_option_sets.iter_mut().for_each(sort_options);
+
+        let solutions = client.get_solutions().await.context("Failed to load solutions")?;
+        let mut solutions: Vec<SolutionSnapshot> = futures::stream::iter(solutions)
+            .map(|solution| async move {
+                let components = client
+                    .get_solution_components(&solution.solution_id)
+                    .await
+                    .with_context(|| format!("Failed to load components of {}", solution.unique_name))?;
+                let components = sorted_by_key(components, |c| {
+                    (c.component_type, c.object_id.clone(), c.solution_component_id.clone())
+                });
+                anyhow::Ok(SolutionSnapshot { solution, components })
+            })
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .try_collect()
+            .await?;
+        solutions.sort_by(|a, b| a.solution.unique_name.cmp(&b.solution.unique_name));
+
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            environment: client.environment_url(),
+            entities,
+            global_option_sets,
+            solutions,
+        })
+    }
+
+    /// Read the attributes, relationships and choice options of entities,
+    /// sorted by logical name
+    pub async fn capture_entities(client: &DataverseClient, entities: Vec<EntityMetadata>) -> Result<Vec<EntitySnapshot>> {
+        let mut entities: Vec<EntitySnapshot> = futures::stream::iter(entities)
+            .map(|metadata| async move {
+                let name = metadata.logical_name.clone();
+                let (attributes, one_to_many, many_to_one, many_to_many) = tokio::try_join!(
+                    client.get_entity_attributes(&name),
+                    client.get_entity_one_to_many(&name),
+                    client.get_entity_many_to_one(&name),
+                    client.get_entity_many_to_many(&name),
+                )
+                .with_context(|| format!("Failed to load metadata of {}", name))?;
+                anyhow::Ok(EntitySnapshot {
+                    metadata,
+                    attributes: sorted_by_key(attributes, |a| a.logical_name.clone()),
+                    one_to_many: sorted_by_key(one_to_many, |r| r.schema_name.clone()),
+                    many_to_one: sorted_by_key(many_to_one, |r| r.schema_name.clone()),
+                    many_to_many: sorted_by_key(many_to_many, |r| r.schema_name.clone()),
+                    option_sets: Vec::new(),
+                })
+            })
+            .buffer_unordered(MAX_CONCURRENT_REQUESTS)
+            .try_collect()
+            .await?;
+        entities.sort_by(|a, b| a.metadata.logical_name.cmp(&b.metadata.logical_name));
+
+        let names: Vec<String> = entities.iter().map(|e| e.metadata.logical_name.clone()).collect();
+        let (choice_options, limits) = tokio::try_join!(
+            async { client.get_choice_options(&names).await.context("Failed to load choice column options") },
+            async { client.get_attribute_limits(&names).await.context("Failed to load column lengths and ranges") },
+        )?;
+        for ((entity, option_sets), limits) in entities.iter_mut().zip(choice_options).zip(limits) {
+            entity.option_sets = sorted_by_key(option_sets, |o| o.logical_name.clone());
+            for option_set in entity.option_sets.iter_mut().filter_map(|o| o.option_set.as_mut()) {
+                sort_options(option_set);
+            }
+            for limit in limits {
+                if let Some(attribute) = entity.attributes.iter_mut().find(|a| a.logical_name == limit.logical_name) {
+                    attribute.max_length = limit.max_length;
+                    attribute.min_value = limit.min_value;
+                    attribute.max_value = limit.max_value;
+                }
+            }
+        }
+        Ok(entities)
+    }
+
+    /// Write the snapshot as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut content = serde_json::to_string_pretty(self)?;
+        content.push('\n');
+        fs::write(path, content).with_context(|| format!("Failed to write snapshot {}", path.display()))
+    }
+
+    /// Read a snapshot file, rejecting versions newer than this build knows
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("Failed to read snapshot {}", path.display()))?;
+        let snapshot: Snapshot =
+            serde_json::from_str(&content).with_context(|| format!("Invalid snapshot {}", path.display()))?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            bail!(
+                "Snapshot version {} is newer than the supported version {}",
+                snapshot.version,
+                SNAPSHOT_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+
+    /// An entity by logical name
+    pub fn entity(&self, logical_name: &str) -> Result<&EntitySnapshot> {
+        self.entities
+            .iter()
+            .find(|e| e.metadata.logical_name == logical_name)
+            .with_context(|| format!("Entity '{}' is not in the snapshot", logical_name))
+    }
+
+    /// Components of a solution, empty if the solution is unknown
+    pub fn solution_components(&self, solution_id: &str) -> Vec<SolutionComponent> {
+        self.solutions
+            .iter()
+            .find(|s| s.solution.solution_id == solution_id)
+            .map(|s| s.components.clone())
+            .unwrap_or_default()
+    }
+
+    /// Entities and attributes in the shape of the metadata cache
+    pub fn metadata_cache(&self) -> MetadataCache {
+        let mut entities: Vec<EntityMetadata> = self.entities.iter().map(|e| e.metadata.clone()).collect();
+        entities.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        MetadataCache {
+            version_stamp: None,
+            entities,
+            attributes: self
+                .entities
+                .iter()
+                .map(|e| (e.metadata.logical_name.clone(), e.attributes.clone()))
+                .collect(),
+        }
+    }
+}
+
+fn sort_options(option_set: &mut OptionSetMetadata) {
+    if let Some(options) = &mut option_set.options {
+        options.sort_by_key(|o| o.value);
+    }
+}
+
+fn sorted_by_key<T, K: Ord>(mut items: Vec<T>, key: impl FnMut(&T) -> K) -> Vec<T> {
+    items.sort_by_key(key);
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_load_checks_version() {
+        let mut document = json!({
+            "version": 1,
+            "environment": "https://org.crm.dynamics.com",
+            "entities": [{
+                "metadata": { "MetadataId": "e1", "LogicalName": "account" },
+                "attributes": [{ "MetadataId": "a1", "LogicalName": "name" }],
+                "one_to_many": [],
+                "many_to_one": [],
+                "many_to_many": [],
+            }],
+            "global_option_sets": [],
+            "solutions": [{
+                "solution": { "solutionid": "s1", "uniquename": "core" },
+                "components": [{ "solutioncomponentid": "c1", "componenttype": 1, "objectid": "e1" }],
+            }],
+        });
+        let path = std::env::temp_dir().join(format!("rynamo-snapshot-{}.json", std::process::id()));
+        fs::write(&path, document.to_string()).unwrap();
+        let snapshot = Snapshot::load(&path).unwrap();
+        assert_eq!(snapshot.entity("account").unwrap().attributes[0].logical_name, "name");
+        assert!(snapshot.entity("contact").is_err());
+        assert_eq!(snapshot.solution_components("s1").len(), 1);
+        assert_eq!(snapshot.metadata_cache().attributes["account"].len(), 1);
+
+        document["version"] = json!(SNAPSHOT_VERSION + 1);
+        fs::write(&path, document.to_string()).unwrap();
+        assert!(Snapshot::load(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
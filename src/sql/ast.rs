@@ -0,0 +1,234 @@
+//! SQL syntax tree
+
+/// A parsed `SELECT` statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectStatement {
+    /// `SELECT TOP n`
+    pub top: Option<u64>,
+    /// Projection list
+    pub columns: Vec<SelectItem>,
+    /// `FROM` table
+    pub from: TableRef,
+    /// `JOIN` clauses in the order written
+    pub joins: Vec<Join>,
+    /// `WHERE` condition
+    pub where_clause: Option<Expr>,
+    /// `GROUP BY` items
+    pub group_by: Vec<GroupByItem>,
+    /// `ORDER BY` items
+    pub order_by: Vec<OrderByItem>,
+    /// `LIMIT n`
+    pub limit: Option<u64>,
+}
+
+/// An item in the projection list
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*` or `alias.*`
+    Wildcard { qualifier: Option<String>, position: usize },
+    /// A column with an optional `AS alias`
+    Column { column: ColumnRef, alias: Option<String> },
+    /// `COUNT(*)`, `SUM(col)`, `COUNT(DISTINCT col)`, ...
+    Aggregate {
+        function: AggregateFn,
+        /// `None` for `COUNT(*)`
+        column: Option<ColumnRef>,
+        distinct: bool,
+        alias: Option<String>,
+        position: usize,
+    },
+    /// A date part such as `YEAR(createdon)`, used together with `GROUP BY`
+    DateGroup { grouping: DateGrouping, column: ColumnRef, alias: Option<String> },
+}
+
+impl SelectStatement {
+    /// Whether the statement groups rows and must be run as an aggregate query
+    pub fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty()
+            || self
+                .columns
+                .iter()
+                .any(|c| matches!(c, SelectItem::Aggregate { .. } | SelectItem::DateGroup { .. }))
+    }
+}
+
+/// Aggregate function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl AggregateFn {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "COUNT" => Some(Self::Count),
+            "SUM" => Some(Self::Sum),
+            "AVG" => Some(Self::Avg),
+            "MIN" => Some(Self::Min),
+            "MAX" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    /// Lower-case name, as used by FetchXML `aggregate`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+/// Date part used for grouping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateGrouping {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl DateGrouping {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "DAY" => Some(Self::Day),
+            "WEEK" => Some(Self::Week),
+            "MONTH" => Some(Self::Month),
+            "QUARTER" => Some(Self::Quarter),
+            "YEAR" => Some(Self::Year),
+            _ => None,
+        }
+    }
+
+    /// Lower-case name, as used by FetchXML `dategrouping`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Quarter => "quarter",
+            Self::Year => "year",
+        }
+    }
+}
+
+/// A `GROUP BY` item, optionally grouped by a date part
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupByItem {
+    pub column: ColumnRef,
+    pub grouping: Option<DateGrouping>,
+}
+
+/// A (possibly qualified) column reference such as `name` or `a.name`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnRef {
+    pub qualifier: Option<String>,
+    pub name: String,
+    pub position: usize,
+}
+
+/// A table in the `FROM` clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+    pub name: String,
+    pub alias: Option<String>,
+    pub position: usize,
+}
+
+impl TableRef {
+    /// Check whether a column qualifier refers to this table
+    pub fn matches_qualifier(&self, qualifier: &str) -> bool {
+        match &self.alias {
+            Some(alias) => alias.eq_ignore_ascii_case(qualifier),
+            None => self.name.eq_ignore_ascii_case(qualifier),
+        }
+    }
+}
+
+/// Kind of join
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+}
+
+/// `[INNER | LEFT [OUTER]] JOIN table [alias] ON a.x = b.y`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub kind: JoinKind,
+    pub table: TableRef,
+    pub left: ColumnRef,
+    pub right: ColumnRef,
+}
+
+/// An `ORDER BY` item
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByItem {
+    pub column: ColumnRef,
+    pub descending: bool,
+}
+
+/// Boolean expression in a `WHERE` clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// `column <op> value`
+    Compare { column: ColumnRef, op: CompareOp, value: Value },
+    /// `column [NOT] LIKE 'pattern'`
+    Like { column: ColumnRef, pattern: Value, negated: bool },
+    /// `column [NOT] IN (v1, v2, ...)`
+    In { column: ColumnRef, values: Vec<Value>, negated: bool },
+    /// `column IS [NOT] NULL`
+    IsNull { column: ColumnRef, negated: bool },
+}
+
+/// Comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl CompareOp {
+    /// OData operator keyword
+    pub fn odata(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::NotEq => "ne",
+            Self::Lt => "lt",
+            Self::LtEq => "le",
+            Self::Gt => "gt",
+            Self::GtEq => "ge",
+        }
+    }
+}
+
+/// Literal value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    String(String),
+    Number(String),
+    Boolean(bool),
+    Null,
+}
+
+/// A literal together with its position in the source text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Value {
+    pub literal: Literal,
+    pub position: usize,
+}
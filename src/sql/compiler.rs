@@ -0,0 +1,712 @@
+//! Compile a validated SQL statement into an OData or FetchXML query
+
+use super::ast::{ColumnRef, CompareOp, Expr, JoinKind, Literal, SelectItem, SelectStatement, TableRef, Value};
+use super::fetchxml;
+use super::SqlError;
+use crate::models::query::QueryDefinition;
+use crate::models::{AttributeMetadata, EntityMetadata, RelationshipMetadata};
+use std::collections::HashMap;
+
+/// Metadata the compiler validates against
+pub struct Schema<'a> {
+    /// All entities in the environment
+    pub entities: &'a [EntityMetadata],
+    /// Attributes keyed by entity logical name
+    pub attributes: &'a HashMap<String, Vec<AttributeMetadata>>,
+    /// Many-to-one and one-to-many relationships keyed by entity logical name
+    pub relationships: &'a HashMap<String, Vec<RelationshipMetadata>>,
+}
+
+/// A compiled query ready to send to Dataverse
+#[derive(Debug, Clone)]
+pub enum CompiledQuery {
+    /// Single-entity query sent as an OData URL
+    OData(QueryDefinition),
+    /// Query with joins or aggregates, sent as FetchXML
+    FetchXml { entity_set_name: String, fetch_xml: String },
+}
+
+/// Find an entity by logical, entity set or display name
+fn resolve_table<'a>(table: &TableRef, entities: &'a [EntityMetadata]) -> Result<&'a EntityMetadata, SqlError> {
+    let name = table.name.to_lowercase();
+    entities
+        .iter()
+        .find(|e| e.logical_name == name)
+        .or_else(|| {
+            entities.iter().find(|e| {
+                e.entity_set_name
+                    .as_deref()
+                    .is_some_and(|s| s.eq_ignore_ascii_case(&name))
+            })
+        })
+        .or_else(|| entities.iter().find(|e| e.get_display_name().to_lowercase() == name))
+        .ok_or_else(|| SqlError::new(format!("Unknown entity '{}'", table.name), table.position))
+}
+
+/// Resolve the `FROM` entity followed by every joined entity
+pub fn resolve_entities<'a>(
+    stmt: &SelectStatement,
+    entities: &'a [EntityMetadata],
+) -> Result<Vec<&'a EntityMetadata>, SqlError> {
+    std::iter::once(&stmt.from)
+        .chain(stmt.joins.iter().map(|j| &j.table))
+        .map(|table| resolve_table(table, entities))
+        .collect()
+}
+
+/// Compile a parsed statement into a query
+pub fn compile(stmt: &SelectStatement, schema: &Schema) -> Result<CompiledQuery, SqlError> {
+    let compiler = Compiler::new(stmt, schema)?;
+    if stmt.joins.is_empty() && !stmt.is_aggregate() {
+        compiler.odata().map(CompiledQuery::OData)
+    } else {
+        let entity_set_name = compiler.entity_set_name()?;
+        let fetch_xml = fetchxml::build(&compiler)?;
+        Ok(CompiledQuery::FetchXml { entity_set_name, fetch_xml })
+    }
+}
+
+/// Attribute type name without the `Type` suffix used by `AttributeTypeName`
+pub(super) fn base_type(attr: &AttributeMetadata) -> String {
+    let name = attr.get_type_name();
+    name.strip_suffix("Type").map(str::to_string).unwrap_or(name)
+}
+
+/// Name of the attribute as exposed by the Web API
+fn odata_name(attr: &AttributeMetadata) -> String {
+    match base_type(attr).as_str() {
+        "Lookup" | "Customer" | "Owner" => format!("_{}_value", attr.logical_name),
+        _ => attr.logical_name.clone(),
+    }
+}
+
+/// A literal checked against an attribute's type
+pub(super) enum TypedValue {
+    /// Text that must be quoted in OData
+    Text(String),
+    /// Numbers, dates and GUIDs written unquoted
+    Raw(String),
+    Bool(bool),
+}
+
+/// An entity taking part in the query
+pub(super) struct Table<'a> {
+    pub table: &'a TableRef,
+    pub entity: &'a EntityMetadata,
+    pub attributes: &'a [AttributeMetadata],
+    /// Index of the table this one is joined to, `None` for the `FROM` table
+    pub parent: Option<usize>,
+    /// Join column on this table
+    pub from_attribute: String,
+    /// Join column on the parent table
+    pub to_attribute: String,
+    pub kind: JoinKind,
+}
+
+impl Table<'_> {
+    /// Name used to qualify this table's columns
+    pub fn qualifier(&self) -> &str {
+        self.table.alias.as_deref().unwrap_or(&self.table.name)
+    }
+}
+
+pub(super) struct Compiler<'a> {
+    pub stmt: &'a SelectStatement,
+    pub tables: Vec<Table<'a>>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(stmt: &'a SelectStatement, schema: &Schema<'a>) -> Result<Self, SqlError> {
+        let attributes_for = |entity: &EntityMetadata, table: &TableRef| {
+            schema
+                .attributes
+                .get(&entity.logical_name)
+                .map(|a| a.as_slice())
+                .ok_or_else(|| {
+                    SqlError::new(
+                        format!("Attribute metadata for '{}' is not loaded", entity.logical_name),
+                        table.position,
+                    )
+                })
+        };
+
+        let root = resolve_table(&stmt.from, schema.entities)?;
+        let mut compiler = Compiler {
+            stmt,
+            tables: vec![Table {
+                table: &stmt.from,
+                entity: root,
+                attributes: attributes_for(root, &stmt.from)?,
+                parent: None,
+                from_attribute: String::new(),
+                to_attribute: String::new(),
+                kind: JoinKind::Inner,
+            }],
+        };
+
+        for join in &stmt.joins {
+            let entity = resolve_table(&join.table, schema.entities)?;
+            let qualifier = join.table.alias.as_deref().unwrap_or(&join.table.name);
+            if compiler.tables.iter().any(|t| t.qualifier().eq_ignore_ascii_case(qualifier)) {
+                return Err(SqlError::new(
+                    format!("'{}' is already used, give the joined table an alias", qualifier),
+                    join.table.position,
+                ));
+            }
+            let index = compiler.tables.len();
+            compiler.tables.push(Table {
+                table: &join.table,
+                entity,
+                attributes: attributes_for(entity, &join.table)?,
+                parent: None,
+                from_attribute: String::new(),
+                to_attribute: String::new(),
+                kind: join.kind,
+            });
+
+            // One side of the ON condition must be the new table, the other an earlier one
+            let (left_table, left_attr) = compiler.qualified_column(&join.left)?;
+            let (right_table, right_attr) = compiler.qualified_column(&join.right)?;
+            let (parent, parent_attr, own_attr) = if right_table == index && left_table < index {
+                (left_table, left_attr, right_attr)
+            } else if left_table == index && right_table < index {
+                (right_table, right_attr, left_attr)
+            } else {
+                return Err(SqlError::new(
+                    format!("The ON condition must compare a column of '{}' with an earlier table", qualifier),
+                    join.left.position,
+                ));
+            };
+
+            let parent_entity = &compiler.tables[parent].entity.logical_name;
+            let relates = |referencing: &str, referencing_attr: &str, referenced: &str, referenced_attr: &str| {
+                schema
+                    .relationships
+                    .get(parent_entity)
+                    .into_iter()
+                    .chain(schema.relationships.get(&entity.logical_name))
+                    .flatten()
+                    .any(|r| {
+                        r.referencing_entity.as_deref() == Some(referencing)
+                            && r.referencing_attribute.as_deref() == Some(referencing_attr)
+                            && r.referenced_entity.as_deref() == Some(referenced)
+                            && r.referenced_attribute.as_deref() == Some(referenced_attr)
+                    })
+            };
+            let parent_attr = parent_attr.logical_name.clone();
+            let own_attr = own_attr.logical_name.clone();
+            if !relates(parent_entity, &parent_attr, &entity.logical_name, &own_attr)
+                && !relates(&entity.logical_name, &own_attr, parent_entity, &parent_attr)
+            {
+                return Err(SqlError::new(
+                    format!(
+                        "No relationship joins {}.{} to {}.{}",
+                        parent_entity, parent_attr, entity.logical_name, own_attr
+                    ),
+                    join.left.position,
+                ));
+            }
+
+            let table = &mut compiler.tables[index];
+            table.parent = Some(parent);
+            table.from_attribute = own_attr;
+            table.to_attribute = parent_attr;
+        }
+
+        Ok(compiler)
+    }
+
+    fn entity_set_name(&self) -> Result<String, SqlError> {
+        let root = &self.tables[0];
+        root.entity.entity_set_name.clone().ok_or_else(|| {
+            SqlError::new(
+                format!("Entity '{}' cannot be queried through the Web API", root.entity.logical_name),
+                root.table.position,
+            )
+        })
+    }
+
+    /// Build the OData query for a statement without joins
+    fn odata(&self) -> Result<QueryDefinition, SqlError> {
+        let mut select = Vec::new();
+        let mut wildcard = false;
+        for item in &self.stmt.columns {
+            match item {
+                SelectItem::Wildcard { qualifier, position } => {
+                    if let Some(q) = qualifier {
+                        self.table_index(q, *position)?;
+                    }
+                    wildcard = true;
+                }
+                SelectItem::Column { column, alias } => {
+                    if alias.is_some() {
+                        return Err(SqlError::new(
+                            "Column aliases are not supported by the Web API",
+                            column.position,
+                        ));
+                    }
+                    let name = odata_name(self.column(column)?.1);
+                    if !select.contains(&name) {
+                        select.push(name);
+                    }
+                }
+                SelectItem::Aggregate { position, .. } => {
+                    return Err(SqlError::new("Aggregates are not supported by the Web API", *position));
+                }
+                SelectItem::DateGroup { column, .. } => {
+                    return Err(SqlError::new("Date grouping is not supported by the Web API", column.position));
+                }
+            }
+        }
+        if wildcard {
+            select.clear();
+        }
+
+        let filter = match &self.stmt.where_clause {
+            Some(expr) => self.odata_expr(expr)?,
+            None => String::new(),
+        };
+
+        let order_by = self
+            .stmt
+            .order_by
+            .iter()
+            .map(|item| {
+                let attr = self.column(&item.column)?.1;
+                let dir = if item.descending { " desc" } else { "" };
+                Ok(format!("{}{}", odata_name(attr), dir))
+            })
+            .collect::<Result<Vec<_>, SqlError>>()?
+            .join(",");
+
+        Ok(QueryDefinition {
+            entity_set_name: self.entity_set_name()?,
+            select,
+            filter,
+            order_by,
+            top: self.stmt.top.or(self.stmt.limit).map(|n| n as usize),
+            skip: None,
+        })
+    }
+
+    /// Find the table a qualifier refers to
+    pub fn table_index(&self, qualifier: &str, position: usize) -> Result<usize, SqlError> {
+        self.tables
+            .iter()
+            .position(|t| t.table.matches_qualifier(qualifier))
+            .ok_or_else(|| SqlError::new(format!("Unknown table or alias '{}'", qualifier), position))
+    }
+
+    fn find_attribute(&self, table: usize, name: &str) -> Option<&'a AttributeMetadata> {
+        self.tables[table]
+            .attributes
+            .iter()
+            .find(|a| a.logical_name.eq_ignore_ascii_case(name))
+    }
+
+    /// Resolve a column that must carry a table qualifier
+    fn qualified_column(&self, column: &ColumnRef) -> Result<(usize, &'a AttributeMetadata), SqlError> {
+        if column.qualifier.is_none() {
+            return Err(SqlError::new(
+                format!("Qualify '{}' with a table name or alias", column.name),
+                column.position,
+            ));
+        }
+        self.column(column)
+    }
+
+    /// Resolve and validate a column reference, returning its table index
+    pub fn column(&self, column: &ColumnRef) -> Result<(usize, &'a AttributeMetadata), SqlError> {
+        let (table, attr) = match &column.qualifier {
+            Some(q) => {
+                let table = self.table_index(q, column.position)?;
+                let attr = self.find_attribute(table, &column.name).ok_or_else(|| {
+                    SqlError::new(
+                        format!("Unknown column '{}' on '{}'", column.name, self.tables[table].table.name),
+                        column.position,
+                    )
+                })?;
+                (table, attr)
+            }
+            None => {
+                let mut matches = (0..self.tables.len())
+                    .filter_map(|t| self.find_attribute(t, &column.name).map(|a| (t, a)));
+                let first = matches.next().ok_or_else(|| {
+                    SqlError::new(
+                        format!("Unknown column '{}' on '{}'", column.name, self.stmt.from.name),
+                        column.position,
+                    )
+                })?;
+                if matches.next().is_some() {
+                    return Err(SqlError::new(
+                        format!("Column '{}' is ambiguous, qualify it with a table alias", column.name),
+                        column.position,
+                    ));
+                }
+                first
+            }
+        };
+        if base_type(attr) == "Virtual" {
+            return Err(SqlError::new(
+                format!("Column '{}' is virtual and cannot be queried", attr.logical_name),
+                column.position,
+            ));
+        }
+        Ok((table, attr))
+    }
+
+    fn odata_expr(&self, expr: &Expr) -> Result<String, SqlError> {
+        match expr {
+            Expr::And(left, right) => Ok(format!("({} and {})", self.odata_expr(left)?, self.odata_expr(right)?)),
+            Expr::Or(left, right) => Ok(format!("({} or {})", self.odata_expr(left)?, self.odata_expr(right)?)),
+            Expr::Not(inner) => Ok(format!("not ({})", self.odata_expr(inner)?)),
+            Expr::Compare { column, op, value } => {
+                let attr = self.column(column)?.1;
+                Ok(format!("{} {} {}", odata_name(attr), op.odata(), odata_literal(self.value(attr, value)?)))
+            }
+            Expr::IsNull { column, negated } => {
+                let attr = self.column(column)?.1;
+                let op = if *negated { "ne" } else { "eq" };
+                Ok(format!("{} {} null", odata_name(attr), op))
+            }
+            Expr::In { column, values, negated } => {
+                let attr = self.column(column)?.1;
+                let (op, join) = if *negated { (CompareOp::NotEq, " and ") } else { (CompareOp::Eq, " or ") };
+                let parts = values
+                    .iter()
+                    .map(|v| {
+                        Ok(format!("{} {} {}", odata_name(attr), op.odata(), odata_literal(self.value(attr, v)?)))
+                    })
+                    .collect::<Result<Vec<_>, SqlError>>()?;
+                Ok(format!("({})", parts.join(join)))
+            }
+            Expr::Like { column, pattern, negated } => {
+                let attr = self.column(column)?.1;
+                let filter = self.odata_like(attr, pattern)?;
+                Ok(if *negated { format!("not {}", filter) } else { filter })
+            }
+        }
+    }
+
+    /// Check that a LIKE pattern is a string on a text column
+    pub fn like_pattern<'v>(&self, attr: &AttributeMetadata, pattern: &'v Value) -> Result<&'v str, SqlError> {
+        let Literal::String(text) = &pattern.literal else {
+            return Err(SqlError::new("LIKE requires a string pattern", pattern.position));
+        };
+        if !matches!(base_type(attr).as_str(), "String" | "Memo") {
+            return Err(SqlError::new(
+                format!("LIKE can only be used on text columns, '{}' is {}", attr.logical_name, attr.get_type_name()),
+                pattern.position,
+            ));
+        }
+        Ok(text)
+    }
+
+    /// Translate a LIKE pattern into startswith/endswith/contains
+    fn odata_like(&self, attr: &AttributeMetadata, pattern: &Value) -> Result<String, SqlError> {
+        let text = self.like_pattern(attr, pattern)?;
+        let leading = text.starts_with('%');
+        let rest = text.strip_prefix('%').unwrap_or(text);
+        let trailing = !rest.is_empty() && rest.ends_with('%');
+        let inner = if trailing { &rest[..rest.len() - 1] } else { rest };
+        if inner.contains('%') || inner.contains('_') {
+            return Err(SqlError::new(
+                "Only leading and trailing % wildcards are supported in LIKE",
+                pattern.position,
+            ));
+        }
+
+        let name = odata_name(attr);
+        let quoted = quote(inner);
+        Ok(match (leading, trailing) {
+            (true, true) => format!("contains({},{})", name, quoted),
+            (false, true) => format!("startswith({},{})", name, quoted),
+            (true, false) => format!("endswith({},{})", name, quoted),
+            (false, false) => format!("{} eq {}", name, quoted),
+        })
+    }
+
+    /// Check a literal against the attribute's type
+    pub fn value(&self, attr: &AttributeMetadata, value: &Value) -> Result<TypedValue, SqlError> {
+        let type_name = base_type(attr);
+        let mismatch = |expected: &str| {
+            SqlError::new(
+                format!("Column '{}' is {}, expected {}", attr.logical_name, attr.get_type_name(), expected),
+                value.position,
+            )
+        };
+
+        match type_name.as_str() {
+            "Integer" | "BigInt" | "Picklist" | "State" | "Status" => {
+                let valid = |n: &str| match type_name.as_str() {
+                    "BigInt" => n.parse::<i64>().is_ok(),
+                    _ => n.parse::<i32>().is_ok(),
+                };
+                match &value.literal {
+                    Literal::Number(n) if valid(n) => Ok(TypedValue::Raw(n.clone())),
+                    Literal::String(s) if valid(s.trim()) => Ok(TypedValue::Raw(s.trim().to_string())),
+                    _ => Err(mismatch("a whole number")),
+                }
+            }
+            "Decimal" | "Double" | "Money" => match &value.literal {
+                Literal::Number(n) if is_plain_decimal(n) => Ok(TypedValue::Raw(n.clone())),
+                Literal::String(s) if is_plain_decimal(s.trim()) => Ok(TypedValue::Raw(s.trim().to_string())),
+                _ => Err(mismatch("a number")),
+            },
+            "Boolean" => match &value.literal {
+                Literal::Boolean(b) => Ok(TypedValue::Bool(*b)),
+                Literal::Number(n) if n == "0" || n == "1" => Ok(TypedValue::Bool(n == "1")),
+                _ => Err(mismatch("TRUE or FALSE")),
+            },
+            "DateTime" => match &value.literal {
+                Literal::String(s) if is_iso_date(s) => Ok(TypedValue::Raw(s.clone())),
+                _ => Err(mismatch("a date such as '2024-01-31'")),
+            },
+            "Uniqueidentifier" | "Lookup" | "Customer" | "Owner" => match &value.literal {
+                Literal::String(s)
+                    if !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit() || c == '-') =>
+                {
+                    Ok(TypedValue::Raw(s.clone()))
+                }
+                _ => Err(mismatch("a GUID")),
+            },
+            _ => match &value.literal {
+                Literal::String(s) => Ok(TypedValue::Text(s.clone())),
+                Literal::Number(n) => Ok(TypedValue::Text(n.clone())),
+                _ => Err(mismatch("a string")),
+            },
+        }
+    }
+}
+
+fn odata_literal(value: TypedValue) -> String {
+    match value {
+        TypedValue::Text(s) => quote(&s),
+        TypedValue::Raw(s) => urlencoding::encode(&s).into_owned(),
+        TypedValue::Bool(b) => b.to_string(),
+    }
+}
+
+/// Whether a string is a finite number written with digits and at most one
+/// decimal point, so `inf`, `NaN` and exponents are rejected
+fn is_plain_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    digits.chars().any(|c| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.matches('.').count() <= 1
+        && s.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
+/// Whether a string is an ISO-8601 date or date-time
+fn is_iso_date(s: &str) -> bool {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()
+        || DateTime::parse_from_rfc3339(s).is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        || NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M").is_ok()
+}
+
+/// Quote and escape an OData string literal for use in a URL
+fn quote(s: &str) -> String {
+    format!("'{}'", urlencoding::encode(&s.replace('\'', "''")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse;
+    use serde_json::json;
+
+    struct TestSchema {
+        entities: Vec<EntityMetadata>,
+        attributes: HashMap<String, Vec<AttributeMetadata>>,
+        relationships: HashMap<String, Vec<RelationshipMetadata>>,
+    }
+
+    fn test_schema() -> TestSchema {
+        let entity = |name: &str, set: &str| -> EntityMetadata {
+            serde_json::from_value(json!({
+                "MetadataId": name,
+                "LogicalName": name,
+                "EntitySetName": set
+            }))
+            .unwrap()
+        };
+        let attr = |name: &str, type_name: &str| -> AttributeMetadata {
+            serde_json::from_value(json!({
+                "MetadataId": name,
+                "LogicalName": name,
+                "AttributeTypeName": { "Value": type_name }
+            }))
+            .unwrap()
+        };
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "account".to_string(),
+            vec![
+                attr("accountid", "UniqueidentifierType"),
+                attr("name", "StringType"),
+                attr("revenue", "MoneyType"),
+                attr("statecode", "StateType"),
+                attr("parentaccountid", "LookupType"),
+                attr("ownerid", "OwnerType"),
+                attr("createdon", "DateTimeType"),
+            ],
+        );
+        attributes.insert(
+            "contact".to_string(),
+            vec![
+                attr("contactid", "UniqueidentifierType"),
+                attr("fullname", "StringType"),
+                attr("parentcustomerid", "CustomerType"),
+            ],
+        );
+        let relationship: RelationshipMetadata = serde_json::from_value(json!({
+            "SchemaName": "contact_customer_accounts",
+            "ReferencingEntity": "contact",
+            "ReferencingAttribute": "parentcustomerid",
+            "ReferencedEntity": "account",
+            "ReferencedAttribute": "accountid"
+        }))
+        .unwrap();
+        let mut relationships = HashMap::new();
+        relationships.insert("account".to_string(), vec![relationship]);
+
+        TestSchema {
+            entities: vec![entity("account", "accounts"), entity("contact", "contacts")],
+            attributes,
+            relationships,
+        }
+    }
+
+    fn compile_sql(sql: &str) -> Result<CompiledQuery, SqlError> {
+        let data = test_schema();
+        let schema = Schema {
+            entities: &data.entities,
+            attributes: &data.attributes,
+            relationships: &data.relationships,
+        };
+        compile(&parse(sql)?, &schema)
+    }
+
+    #[test]
+    fn test_compile_to_odata_url() {
+        let query = compile_sql(
+            "SELECT TOP 10 a.name, parentaccountid FROM account a \
+             WHERE (name = 'Contoso Ltd' OR name LIKE 'abc%') AND statecode IN (0, 1) AND parentaccountid IS NULL \
+             ORDER BY revenue DESC",
+        )
+        .unwrap();
+        let CompiledQuery::OData(query) = query else {
+            panic!("expected an OData query");
+        };
+
+        assert_eq!(
+            query.build_url(),
+            "accounts?$select=name,_parentaccountid_value\
+             &$filter=(((name eq 'Contoso%20Ltd' or startswith(name,'abc')) and (statecode eq 0 or statecode eq 1)) and _parentaccountid_value eq null)\
+             &$orderby=revenue desc&$top=10"
+        );
+    }
+
+    #[test]
+    fn test_compile_validates_columns_and_types() {
+        let err = compile_sql("SELECT nmae FROM account").unwrap_err();
+        assert_eq!(err.position, 7);
+
+        let err = compile_sql("SELECT name FROM account WHERE revenue = 'lots'").unwrap_err();
+        assert_eq!(err.position, 41);
+
+        let err = compile_sql("SELECT name FROM lead").unwrap_err();
+        assert_eq!(err.position, 17);
+
+        for (sql, position) in [
+            ("SELECT name FROM account WHERE revenue = 'inf'", 41),
+            ("SELECT name FROM account WHERE revenue = 'NaN'", 41),
+            ("SELECT name FROM account WHERE revenue = '1e400'", 41),
+            ("SELECT name FROM account WHERE statecode = 1.5", 43),
+            ("SELECT name FROM account WHERE statecode = '3000000000'", 43),
+        ] {
+            assert_eq!(compile_sql(sql).unwrap_err().position, position, "{}", sql);
+        }
+        assert!(compile_sql("SELECT name FROM account WHERE revenue = '-12.50' AND statecode = '1'").is_ok());
+    }
+
+    #[test]
+    fn test_compile_dates() {
+        let query = compile_sql("SELECT name FROM account WHERE createdon >= '2024-01-31T00:00:00+01:00'").unwrap();
+        let CompiledQuery::OData(query) = query else {
+            panic!("expected an OData query");
+        };
+        assert_eq!(
+            query.build_url(),
+            "accounts?$select=name&$filter=createdon ge 2024-01-31T00%3A00%3A00%2B01%3A00"
+        );
+
+        let err = compile_sql("SELECT name FROM account WHERE createdon = '2024 or statecode eq 1'").unwrap_err();
+        assert_eq!(err.position, 43);
+    }
+
+    #[test]
+    fn test_compile_join_to_link_entity() {
+        let query = compile_sql(
+            "SELECT a.name, c.fullname FROM account a \
+             LEFT JOIN contact c ON c.parentcustomerid = a.accountid \
+             WHERE NOT (c.fullname LIKE 'J%' OR a.statecode = 1)",
+        )
+        .unwrap();
+        let CompiledQuery::FetchXml { entity_set_name, fetch_xml } = query else {
+            panic!("expected a FetchXML query");
+        };
+
+        assert_eq!(entity_set_name, "accounts");
+        assert_eq!(
+            fetch_xml,
+            "<fetch><entity name=\"account\">\
+             <attribute name=\"name\"/>\
+             <filter type=\"and\">\
+             <condition entityname=\"c\" attribute=\"fullname\" operator=\"not-like\" value=\"J%\"/>\
+             <condition attribute=\"statecode\" operator=\"ne\" value=\"1\"/>\
+             </filter>\
+             <link-entity name=\"contact\" from=\"parentcustomerid\" to=\"accountid\" alias=\"c\" link-type=\"outer\">\
+             <attribute name=\"fullname\"/>\
+             </link-entity>\
+             </entity></fetch>"
+        );
+    }
+
+    #[test]
+    fn test_compile_aggregate() {
+        let query = compile_sql(
+            "SELECT ownerid, YEAR(createdon) AS yr, COUNT(*) total, SUM(revenue) FROM account \
+             WHERE statecode = 0 GROUP BY ownerid, YEAR(createdon) ORDER BY total DESC",
+        )
+        .unwrap();
+        let CompiledQuery::FetchXml { fetch_xml, .. } = query else {
+            panic!("expected a FetchXML query");
+        };
+
+        assert_eq!(
+            fetch_xml,
+            "<fetch aggregate=\"true\"><entity name=\"account\">\
+             <attribute name=\"ownerid\" alias=\"ownerid\" groupby=\"true\"/>\
+             <attribute name=\"createdon\" alias=\"yr\" groupby=\"true\" dategrouping=\"year\"/>\
+             <attribute name=\"accountid\" alias=\"total\" aggregate=\"count\"/>\
+             <attribute name=\"revenue\" alias=\"sum_revenue\" aggregate=\"sum\"/>\
+             <order alias=\"total\" descending=\"true\"/>\
+             <filter type=\"and\"><condition attribute=\"statecode\" operator=\"eq\" value=\"0\"/></filter>\
+             </entity></fetch>"
+        );
+
+        let err = compile_sql("SELECT name, COUNT(*) FROM account GROUP BY ownerid").unwrap_err();
+        assert_eq!(err.position, 7);
+    }
+
+    #[test]
+    fn test_join_requires_relationship() {
+        let err = compile_sql("SELECT a.name FROM account a JOIN contact c ON c.fullname = a.name").unwrap_err();
+        assert!(err.message.starts_with("No relationship"));
+        assert_eq!(err.position, 47);
+    }
+}
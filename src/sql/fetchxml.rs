@@ -0,0 +1,403 @@
+//! Emit FetchXML for statements the Web API can't express as OData
+
+use super::ast::{AggregateFn, CompareOp, DateGrouping, Expr, JoinKind, SelectItem};
+use super::compiler::{base_type, Compiler, TypedValue};
+use super::SqlError;
+
+/// Columns and sort orders collected for one `<entity>` or `<link-entity>`
+#[derive(Default)]
+struct TableElements {
+    all_attributes: bool,
+    /// Rendered `<attribute>` elements
+    attributes: Vec<String>,
+    orders: Vec<String>,
+}
+
+/// A `GROUP BY` column and the alias its values are returned under
+struct Group {
+    table: usize,
+    attribute: String,
+    grouping: Option<DateGrouping>,
+    alias: Option<String>,
+}
+
+/// Build a FetchXML document from a compiled statement
+pub(super) fn build(compiler: &Compiler) -> Result<String, SqlError> {
+    let stmt = compiler.stmt;
+    let mut elements: Vec<TableElements> = compiler.tables.iter().map(|_| TableElements::default()).collect();
+
+    if stmt.is_aggregate() {
+        aggregate_columns(compiler, &mut elements)?;
+    } else {
+        for item in &stmt.columns {
+            match item {
+                SelectItem::Wildcard { qualifier: None, .. } => {
+                    elements.iter_mut().for_each(|e| e.all_attributes = true);
+                }
+                SelectItem::Wildcard { qualifier: Some(q), position } => {
+                    elements[compiler.table_index(q, *position)?].all_attributes = true;
+                }
+                SelectItem::Column { column, alias } => {
+                    if alias.is_some() {
+                        return Err(SqlError::new("Column aliases are only supported with aggregates", column.position));
+                    }
+                    let (table, attr) = compiler.column(column)?;
+                    let element = format!("<attribute name=\"{}\"/>", escape(&attr.logical_name));
+                    if !elements[table].attributes.contains(&element) {
+                        elements[table].attributes.push(element);
+                    }
+                }
+                SelectItem::Aggregate { .. } | SelectItem::DateGroup { .. } => unreachable!("handled as aggregate"),
+            }
+        }
+
+        for item in &stmt.order_by {
+            let (table, attr) = compiler.column(&item.column)?;
+            let descending = if item.descending { " descending=\"true\"" } else { "" };
+            elements[table]
+                .orders
+                .push(format!("<order attribute=\"{}\"{}/>", escape(&attr.logical_name), descending));
+        }
+    }
+
+    let filter = match &stmt.where_clause {
+        Some(expr) => {
+            let mut conditions = Vec::new();
+            flatten(compiler, expr, false, true, &mut conditions)?;
+            format!("<filter type=\"and\">{}</filter>", conditions.concat())
+        }
+        None => String::new(),
+    };
+
+    let top = match stmt.top.or(stmt.limit) {
+        Some(n) => format!(" top=\"{}\"", n),
+        None => String::new(),
+    };
+
+    let aggregate = if stmt.is_aggregate() { " aggregate=\"true\"" } else { "" };
+
+    Ok(format!(
+        "<fetch{}{}>{}</fetch>",
+        top,
+        aggregate,
+        render_table(compiler, &elements, 0, &filter)
+    ))
+}
+
+/// Collect grouped and aggregated columns, which are returned under their aliases
+fn aggregate_columns(compiler: &Compiler, elements: &mut [TableElements]) -> Result<(), SqlError> {
+    let stmt = compiler.stmt;
+    let mut aliases: Vec<String> = Vec::new();
+    let mut use_alias = |alias: String, position: usize| -> Result<String, SqlError> {
+        if alias.is_empty() || !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(SqlError::new(
+                format!("Alias '{}' may only contain letters, digits and underscores", alias),
+                position,
+            ));
+        }
+        if aliases.iter().any(|a| a.eq_ignore_ascii_case(&alias)) {
+            return Err(SqlError::new(format!("Duplicate column alias '{}'", alias), position));
+        }
+        aliases.push(alias.clone());
+        Ok(alias)
+    };
+
+    let mut groups = Vec::new();
+    for item in &stmt.group_by {
+        let (table, attr) = compiler.column(&item.column)?;
+        if item.grouping.is_some() && base_type(attr) != "DateTime" {
+            return Err(SqlError::new(
+                format!("Column '{}' is {}, date grouping needs a date column", attr.logical_name, attr.get_type_name()),
+                item.column.position,
+            ));
+        }
+        groups.push(Group {
+            table,
+            attribute: attr.logical_name.clone(),
+            grouping: item.grouping,
+            alias: None,
+        });
+    }
+
+    let mut aggregates = Vec::new();
+    for item in &stmt.columns {
+        match item {
+            SelectItem::Wildcard { position, .. } => {
+                return Err(SqlError::new("SELECT * can't be combined with GROUP BY or aggregates", *position));
+            }
+            SelectItem::Column { column, alias } | SelectItem::DateGroup { column, alias, .. } => {
+                let grouping = match item {
+                    SelectItem::DateGroup { grouping, .. } => Some(*grouping),
+                    _ => None,
+                };
+                let (table, attr) = compiler.column(column)?;
+                let group = groups
+                    .iter_mut()
+                    .find(|g| g.table == table && g.attribute == attr.logical_name && g.grouping == grouping)
+                    .ok_or_else(|| {
+                        SqlError::new(
+                            format!("'{}' must appear in GROUP BY or be used in an aggregate", column.name),
+                            column.position,
+                        )
+                    })?;
+                let default = match grouping {
+                    Some(g) => format!("{}_{}", g.name(), attr.logical_name),
+                    None => attr.logical_name.clone(),
+                };
+                group.alias = Some(use_alias(alias.clone().unwrap_or(default), column.position)?);
+            }
+            SelectItem::Aggregate { function, column, distinct, alias, position } => {
+                let (table, attribute, aggregate, default) = match column {
+                    None => {
+                        let root = &compiler.tables[0].entity;
+                        let id = root
+                            .primary_id_attribute
+                            .clone()
+                            .unwrap_or_else(|| format!("{}id", root.logical_name));
+                        (0, id, "count", "count".to_string())
+                    }
+                    Some(column) => {
+                        let (table, attr) = compiler.column(column)?;
+                        let numeric = matches!(
+                            base_type(attr).as_str(),
+                            "Integer" | "BigInt" | "Decimal" | "Double" | "Money"
+                        );
+                        if *function != AggregateFn::Count && !numeric {
+                            return Err(SqlError::new(
+                                format!(
+                                    "{} needs a numeric column, '{}' is {}",
+                                    function.name().to_uppercase(),
+                                    attr.logical_name,
+                                    attr.get_type_name()
+                                ),
+                                column.position,
+                            ));
+                        }
+                        let aggregate = if *function == AggregateFn::Count { "countcolumn" } else { function.name() };
+                        let default = format!("{}_{}", function.name(), attr.logical_name);
+                        (table, attr.logical_name.clone(), aggregate, default)
+                    }
+                };
+                let alias = use_alias(alias.clone().unwrap_or(default), *position)?;
+                let distinct = if *distinct { " distinct=\"true\"" } else { "" };
+                aggregates.push((
+                    table,
+                    format!(
+                        "<attribute name=\"{}\" alias=\"{}\" aggregate=\"{}\"{}/>",
+                        escape(&attribute),
+                        escape(&alias),
+                        aggregate,
+                        distinct
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Grouped columns that aren't selected still need an alias
+    for (group, item) in groups.iter_mut().zip(&stmt.group_by) {
+        if group.alias.is_none() {
+            let default = match group.grouping {
+                Some(g) => format!("{}_{}", g.name(), group.attribute),
+                None => group.attribute.clone(),
+            };
+            group.alias = Some(use_alias(default, item.column.position)?);
+        }
+    }
+
+    for group in &groups {
+        let dategrouping = match group.grouping {
+            Some(g) => format!(" dategrouping=\"{}\"", g.name()),
+            None => String::new(),
+        };
+        elements[group.table].attributes.push(format!(
+            "<attribute name=\"{}\" alias=\"{}\" groupby=\"true\"{}/>",
+            escape(&group.attribute),
+            escape(group.alias.as_deref().unwrap_or_default()),
+            dategrouping
+        ));
+    }
+    for (table, element) in aggregates {
+        elements[table].attributes.push(element);
+    }
+
+    // Aggregate results can only be ordered by alias
+    for item in &stmt.order_by {
+        let by_alias = item.column.qualifier.is_none()
+            && aliases.iter().any(|a| a.eq_ignore_ascii_case(&item.column.name));
+        let alias = if by_alias {
+            item.column.name.clone()
+        } else {
+            let (table, attr) = compiler.column(&item.column)?;
+            groups
+                .iter()
+                .find(|g| g.table == table && g.attribute == attr.logical_name && g.grouping.is_none())
+                .and_then(|g| g.alias.clone())
+                .ok_or_else(|| {
+                    SqlError::new(
+                        "ORDER BY in an aggregate query must use a grouped column or a column alias",
+                        item.column.position,
+                    )
+                })?
+        };
+        let descending = if item.descending { " descending=\"true\"" } else { "" };
+        elements[0]
+            .orders
+            .push(format!("<order alias=\"{}\"{}/>", escape(&alias), descending));
+    }
+
+    Ok(())
+}
+
+/// Render a table and the tables joined to it
+fn render_table(compiler: &Compiler, elements: &[TableElements], index: usize, filter: &str) -> String {
+    let table = &compiler.tables[index];
+    let own = &elements[index];
+
+    let mut body = String::new();
+    if own.all_attributes {
+        body.push_str("<all-attributes/>");
+    } else {
+        body.push_str(&own.attributes.concat());
+    }
+    body.push_str(&own.orders.concat());
+    body.push_str(filter);
+    for (child, _) in compiler.tables.iter().enumerate().filter(|(_, t)| t.parent == Some(index)) {
+        body.push_str(&render_table(compiler, elements, child, ""));
+    }
+
+    if table.parent.is_none() {
+        format!("<entity name=\"{}\">{}</entity>", escape(&table.entity.logical_name), body)
+    } else {
+        let link_type = match table.kind {
+            JoinKind::Inner => "inner",
+            JoinKind::Left => "outer",
+        };
+        format!(
+            "<link-entity name=\"{}\" from=\"{}\" to=\"{}\" alias=\"{}\" link-type=\"{}\">{}</link-entity>",
+            escape(&table.entity.logical_name),
+            escape(&table.from_attribute),
+            escape(&table.to_attribute),
+            escape(table.qualifier()),
+            link_type,
+            body
+        )
+    }
+}
+
+/// Render an expression as a `<filter>`, pushing NOT down to the conditions
+fn filter(compiler: &Compiler, expr: &Expr, negated: bool) -> Result<String, SqlError> {
+    match expr {
+        Expr::Not(inner) => filter(compiler, inner, !negated),
+        Expr::And(..) | Expr::Or(..) => {
+            let is_and = matches!(expr, Expr::And(..)) != negated;
+            let mut conditions = Vec::new();
+            flatten(compiler, expr, negated, is_and, &mut conditions)?;
+            let filter_type = if is_and { "and" } else { "or" };
+            Ok(format!("<filter type=\"{}\">{}</filter>", filter_type, conditions.concat()))
+        }
+        _ => condition(compiler, expr, negated),
+    }
+}
+
+/// Collect the children of nested filters of the same type into one list
+fn flatten(compiler: &Compiler, expr: &Expr, negated: bool, is_and: bool, out: &mut Vec<String>) -> Result<(), SqlError> {
+    match expr {
+        Expr::Not(inner) => flatten(compiler, inner, !negated, is_and, out),
+        Expr::And(left, right) | Expr::Or(left, right) if (matches!(expr, Expr::And(..)) != negated) == is_and => {
+            flatten(compiler, left, negated, is_and, out)?;
+            flatten(compiler, right, negated, is_and, out)
+        }
+        _ => {
+            out.push(filter(compiler, expr, negated)?);
+            Ok(())
+        }
+    }
+}
+
+/// Render a single predicate as a `<condition>`
+fn condition(compiler: &Compiler, expr: &Expr, negated: bool) -> Result<String, SqlError> {
+    let (column, operator, value) = match expr {
+        Expr::Compare { column, op, value } => {
+            let attr = compiler.column(column)?.1;
+            let op = if negated { negate(*op) } else { *op };
+            let value = fetch_value(compiler.value(attr, value)?);
+            (column, op.odata(), Some(value))
+        }
+        Expr::IsNull { column, negated: not_null } => {
+            let operator = if *not_null != negated { "not-null" } else { "null" };
+            (column, operator, None)
+        }
+        Expr::In { column, values, negated: not_in } => {
+            let (table, attr) = compiler.column(column)?;
+            let items = values
+                .iter()
+                .map(|v| compiler.value(attr, v).map(|v| format!("<value>{}</value>", escape(&fetch_value(v)))))
+                .collect::<Result<String, SqlError>>()?;
+            let operator = if *not_in != negated { "not-in" } else { "in" };
+            return Ok(format!(
+                "<condition{} attribute=\"{}\" operator=\"{}\">{}</condition>",
+                entity_name(compiler, table),
+                escape(&attr.logical_name),
+                operator,
+                items
+            ));
+        }
+        Expr::Like { column, pattern, negated: not_like } => {
+            let attr = compiler.column(column)?.1;
+            let pattern = compiler.like_pattern(attr, pattern)?.to_string();
+            let operator = if *not_like != negated { "not-like" } else { "like" };
+            (column, operator, Some(pattern))
+        }
+        Expr::And(..) | Expr::Or(..) | Expr::Not(..) => return filter(compiler, expr, negated),
+    };
+
+    let (table, attr) = compiler.column(column)?;
+    let value = match value {
+        Some(v) => format!(" value=\"{}\"", escape(&v)),
+        None => String::new(),
+    };
+    Ok(format!(
+        "<condition{} attribute=\"{}\" operator=\"{}\"{}/>",
+        entity_name(compiler, table),
+        escape(&attr.logical_name),
+        operator,
+        value
+    ))
+}
+
+/// `entityname` attribute for conditions on joined tables
+fn entity_name(compiler: &Compiler, table: usize) -> String {
+    if table == 0 {
+        String::new()
+    } else {
+        format!(" entityname=\"{}\"", escape(compiler.tables[table].qualifier()))
+    }
+}
+
+fn negate(op: CompareOp) -> CompareOp {
+    match op {
+        CompareOp::Eq => CompareOp::NotEq,
+        CompareOp::NotEq => CompareOp::Eq,
+        CompareOp::Lt => CompareOp::GtEq,
+        CompareOp::LtEq => CompareOp::Gt,
+        CompareOp::Gt => CompareOp::LtEq,
+        CompareOp::GtEq => CompareOp::Lt,
+    }
+}
+
+fn fetch_value(value: TypedValue) -> String {
+    match value {
+        TypedValue::Text(s) | TypedValue::Raw(s) => s,
+        TypedValue::Bool(b) => if b { "1" } else { "0" }.to_string(),
+    }
+}
+
+/// Escape text for use in XML attributes and content
+pub(super) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
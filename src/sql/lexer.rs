@@ -0,0 +1,203 @@
+//! SQL tokenizer
+
+use super::SqlError;
+
+/// Kind of a lexical token
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// Bare identifier or keyword (keywords are matched case-insensitively by the parser)
+    Ident(String),
+    /// Identifier quoted with [brackets] or "double quotes" - never treated as a keyword
+    QuotedIdent(String),
+    /// 'string literal' with '' escapes resolved
+    String(String),
+    /// Numeric literal, kept as written
+    Number(String),
+    Comma,
+    Dot,
+    Star,
+    LParen,
+    RParen,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Semicolon,
+}
+
+impl TokenKind {
+    /// Human readable description used in error messages
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Ident(s) | Self::QuotedIdent(s) => format!("'{}'", s),
+            Self::String(s) => format!("string '{}'", s),
+            Self::Number(n) => format!("number {}", n),
+            Self::Comma => "','".to_string(),
+            Self::Dot => "'.'".to_string(),
+            Self::Star => "'*'".to_string(),
+            Self::LParen => "'('".to_string(),
+            Self::RParen => "')'".to_string(),
+            Self::Eq => "'='".to_string(),
+            Self::NotEq => "'<>'".to_string(),
+            Self::Lt => "'<'".to_string(),
+            Self::LtEq => "'<='".to_string(),
+            Self::Gt => "'>'".to_string(),
+            Self::GtEq => "'>='".to_string(),
+            Self::Semicolon => "';'".to_string(),
+        }
+    }
+}
+
+/// A token together with its character offset in the source text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub position: usize,
+}
+
+/// Split SQL text into tokens
+pub fn tokenize(input: &str) -> Result<Vec<Token>, SqlError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        // Line comments
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+
+        let kind = match c {
+            ',' => { i += 1; TokenKind::Comma }
+            '.' if !chars.get(i + 1).is_some_and(|n| n.is_ascii_digit()) => { i += 1; TokenKind::Dot }
+            '*' => { i += 1; TokenKind::Star }
+            '(' => { i += 1; TokenKind::LParen }
+            ')' => { i += 1; TokenKind::RParen }
+            ';' => { i += 1; TokenKind::Semicolon }
+            '=' => { i += 1; TokenKind::Eq }
+            '!' if chars.get(i + 1) == Some(&'=') => { i += 2; TokenKind::NotEq }
+            '<' => match chars.get(i + 1) {
+                Some('>') => { i += 2; TokenKind::NotEq }
+                Some('=') => { i += 2; TokenKind::LtEq }
+                _ => { i += 1; TokenKind::Lt }
+            },
+            '>' => match chars.get(i + 1) {
+                Some('=') => { i += 2; TokenKind::GtEq }
+                _ => { i += 1; TokenKind::Gt }
+            },
+            '\'' => {
+                i += 1;
+                let mut value = String::new();
+                loop {
+                    match chars.get(i) {
+                        None => return Err(SqlError::new("Unterminated string literal", start)),
+                        Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                            value.push('\'');
+                            i += 2;
+                        }
+                        Some('\'') => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                TokenKind::String(value)
+            }
+            '[' | '"' => {
+                let close = if c == '[' { ']' } else { '"' };
+                i += 1;
+                let name_start = i;
+                while i < chars.len() && chars[i] != close {
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(SqlError::new("Unterminated quoted identifier", start));
+                }
+                let name: String = chars[name_start..i].iter().collect();
+                i += 1;
+                TokenKind::QuotedIdent(name)
+            }
+            c if c.is_ascii_digit()
+                || ((c == '.' || c == '-') && chars.get(i + 1).is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let mut seen_dot = c == '.';
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    if chars[i] == '.' {
+                        if seen_dot {
+                            return Err(SqlError::new("A number can only have one decimal point", i));
+                        }
+                        seen_dot = true;
+                    }
+                    i += 1;
+                }
+                TokenKind::Number(chars[start..i].iter().collect())
+            }
+            c if c.is_alphabetic() || c == '_' || c == '@' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '@') {
+                    i += 1;
+                }
+                TokenKind::Ident(chars[start..i].iter().collect())
+            }
+            other => {
+                return Err(SqlError::new(format!("Unexpected character '{}'", other), start));
+            }
+        };
+
+        tokens.push(Token { kind, position: start });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_operators_and_literals() {
+        let tokens = tokenize("name <> 'O''Brien' AND revenue >= -10.5").unwrap();
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident("name".to_string()),
+                TokenKind::NotEq,
+                TokenKind::String("O'Brien".to_string()),
+                TokenKind::Ident("AND".to_string()),
+                TokenKind::Ident("revenue".to_string()),
+                TokenKind::GtEq,
+                TokenKind::Number("-10.5".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string_reports_position() {
+        let err = tokenize("SELECT * FROM account WHERE name = 'abc").unwrap_err();
+        assert_eq!(err.position, 35);
+    }
+
+    #[test]
+    fn test_number_with_two_decimal_points_is_rejected() {
+        let err = tokenize("WHERE revenue = 1.2.3").unwrap_err();
+        assert_eq!(err.position, 19);
+    }
+}
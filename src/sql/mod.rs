@@ -0,0 +1,32 @@
+//! SQL console support: tokenizer, parser and OData compiler
+//!
+//! Queries are parsed into a small AST, validated against cached entity and
+//! attribute metadata, and compiled into a Dataverse Web API query. Statements
+//! with joins are compiled to FetchXML with `<link-entity>` elements instead.
+
+mod ast;
+mod compiler;
+mod fetchxml;
+mod lexer;
+mod parser;
+
+pub use compiler::{compile, resolve_entities, CompiledQuery, Schema};
+pub use parser::parse;
+
+/// A SQL error with the character position it refers to
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message} (at column {})", position + 1)]
+pub struct SqlError {
+    pub message: String,
+    /// Zero-based character offset into the query text
+    pub position: usize,
+}
+
+impl SqlError {
+    pub fn new(message: impl Into<String>, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            position,
+        }
+    }
+}
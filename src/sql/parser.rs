@@ -0,0 +1,510 @@
+//! Recursive descent parser for the SQL console dialect
+
+use super::ast::{
+    AggregateFn, ColumnRef, CompareOp, DateGrouping, Expr, GroupByItem, Join, JoinKind, Literal,
+    OrderByItem, SelectItem, SelectStatement, TableRef, Value,
+};
+use super::lexer::{tokenize, Token, TokenKind};
+use super::SqlError;
+
+/// Words that can't be used as bare identifiers or aliases
+const RESERVED: &[&str] = &[
+    "SELECT", "TOP", "FROM", "WHERE", "AND", "OR", "NOT", "LIKE", "IN", "IS", "NULL", "ORDER",
+    "BY", "ASC", "DESC", "LIMIT", "AS", "TRUE", "FALSE", "JOIN", "INNER", "LEFT", "OUTER", "ON",
+    "GROUP", "DISTINCT",
+];
+
+/// Parse a single `SELECT` statement
+pub fn parse(sql: &str) -> Result<SelectStatement, SqlError> {
+    let tokens = tokenize(sql)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: sql.chars().count(),
+    };
+    parser.parse_select()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Character length of the input, used for "unexpected end" errors
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn position(&self) -> usize {
+        self.peek().map(|t| t.position).unwrap_or(self.end)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error_here(&self, expected: &str) -> SqlError {
+        match self.peek() {
+            Some(token) => SqlError::new(
+                format!("Expected {}, found {}", expected, token.kind.describe()),
+                token.position,
+            ),
+            None => SqlError::new(format!("Expected {}, found end of query", expected), self.end),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Ident(word), .. }) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SqlError> {
+        if self.accept_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.error_here(keyword))
+        }
+    }
+
+    fn accept(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().map(|t| &t.kind) == Some(kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), SqlError> {
+        if self.accept(kind) {
+            Ok(())
+        } else {
+            Err(self.error_here(&kind.describe()))
+        }
+    }
+
+    /// Parse an identifier, rejecting reserved words unless quoted
+    fn identifier(&mut self, what: &str) -> Result<(String, usize), SqlError> {
+        match self.peek().cloned() {
+            Some(Token { kind: TokenKind::Ident(word), position }) => {
+                if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(&word)) {
+                    return Err(self.error_here(what));
+                }
+                self.pos += 1;
+                Ok((word, position))
+            }
+            Some(Token { kind: TokenKind::QuotedIdent(word), position }) => {
+                self.pos += 1;
+                Ok((word, position))
+            }
+            _ => Err(self.error_here(what)),
+        }
+    }
+
+    /// Parse an optional `[AS] alias`
+    fn alias(&mut self) -> Result<Option<String>, SqlError> {
+        if self.accept_keyword("AS") {
+            return Ok(Some(self.identifier("alias")?.0));
+        }
+        match self.peek() {
+            Some(Token { kind: TokenKind::Ident(word), .. })
+                if !RESERVED.iter().any(|r| r.eq_ignore_ascii_case(word)) =>
+            {
+                Ok(Some(self.identifier("alias")?.0))
+            }
+            Some(Token { kind: TokenKind::QuotedIdent(_), .. }) => Ok(Some(self.identifier("alias")?.0)),
+            _ => Ok(None),
+        }
+    }
+
+    fn unsigned_integer(&mut self, what: &str) -> Result<u64, SqlError> {
+        match self.peek().cloned() {
+            Some(Token { kind: TokenKind::Number(n), position }) => {
+                self.pos += 1;
+                n.parse::<u64>()
+                    .map_err(|_| SqlError::new(format!("{} must be a positive whole number", what), position))
+            }
+            _ => Err(self.error_here(&format!("a number for {}", what))),
+        }
+    }
+
+    fn parse_select(&mut self) -> Result<SelectStatement, SqlError> {
+        self.expect_keyword("SELECT")?;
+
+        let top = if self.accept_keyword("TOP") {
+            let parenthesized = self.accept(&TokenKind::LParen);
+            let n = self.unsigned_integer("TOP")?;
+            if parenthesized {
+                self.expect(&TokenKind::RParen)?;
+            }
+            Some(n)
+        } else {
+            None
+        };
+
+        let columns = self.parse_select_list()?;
+
+        self.expect_keyword("FROM")?;
+        let (name, position) = self.identifier("entity name")?;
+        let alias = self.alias()?;
+        let from = TableRef { name, alias, position };
+
+        let mut joins = Vec::new();
+        while let Some(kind) = self.parse_join_kind()? {
+            let (name, position) = self.identifier("entity name")?;
+            let alias = self.alias()?;
+            let table = TableRef { name, alias, position };
+            self.expect_keyword("ON")?;
+            let left = self.parse_column_ref()?;
+            self.expect(&TokenKind::Eq)?;
+            let right = self.parse_column_ref()?;
+            joins.push(Join { kind, table, left, right });
+        }
+
+        let where_clause = if self.accept_keyword("WHERE") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+
+        let mut group_by = Vec::new();
+        if self.accept_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                let (column, grouping) = self.parse_grouped_column()?;
+                group_by.push(GroupByItem { column, grouping });
+                if !self.accept(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.accept_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let column = self.parse_column_ref()?;
+                let descending = if self.accept_keyword("DESC") {
+                    true
+                } else {
+                    self.accept_keyword("ASC");
+                    false
+                };
+                order_by.push(OrderByItem { column, descending });
+                if !self.accept(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let limit = if self.is_keyword("LIMIT") {
+            let position = self.position();
+            self.pos += 1;
+            if top.is_some() {
+                return Err(SqlError::new("Use either TOP or LIMIT, not both", position));
+            }
+            Some(self.unsigned_integer("LIMIT")?)
+        } else {
+            None
+        };
+
+        self.accept(&TokenKind::Semicolon);
+        if self.peek().is_some() {
+            return Err(self.error_here("end of query"));
+        }
+
+        Ok(SelectStatement {
+            top,
+            columns,
+            from,
+            joins,
+            where_clause,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    /// Parse the keywords introducing a join, if present
+    fn parse_join_kind(&mut self) -> Result<Option<JoinKind>, SqlError> {
+        if self.accept_keyword("JOIN") {
+            return Ok(Some(JoinKind::Inner));
+        }
+        if self.accept_keyword("INNER") {
+            self.expect_keyword("JOIN")?;
+            return Ok(Some(JoinKind::Inner));
+        }
+        if self.accept_keyword("LEFT") {
+            self.accept_keyword("OUTER");
+            self.expect_keyword("JOIN")?;
+            return Ok(Some(JoinKind::Left));
+        }
+        Ok(None)
+    }
+
+    fn parse_select_list(&mut self) -> Result<Vec<SelectItem>, SqlError> {
+        let mut items = Vec::new();
+        loop {
+            let position = self.position();
+            if self.accept(&TokenKind::Star) {
+                items.push(SelectItem::Wildcard { qualifier: None, position });
+            } else if self.next_is_call() {
+                items.push(self.parse_function(position)?);
+            } else {
+                let (first, first_pos) = self.identifier("column name")?;
+                if self.accept(&TokenKind::Dot) {
+                    if self.accept(&TokenKind::Star) {
+                        items.push(SelectItem::Wildcard { qualifier: Some(first), position });
+                    } else {
+                        let (name, _) = self.identifier("column name")?;
+                        let column = ColumnRef { qualifier: Some(first), name, position: first_pos };
+                        let alias = self.alias()?;
+                        items.push(SelectItem::Column { column, alias });
+                    }
+                } else {
+                    let column = ColumnRef { qualifier: None, name: first, position: first_pos };
+                    let alias = self.alias()?;
+                    items.push(SelectItem::Column { column, alias });
+                }
+            }
+            if !self.accept(&TokenKind::Comma) {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Check for `name(` at the current position
+    fn next_is_call(&self) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Ident(_), .. }))
+            && matches!(self.tokens.get(self.pos + 1), Some(Token { kind: TokenKind::LParen, .. }))
+    }
+
+    /// Parse an aggregate or date part function call in the select list
+    fn parse_function(&mut self, position: usize) -> Result<SelectItem, SqlError> {
+        let Some(Token { kind: TokenKind::Ident(name), .. }) = self.advance() else {
+            return Err(self.error_here("function name"));
+        };
+        self.expect(&TokenKind::LParen)?;
+
+        if let Some(function) = AggregateFn::from_name(&name) {
+            let (column, distinct) = if function == AggregateFn::Count && self.accept(&TokenKind::Star) {
+                (None, false)
+            } else {
+                let distinct = self.accept_keyword("DISTINCT");
+                (Some(self.parse_column_ref()?), distinct)
+            };
+            if distinct && function != AggregateFn::Count {
+                return Err(SqlError::new("DISTINCT is only supported with COUNT", position));
+            }
+            self.expect(&TokenKind::RParen)?;
+            let alias = self.alias()?;
+            return Ok(SelectItem::Aggregate { function, column, distinct, alias, position });
+        }
+
+        if let Some(grouping) = DateGrouping::from_name(&name) {
+            let column = self.parse_column_ref()?;
+            self.expect(&TokenKind::RParen)?;
+            let alias = self.alias()?;
+            return Ok(SelectItem::DateGroup { grouping, column, alias });
+        }
+
+        Err(SqlError::new(
+            format!("Unknown function '{}', expected COUNT, SUM, AVG, MIN, MAX, YEAR, QUARTER, MONTH, WEEK or DAY", name),
+            position,
+        ))
+    }
+
+    /// Parse `column` or `YEAR(column)` in a GROUP BY clause
+    fn parse_grouped_column(&mut self) -> Result<(ColumnRef, Option<DateGrouping>), SqlError> {
+        if !self.next_is_call() {
+            return Ok((self.parse_column_ref()?, None));
+        }
+        let position = self.position();
+        let (name, _) = self.identifier("column name")?;
+        let grouping = DateGrouping::from_name(&name).ok_or_else(|| {
+            SqlError::new(
+                format!("Unknown function '{}', expected YEAR, QUARTER, MONTH, WEEK or DAY", name),
+                position,
+            )
+        })?;
+        self.expect(&TokenKind::LParen)?;
+        let column = self.parse_column_ref()?;
+        self.expect(&TokenKind::RParen)?;
+        Ok((column, Some(grouping)))
+    }
+
+    fn parse_column_ref(&mut self) -> Result<ColumnRef, SqlError> {
+        let (first, position) = self.identifier("column name")?;
+        if self.accept(&TokenKind::Dot) {
+            let (name, _) = self.identifier("column name")?;
+            Ok(ColumnRef { qualifier: Some(first), name, position })
+        } else {
+            Ok(ColumnRef { qualifier: None, name: first, position })
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, SqlError> {
+        let mut left = self.parse_and()?;
+        while self.accept_keyword("OR") {
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, SqlError> {
+        let mut left = self.parse_not()?;
+        while self.accept_keyword("AND") {
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, SqlError> {
+        if self.accept_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        if self.accept(&TokenKind::LParen) {
+            let inner = self.parse_or()?;
+            self.expect(&TokenKind::RParen)?;
+            return Ok(inner);
+        }
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> Result<Expr, SqlError> {
+        let column = self.parse_column_ref()?;
+
+        if self.accept_keyword("IS") {
+            let negated = self.accept_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { column, negated });
+        }
+
+        let negated = self.accept_keyword("NOT");
+        if self.accept_keyword("LIKE") {
+            let pattern = self.parse_value()?;
+            return Ok(Expr::Like { column, pattern, negated });
+        }
+        if self.accept_keyword("IN") {
+            self.expect(&TokenKind::LParen)?;
+            let mut values = vec![self.parse_value()?];
+            while self.accept(&TokenKind::Comma) {
+                values.push(self.parse_value()?);
+            }
+            self.expect(&TokenKind::RParen)?;
+            return Ok(Expr::In { column, values, negated });
+        }
+        if negated {
+            return Err(self.error_here("LIKE or IN after NOT"));
+        }
+
+        let op_position = self.position();
+        let op = match self.advance().map(|t| t.kind) {
+            Some(TokenKind::Eq) => CompareOp::Eq,
+            Some(TokenKind::NotEq) => CompareOp::NotEq,
+            Some(TokenKind::Lt) => CompareOp::Lt,
+            Some(TokenKind::LtEq) => CompareOp::LtEq,
+            Some(TokenKind::Gt) => CompareOp::Gt,
+            Some(TokenKind::GtEq) => CompareOp::GtEq,
+            _ => {
+                return Err(SqlError::new(
+                    "Expected a comparison operator, LIKE, IN or IS NULL",
+                    op_position,
+                ));
+            }
+        };
+
+        let value = self.parse_value()?;
+        if value.literal == Literal::Null {
+            let hint = if op == CompareOp::NotEq { "IS NOT NULL" } else { "IS NULL" };
+            return Err(SqlError::new(format!("Use {} to compare with NULL", hint), value.position));
+        }
+        Ok(Expr::Compare { column, op, value })
+    }
+
+    fn parse_value(&mut self) -> Result<Value, SqlError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error_here("a value"));
+        };
+        let literal = match token.kind {
+            TokenKind::String(s) => Literal::String(s),
+            TokenKind::Number(n) => Literal::Number(n),
+            TokenKind::Ident(word) if word.eq_ignore_ascii_case("TRUE") => Literal::Boolean(true),
+            TokenKind::Ident(word) if word.eq_ignore_ascii_case("FALSE") => Literal::Boolean(false),
+            TokenKind::Ident(word) if word.eq_ignore_ascii_case("NULL") => Literal::Null,
+            _ => return Err(self.error_here("a value")),
+        };
+        self.pos += 1;
+        Ok(Value { literal, position: token.position })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_statement() {
+        let stmt = parse(
+            "SELECT TOP 10 a.name, revenue FROM account a \
+             WHERE (name = 'Contoso Ltd' OR name LIKE 'abc%') AND statecode IN (0, 1) AND parentaccountid IS NULL \
+             ORDER BY revenue DESC, name",
+        )
+        .unwrap();
+
+        assert_eq!(stmt.top, Some(10));
+        assert_eq!(stmt.columns.len(), 2);
+        assert_eq!(stmt.from.name, "account");
+        assert_eq!(stmt.from.alias.as_deref(), Some("a"));
+        assert_eq!(stmt.order_by.len(), 2);
+        assert!(stmt.order_by[0].descending);
+        assert!(matches!(stmt.where_clause, Some(Expr::And(_, _))));
+    }
+
+    #[test]
+    fn test_parse_aggregates() {
+        let stmt = parse(
+            "SELECT ownerid, YEAR(createdon) yr, COUNT(*) AS total, COUNT(DISTINCT name) FROM account \
+             GROUP BY ownerid, YEAR(createdon) ORDER BY total DESC",
+        )
+        .unwrap();
+
+        assert!(stmt.is_aggregate());
+        assert_eq!(stmt.columns.len(), 4);
+        assert!(matches!(
+            &stmt.columns[2],
+            SelectItem::Aggregate { function: AggregateFn::Count, column: None, .. }
+        ));
+        assert!(matches!(&stmt.columns[3], SelectItem::Aggregate { distinct: true, .. }));
+        assert_eq!(stmt.group_by[1].grouping, Some(DateGrouping::Year));
+    }
+
+    #[test]
+    fn test_parse_errors_are_positional() {
+        let err = parse("SELECT name FROM account WHERE name = NULL").unwrap_err();
+        assert_eq!(err.position, 38);
+
+        let err = parse("SELECT name FROM WHERE").unwrap_err();
+        assert_eq!(err.position, 17);
+
+        let err = parse("SELECT TOP 5 name FROM account LIMIT 5").unwrap_err();
+        assert_eq!(err.position, 31);
+    }
+}
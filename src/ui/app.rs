@@ -1,14 +1,27 @@
 //! Application state and main TUI logic
 
-use crate::api::DataverseClient;
+use crate::api::{DataverseClient, DataverseError, DataverseErrorKind, MAX_CONCURRENT_REQUESTS};
 use crate::models::{
     AttributeMetadata, EntityMetadata, QueryResult,
     RelationshipMetadata, RoleAssignment, RoleSource, SecurityRole, Solution, SolutionComponent,
-    ComponentType, SystemUser, Team, OptionSetMetadata, SystemJob,
+    ComponentType, SystemUser, Team, OptionSetMetadata, SystemJob, EntityView,
 };
+use super::editor::TextEditor;
+use super::graph::{EntityRelationships, RelationshipGraph};
 use super::input::{InputMode, KeyBindings};
+use super::tasks::Tasks;
+use crate::history::{HistoryEntry, HistoryKind, QueryHistory};
+use crate::diff::{CompareSource, SchemaDiff};
+use crate::erd::{self, Erd, ErdFormat};
+use crate::metadata_cache::MetadataCache;
+use crate::saved_queries::{SavedQuery, SavedQueryBody, SavedQueryStore};
+use crate::snapshot::Snapshot;
 use anyhow::Context;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 /// Current view in the application
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -29,6 +42,10 @@ pub enum View {
     SystemJobs,
     SystemJobDetail,
     EnvironmentDiscovery,
+    SavedQueries,
+    History,
+    SchemaDiff,
+    RelationshipGraph,
 }
 
 /// Application state for the TUI
@@ -48,6 +65,7 @@ pub enum EntityTab {
     Relationships,
     Metadata,
     Query,
+    Views,
 }
 
 /// Query builder mode
@@ -67,6 +85,7 @@ pub enum SearchResult {
     Entity(usize),     // Index in entities
     Solution(usize),   // Index in solutions
     OptionSet(usize),  // Index in global_optionsets
+    Attribute { entity: String, attribute: String },
 }
 
 /// Detail tab for user view
@@ -93,8 +112,13 @@ pub struct App {
     /// Error message if any
     pub error: Option<String>,
 
+    /// API calls running in the background
+    pub tasks: Tasks,
+
     /// Key binding style
     pub key_bindings: KeyBindings,
+    /// Whether `--vim` was given, which wins over environment profiles
+    pub vim_flag: bool,
 
     /// Input mode
     pub input_mode: InputMode,
@@ -115,8 +139,17 @@ pub struct App {
     pub one_to_many: Vec<RelationshipMetadata>,
     pub many_to_one: Vec<RelationshipMetadata>,
     pub many_to_many: Vec<RelationshipMetadata>,
+    /// Entity the relationship lists above were fully loaded for
+    pub relationships_of: Option<String>,
+    /// Attributes loaded so far, keyed by entity logical name
+    pub attribute_cache: HashMap<String, Vec<AttributeMetadata>>,
+    /// Many-to-one and one-to-many relationships loaded so far, keyed by entity logical name
+    pub relationship_cache: HashMap<String, Vec<RelationshipMetadata>>,
     pub entity_tab: EntityTab,
     pub relationship_index: usize,
+    /// System views followed by personal views for the selected entity
+    pub entity_views: Vec<EntityView>,
+    pub entity_view_index: usize,
 
     // Solution list state
     pub solutions: Vec<Solution>,
@@ -151,8 +184,8 @@ pub struct App {
     pub solution_layers_index: usize,
 
     // FetchXML state
-    pub fetchxml_query: String,
-    pub fetchxml_cursor: usize,
+    pub fetchxml_editor: TextEditor,
+    pub fetchxml_error: Option<crate::fetchxml::FetchXmlError>,
 
     // User list state
     pub users: Vec<SystemUser>,
@@ -195,7 +228,31 @@ pub struct App {
 
     // SQL Console state
     pub sql_query: String,
+    /// Cursor position in `sql_query`, counted in characters
     pub sql_cursor: usize,
+    pub sql_error: Option<crate::sql::SqlError>,
+
+    // Saved queries state
+    pub saved_queries: SavedQueryStore,
+    pub saved_query_index: usize,
+    pub query_name_prompt: Option<QueryNamePrompt>,
+
+    // Query history state
+    pub history: QueryHistory,
+    /// History entry indices matching the search, newest first
+    pub filtered_history: Vec<usize>,
+    pub history_index: usize,
+    /// Position in the recalled texts while cycling with Up/Down in a console
+    pub history_recall: Option<usize>,
+    /// Console text from before history recall started
+    pub history_draft: String,
+
+    // Schema diff state
+    pub schema_diff: Option<SchemaDiff>,
+    pub schema_diff_index: usize,
+
+    /// Relationship graph navigator state
+    pub relationship_graph: RelationshipGraph,
 
     // Record detail state
     pub selected_record_index: Option<usize>,
@@ -204,12 +261,22 @@ pub struct App {
     // Feedback message
     pub message: Option<String>,
 
+    /// Device code sign-in instructions from the credential, `None` once the
+    /// sign-in has ended
+    pub device_code_prompts: Option<tokio::sync::mpsc::UnboundedReceiver<Option<String>>>,
+    /// Sign-in instructions shown in the status bar until the sign-in ends
+    pub sign_in_prompt: Option<String>,
+
+    /// Full details of the last error, shown in the popup opened with '!'
+    pub error_details: Option<String>,
+    pub error_details_scroll: u16,
+
     /// Should quit
     pub should_quit: bool,
 }
 
 /// Filter operator for guided filter building
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum FilterOp {
     #[default]
     Equals,
@@ -286,13 +353,31 @@ impl FilterOp {
 }
 
 /// A single filter condition
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FilterCondition {
     pub attribute_name: String,
     pub operator: FilterOp,
     pub value: String,
 }
 
+/// What the query name prompt does when confirmed
+#[derive(Debug, Clone)]
+pub enum QueryNameTarget {
+    /// Save the captured query under the entered name
+    Save { entity: Option<String>, query: SavedQueryBody },
+    /// Rename the saved query at this index in the store
+    Rename(usize),
+}
+
+/// Name input popup for saving or renaming a query
+#[derive(Debug, Clone)]
+pub struct QueryNamePrompt {
+    pub name: String,
+    pub target: QueryNameTarget,
+    /// Input mode to return to when the prompt closes
+    pub return_mode: InputMode,
+}
+
 impl App {
     /// Create a new app instance
     pub fn new(client: Arc<DataverseClient>, key_bindings: KeyBindings) -> Self {
@@ -311,6 +396,7 @@ impl App {
             view,
             error: None,
             key_bindings,
+            vim_flag: false,
             input_mode: InputMode::Normal,
             search_query: String::new(),
             entities: Vec::new(),
@@ -323,8 +409,13 @@ impl App {
             one_to_many: Vec::new(),
             many_to_one: Vec::new(),
             many_to_many: Vec::new(),
+            relationships_of: None,
+            attribute_cache: HashMap::new(),
+            relationship_cache: HashMap::new(),
             entity_tab: EntityTab::Attributes,
             relationship_index: 0,
+            entity_views: Vec::new(),
+            entity_view_index: 0,
             solutions: Vec::new(),
             filtered_solutions: Vec::new(),
             solution_index: 0,
@@ -345,8 +436,8 @@ impl App {
             discovery_index: 0,
             solution_layers: Vec::new(),
             solution_layers_index: 0,
-            fetchxml_query: String::new(),
-            fetchxml_cursor: 0,
+            fetchxml_editor: TextEditor::default(),
+            fetchxml_error: None,
             users: Vec::new(),
             filtered_users: Vec::new(),
             user_index: 0,
@@ -375,6 +466,10 @@ impl App {
             selected_record_index: None,
             record_detail_index: 0,
             message: None,
+            device_code_prompts: None,
+            sign_in_prompt: None,
+            error_details: None,
+            error_details_scroll: 0,
             should_quit: false,
             
             system_jobs: Vec::new(),
@@ -386,56 +481,220 @@ impl App {
 
             sql_query: String::new(),
             sql_cursor: 0,
+            sql_error: None,
+
+            saved_queries: SavedQueryStore::load().unwrap_or_default(),
+            saved_query_index: 0,
+            query_name_prompt: None,
+
+            history: QueryHistory::load().unwrap_or_default(),
+            filtered_history: Vec::new(),
+            history_index: 0,
+            history_recall: None,
+            history_draft: String::new(),
+            schema_diff: None,
+            schema_diff_index: 0,
+            relationship_graph: RelationshipGraph::default(),
+            tasks: Tasks::default(),
         }
     }
 
-    /// Load initial data (entities)
-    pub async fn load_entities(&mut self) {
-        self.state = AppState::Loading;
-        self.error = None;
+    /// Run `future` in the background, showing a loading placeholder in the
+    /// current view until `apply` has stored its result
+    fn spawn<T, F, A>(&mut self, label: &'static str, future: F, apply: A)
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = T> + Send + 'static,
+        A: FnOnce(&mut App, T) + Send + 'static,
+    {
+        self.tasks.spawn(label, Some(self.view), future, apply);
+    }
 
-        match self.client.get_entities().await {
-            Ok(mut entities) => {
-                entities.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
-                self.filtered_entities = (0..entities.len()).collect();
-                self.entities = entities;
-                self.state = AppState::Ready;
+    /// Run `future` in the background without blocking any view
+    fn spawn_background<T, F, A>(&mut self, label: &'static str, future: F, apply: A)
+    where
+        T: Send + 'static,
+        F: std::future::Future<Output = T> + Send + 'static,
+        A: FnOnce(&mut App, T) + Send + 'static,
+    {
+        self.tasks.spawn(label, None, future, apply);
+    }
+
+    /// Apply the results of finished background tasks
+    pub fn apply_task_updates(&mut self) {
+        while let Some(update) = self.tasks.next_update() {
+            update(self);
+        }
+        if let Some(prompts) = self.device_code_prompts.as_mut() {
+            while let Ok(prompt) = prompts.try_recv() {
+                self.sign_in_prompt = prompt;
             }
-            Err(e) => {
-                self.error = Some(format!("Failed to load entities: {}", e));
-                self.state = AppState::Error;
+        }
+    }
+
+    /// Cancel the tasks loading a view, returning whether any was running
+    pub fn cancel_tasks(&mut self) -> bool {
+        let cancelled = self.tasks.cancel_foreground();
+        if cancelled {
+            if self.state == AppState::Loading {
+                self.state = AppState::Ready;
             }
+            self.message = Some("Cancelled".to_string());
         }
+        cancelled
     }
 
-    /// Load entity details
-    pub async fn load_entity_detail(&mut self, logical_name: &str) {
+    /// Load initial data (entities)
+    ///
+    /// Entities come from the snapshot when offline, or the metadata cache
+    /// when there is one, otherwise from the API. The cache is then refreshed
+    /// in the background.
+    pub fn load_entities(&mut self) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_entity_attributes(logical_name).await {
-            Ok(mut attrs) => {
-                attrs.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
-                self.filtered_attributes = (0..attrs.len()).collect();
-                self.entity_attributes = attrs;
+        let client = self.client.clone();
+        let url = client.environment_url();
+        let load = async move {
+            let cached = match client.snapshot() {
+                Some(snapshot) => Some(snapshot.metadata_cache()),
+                None => MetadataCache::load(&url),
+            };
+            match cached {
+                Some(cache) => Ok(cache),
+                None => client.get_entities().await.map(|mut entities| {
+                    entities.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+                    MetadataCache { entities, ..Default::default() }
+                }),
             }
-            Err(e) => {
-                self.error = Some(format!("Failed to load attributes: {}", e));
-                self.state = AppState::Error;
+        };
+        self.spawn("Loading entities", load, |app, result| {
+            match result {
+                Ok(cache) => {
+                    app.show_metadata(cache.clone());
+                    app.state = AppState::Ready;
+                    if app.client.snapshot().is_none() {
+                        app.refresh_metadata_cache(cache);
+                    }
+                }
+                Err(e) => {
+                    app.error = Some(app.describe_error("Failed to load entities", &e));
+                    app.state = AppState::Error;
+                }
+            }
+        });
+    }
+
+    /// Fetch metadata changed since the cache was saved and store it
+    fn refresh_metadata_cache(&mut self, mut cache: MetadataCache) {
+        let client = self.client.clone();
+        let url = client.environment_url();
+        let refresh = {
+            let url = url.clone();
+            async move {
+                let changed = cache.refresh(&client).await?;
+                if changed {
+                    cache.save(&url)?;
+                }
+                anyhow::Ok(changed.then_some(cache))
+            }
+        };
+        self.spawn_background("Refreshing metadata cache", refresh, move |app, result| {
+            // Ignore a refresh that finished after switching environment
+            if app.client.environment_url() != url {
                 return;
             }
-        }
+            match result {
+                Ok(Some(cache)) => app.show_metadata(cache),
+                Ok(None) => {}
+                Err(e) => app.message = Some(app.describe_error("Failed to refresh metadata cache", &e)),
+            }
+        });
+    }
 
-        if let Ok(rels) = self.client.get_entity_one_to_many(logical_name).await {
-            self.one_to_many = rels;
+    /// Replace the entity list and cached attributes, keeping the selection
+    fn show_metadata(&mut self, cache: MetadataCache) {
+        let selected = self
+            .filtered_entities
+            .get(self.entity_index)
+            .and_then(|&i| self.entities.get(i))
+            .map(|e| e.logical_name.clone());
+
+        self.entities = cache.entities;
+        self.attribute_cache.extend(cache.attributes);
+        self.filter_entities();
+        if let Some(selected) = selected {
+            self.entity_index = self
+                .filtered_entities
+                .iter()
+                .position(|&i| self.entities[i].logical_name == selected)
+                .unwrap_or(0);
         }
-        if let Ok(rels) = self.client.get_entity_many_to_one(logical_name).await {
-            self.many_to_one = rels;
+
+        // Search results hold indices into the entity list
+        if self.view == View::GlobalSearch {
+            let index = self.global_search_index;
+            self.execute_global_search();
+            self.global_search_index = index.min(self.global_search_results.len().saturating_sub(1));
+        } else {
+            self.global_search_results.clear();
         }
-        if let Ok(rels) = self.client.get_entity_many_to_many(logical_name).await {
-            self.many_to_many = rels;
+    }
+
+    /// Load entity details
+    pub fn load_entity_detail(&mut self, logical_name: &str) {
+        self.load_entity_detail_then(logical_name, |_| {});
+    }
+
+    /// Load entity details, then continue with `then` once they are shown
+    fn load_entity_detail_then(&mut self, logical_name: &str, then: impl FnOnce(&mut App) + Send + 'static) {
+        self.state = AppState::Loading;
+        self.error = None;
+
+        let client = self.client.clone();
+        let logical_name = logical_name.to_string();
+        let name = logical_name.clone();
+        let cached_attributes = self.attribute_cache.get(&logical_name).cloned();
+        self.spawn(
+            "Loading entity details",
+            async move { fetch_entity_detail(&client, &name, cached_attributes).await },
+            move |app, result| match result {
+                Ok(detail) => {
+                    app.show_entity_detail(&logical_name, detail);
+                    then(app);
+                }
+                Err(e) => {
+                    app.error = Some(app.describe_error("Failed to load attributes", &e));
+                    app.state = AppState::Error;
+                }
+            },
+        );
+    }
+
+    /// Store fetched entity details and reset the detail tabs
+    fn show_entity_detail(&mut self, logical_name: &str, detail: EntityDetail) {
+        let mut attrs = detail.attributes;
+        attrs.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        self.filtered_attributes = (0..attrs.len()).collect();
+        self.attribute_cache.insert(logical_name.to_string(), attrs.clone());
+        self.entity_attributes = attrs;
+
+        self.one_to_many = detail.one_to_many;
+        self.many_to_one = detail.many_to_one;
+        self.many_to_many = detail.many_to_many;
+        // Cached relationships are not loaded again, so a failed load must not
+        // leave empty lists behind for SQL joins
+        self.relationships_of = detail.relationships_loaded.then(|| logical_name.to_string());
+        if detail.relationships_loaded {
+            self.relationship_cache.insert(
+                logical_name.to_string(),
+                self.many_to_one.iter().chain(&self.one_to_many).cloned().collect(),
+            );
         }
 
+        self.entity_views = detail.views;
+        self.entity_view_index = 0;
+
         self.attribute_index = 0;
         self.relationship_index = 0;
         self.entity_tab = EntityTab::Attributes;
@@ -455,71 +714,81 @@ impl App {
     }
 
     /// Load solutions
-    pub async fn load_solutions(&mut self) {
+    pub fn load_solutions(&mut self) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_solutions().await {
-            Ok(solutions) => {
-                self.filtered_solutions = (0..solutions.len()).collect();
-                self.solutions = solutions;
-                self.state = AppState::Ready;
-            }
-            Err(e) => {
-                self.error = Some(format!("Failed to load solutions: {}", e));
-                self.state = AppState::Error;
+        let client = self.client.clone();
+        self.spawn("Loading solutions", async move { client.get_solutions().await }, |app, result| {
+            match result {
+                Ok(solutions) => {
+                    app.filtered_solutions = (0..solutions.len()).collect();
+                    app.solutions = solutions;
+                    app.state = AppState::Ready;
+                }
+                Err(e) => {
+                    app.error = Some(app.describe_error("Failed to load solutions", &e));
+                    app.state = AppState::Error;
+                }
             }
-        }
+        });
     }
 
     /// Load users
-    pub async fn load_users(&mut self) {
+    pub fn load_users(&mut self) {
         self.state = AppState::Loading;
         self.error = None;
 
-        let result = if self.show_disabled_users {
-            self.client.get_all_users().await
-        } else {
-            self.client.get_users().await
+        let client = self.client.clone();
+        let show_disabled = self.show_disabled_users;
+        let users = async move {
+            if show_disabled {
+                client.get_all_users().await
+            } else {
+                client.get_users().await
+            }
         };
 
-        match result {
+        self.spawn("Loading users", users, |app, result| match result {
             Ok(users) => {
-                self.filtered_users = (0..users.len()).collect();
-                self.users = users;
-                self.state = AppState::Ready;
+                app.filtered_users = (0..users.len()).collect();
+                app.users = users;
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load users: {}", e));
-                self.state = AppState::Error;
+                app.error = Some(app.describe_error("Failed to load users", &e));
+                app.state = AppState::Error;
             }
-        }
+        });
     }
 
     /// Load system jobs
-    pub async fn load_system_jobs(&mut self, filter: Option<&str>) {
+    pub fn load_system_jobs(&mut self, filter: Option<&str>) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_system_jobs(50, filter).await { // Default to top 50
+        let client = self.client.clone();
+        let filter = filter.map(str::to_string);
+        let jobs = async move { client.get_system_jobs(50, filter.as_deref()).await }; // Default to top 50
+        self.spawn("Loading system jobs", jobs, |app, result| match result {
             Ok((jobs, next_link)) => {
-                self.filtered_system_jobs = (0..jobs.len()).collect();
-                self.system_jobs = jobs;
-                self.system_jobs_next_link = next_link;
-                self.state = AppState::Ready;
+                app.filtered_system_jobs = (0..jobs.len()).collect();
+                app.system_jobs = jobs;
+                app.system_jobs_next_link = next_link;
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load system jobs: {}", e));
-                self.state = AppState::Error;
+                app.error = Some(app.describe_error("Failed to load system jobs", &e));
+                app.state = AppState::Error;
             }
-        }
+        });
     }
     
     /// Search system jobs (server-side)
-    pub async fn search_system_jobs(&mut self) {
+    pub fn search_system_jobs(&mut self) {
         let query = self.search_query.trim().to_lowercase();
         if query.is_empty() {
-             self.load_system_jobs(None).await;
+             self.load_system_jobs(None);
              return;
         }
         
@@ -541,106 +810,110 @@ impl App {
             format!("contains(name, '{}')", query)
         };
         
-        self.load_system_jobs(Some(&filter)).await;
+        self.load_system_jobs(Some(&filter));
     }
     
     /// Refresh system jobs
-    pub async fn refresh_system_jobs(&mut self) {
+    pub fn refresh_system_jobs(&mut self) {
         // If we have a search query, re-run search, otherwise load default
         if self.search_query.is_empty() {
-            self.load_system_jobs(None).await;
+            self.load_system_jobs(None);
         } else {
-            self.search_system_jobs().await;
+            self.search_system_jobs();
         }
     }
     
     /// Load more system jobs (next page)
-    pub async fn load_more_system_jobs(&mut self) {
+    pub fn load_more_system_jobs(&mut self) {
         let next_link = match &self.system_jobs_next_link {
             Some(link) => link.clone(),
             None => return,
         };
+        if self.tasks.is_running("Loading more system jobs") {
+            return;
+        }
         
         // Don't set loading state to avoid flickering, just append
-        match self.client.get_next_page_system_jobs(&next_link).await {
+        let client = self.client.clone();
+        let jobs = async move { client.get_next_page_system_jobs(&next_link).await };
+        self.spawn_background("Loading more system jobs", jobs, |app, result| match result {
             Ok((mut new_jobs, new_next_link)) => {
-                self.system_jobs.append(&mut new_jobs);
+                app.system_jobs.append(&mut new_jobs);
                 
                 // Append to filtered list if no filter is active. 
                 // If filter IS active, we should re-filter everything or just append if they match? 
                 // For simplicity, re-filter.
-                self.filter_system_jobs();
+                app.filter_system_jobs();
                 
-                self.system_jobs_next_link = new_next_link;
+                app.system_jobs_next_link = new_next_link;
             }
             Err(e) => {
-                 self.error = Some(format!("Failed to load more jobs: {}", e));
+                 app.error = Some(app.describe_error("Failed to load more jobs", &e));
                  // Don't change state to Error, just show message?
                  // For now, simple error handling
-                 self.message = Some(format!("Error loading more: {}", e));
+                 app.message = Some(format!("Error loading more: {}", e));
             }
-        }
+        });
     }
 
-    /// Load user details (roles, teams)
-    pub async fn load_user_detail(&mut self, user_id: &str) {
-        self.state = AppState::Loading;
-        self.error = None;
-
-        // Load direct roles
-        match self.client.get_user_roles(user_id).await {
-            Ok(roles) => {
-                self.user_direct_roles = roles;
-            }
-            Err(e) => {
-                self.error = Some(format!("Failed to load user roles: {}", e));
-                self.state = AppState::Error;
-                return;
-            }
-        }
+    /// Open the selected system job, fetching its full message
+    pub fn open_system_job(&mut self) {
+        let Some(&index) = self.filtered_system_jobs.get(self.system_job_index) else {
+            return;
+        };
+        let job = self.system_jobs[index].clone();
+        let id = job.id.clone();
+        self.selected_system_job = Some(job);
+        self.view = View::SystemJobDetail;
 
-        // Load teams
-        match self.client.get_user_teams(user_id).await {
-            Ok(teams) => {
-                self.user_teams = teams;
+        let client = self.client.clone();
+        self.spawn("Loading system job", async move { client.get_system_job(&id).await }, |app, result| {
+            if let Ok(details) = result {
+                app.selected_system_job = Some(details);
             }
-            Err(e) => {
-                self.error = Some(format!("Failed to load user teams: {}", e));
-                self.state = AppState::Error;
-                return;
-            }
-        }
-
-        // Build combined role list (direct + from teams)
-        self.user_all_roles.clear();
+        });
+    }
 
-        // Add direct roles
-        for role in &self.user_direct_roles {
-            self.user_all_roles.push(RoleAssignment {
-                role: role.clone(),
-                source: RoleSource::Direct,
-            });
-        }
+    /// Load user details (roles, teams)
+    pub fn load_user_detail(&mut self, user_id: &str) {
+        self.state = AppState::Loading;
+        self.error = None;
 
-        // Add team roles
-        for team in &self.user_teams {
-            if let Ok(team_roles) = self.client.get_team_roles(&team.id).await {
-                for role in team_roles {
-                    self.user_all_roles.push(RoleAssignment {
-                        role,
-                        source: RoleSource::Team(team.name.clone()),
-                    });
+        let client = self.client.clone();
+        let user_id = user_id.to_string();
+        self.spawn(
+            "Loading user details",
+            async move { fetch_user_detail(&client, &user_id).await },
+            |app, result| match result {
+                Ok((direct_roles, teams, team_roles)) => {
+                    app.user_direct_roles = direct_roles;
+                    app.user_teams = teams;
+
+                    // Build combined role list (direct + from teams)
+                    app.user_all_roles = app
+                        .user_direct_roles
+                        .iter()
+                        .map(|role| RoleAssignment {
+                            role: role.clone(),
+                            source: RoleSource::Direct,
+                        })
+                        .chain(team_roles)
+                        .collect();
+
+                    // Sort by role name
+                    app.user_all_roles.sort_by(|a, b| a.role.name.cmp(&b.role.name));
+
+                    app.user_role_index = 0;
+                    app.user_team_index = 0;
+                    app.user_tab = UserTab::DirectRoles;
+                    app.state = AppState::Ready;
                 }
-            }
-        }
-
-        // Sort by role name
-        self.user_all_roles.sort_by(|a, b| a.role.name.cmp(&b.role.name));
-
-        self.user_role_index = 0;
-        self.user_team_index = 0;
-        self.user_tab = UserTab::DirectRoles;
-        self.state = AppState::Ready;
+                Err((context, e)) => {
+                    app.error = Some(app.describe_error(context, &e));
+                    app.state = AppState::Error;
+                }
+            },
+        );
     }
 
     /// Apply search filter to entities
@@ -764,6 +1037,11 @@ impl App {
                     }
                 }
                 EntityTab::Metadata => {}
+                EntityTab::Views => {
+                    if self.entity_view_index > 0 {
+                        self.entity_view_index -= 1;
+                    }
+                }
                 EntityTab::Query => {
                     match self.query_mode {
                         QueryMode::Columns => {
@@ -833,6 +1111,22 @@ impl App {
                     self.environment_index -= 1;
                 }
             }
+            View::SavedQueries => {
+                if self.saved_query_index > 0 {
+                    self.saved_query_index -= 1;
+                }
+            }
+            View::History => {
+                if self.history_index > 0 {
+                    self.history_index -= 1;
+                }
+            }
+            View::SchemaDiff => {
+                if self.schema_diff_index > 0 {
+                    self.schema_diff_index -= 1;
+                }
+            }
+            View::RelationshipGraph => self.relationship_graph.move_up(),
             View::FetchXML => {}
             View::SolutionLayers => {
                 if self.solution_layers_index > 0 {
@@ -878,6 +1172,11 @@ impl App {
                     }
                 }
                 EntityTab::Metadata => {}
+                EntityTab::Views => {
+                    if self.entity_view_index + 1 < self.entity_views.len() {
+                        self.entity_view_index += 1;
+                    }
+                }
                 EntityTab::Query => {
                     match self.query_mode {
                         QueryMode::Columns => {
@@ -1000,6 +1299,23 @@ impl App {
                     self.environment_index += 1;
                 }
             }
+            View::SavedQueries => {
+                if self.saved_query_index + 1 < self.saved_query_indices().len() {
+                    self.saved_query_index += 1;
+                }
+            }
+            View::History => {
+                if self.history_index + 1 < self.filtered_history.len() {
+                    self.history_index += 1;
+                }
+            }
+            View::SchemaDiff => {
+                let rows = self.schema_diff.as_ref().map_or(0, |d| d.rows().len());
+                if self.schema_diff_index + 1 < rows {
+                    self.schema_diff_index += 1;
+                }
+            }
+            View::RelationshipGraph => self.relationship_graph.move_down(),
             View::FetchXML => {}
             View::SolutionLayers => {
                 if !self.solution_layers.is_empty()
@@ -1019,7 +1335,8 @@ impl App {
                     EntityTab::Attributes => EntityTab::Relationships,
                     EntityTab::Relationships => EntityTab::Metadata,
                     EntityTab::Metadata => EntityTab::Query,
-                    EntityTab::Query => EntityTab::Attributes,
+                    EntityTab::Query => EntityTab::Views,
+                    EntityTab::Views => EntityTab::Attributes,
                 };
             }
             View::UserDetail => {
@@ -1032,6 +1349,7 @@ impl App {
                 self.user_role_index = 0;
                 self.user_team_index = 0;
             }
+            View::RelationshipGraph => self.relationship_graph.move_right(),
             _ => {}
         }
     }
@@ -1041,10 +1359,11 @@ impl App {
         match self.view {
             View::EntityDetail => {
                 self.entity_tab = match self.entity_tab {
-                    EntityTab::Attributes => EntityTab::Query,
+                    EntityTab::Attributes => EntityTab::Views,
                     EntityTab::Relationships => EntityTab::Attributes,
                     EntityTab::Metadata => EntityTab::Relationships,
                     EntityTab::Query => EntityTab::Metadata,
+                    EntityTab::Views => EntityTab::Query,
                 };
             }
             View::UserDetail => {
@@ -1057,6 +1376,7 @@ impl App {
                 self.user_role_index = 0;
                 self.user_team_index = 0;
             }
+            View::RelationshipGraph => self.relationship_graph.move_left(),
             _ => {}
         }
     }
@@ -1128,7 +1448,7 @@ impl App {
     }
 
     /// Navigate to a related record from the current record detail view
-    pub async fn navigate_to_related_record(&mut self) {
+    pub fn navigate_to_related_record(&mut self) {
         let Some(row_idx) = self.selected_record_index else { return; };
         let col_idx = self.record_detail_index;
         
@@ -1142,62 +1462,65 @@ impl App {
         let target_entity = self.entities.iter().find(|e| e.logical_name == lookup.logical_name).cloned();
         
         if let Some(entity) = target_entity {
-            // 2. Load entity detail (attributes, etc)
-            self.load_entity_detail(&entity.logical_name).await;
-            self.selected_entity = Some(entity);
-            
-            // 3. Fetch the specific record
-            let logical_name = self.selected_entity.as_ref().unwrap().logical_name.clone();
-            let entity_set = self.selected_entity.as_ref().unwrap().entity_set_name.clone().unwrap_or_else(|| {
+            // 2. Load entity detail (attributes, etc), then fetch the specific record
+            let logical_name = entity.logical_name.clone();
+            let entity_set = entity.entity_set_name.clone().unwrap_or_else(|| {
                 format!("{}s", logical_name)
             });
             let url = format!("{}({})", entity_set, lookup.id);
-            
-            self.state = AppState::Loading;
-            self.error = None;
-            
-            match self.client.execute_query(&url).await {
-                Ok(json) => {
-                    // Wrap single object in a result format
-                    let mut wrapped = serde_json::Map::new();
-                    wrapped.insert("value".to_string(), serde_json::Value::Array(vec![(json.clone())]));
-                    let wrapped_json = serde_json::Value::Object(wrapped);
-                    
-                    self.query_result = QueryResult::from_json(&wrapped_json);
-                    self.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
-                    
-                    self.selected_record_index = Some(0);
-                    self.record_detail_index = 0;
-                    self.view = View::RecordDetail;
-                    self.state = AppState::Ready;
-                }
-                Err(e) => {
-                    self.error = Some(format!("Failed to fetch related record: {}", e));
-                    self.state = AppState::Ready;
-                }
-            }
+            self.selected_entity = Some(entity);
+
+            self.load_entity_detail_then(&logical_name, move |app| {
+                app.state = AppState::Loading;
+                let client = app.client.clone();
+                app.spawn("Loading related record", async move { client.execute_query(&url).await }, |app, result| {
+                    match result {
+                        Ok(json) => {
+                            // Wrap single object in a result format
+                            let mut wrapped = serde_json::Map::new();
+                            wrapped.insert("value".to_string(), serde_json::Value::Array(vec![(json.clone())]));
+                            let wrapped_json = serde_json::Value::Object(wrapped);
+                            
+                            app.query_result = QueryResult::from_json(&wrapped_json);
+                            app.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
+                            
+                            app.selected_record_index = Some(0);
+                            app.record_detail_index = 0;
+                            app.view = View::RecordDetail;
+                            app.state = AppState::Ready;
+                        }
+                        Err(e) => {
+                            app.error = Some(app.describe_error("Failed to fetch related record", &e));
+                            app.state = AppState::Ready;
+                        }
+                    }
+                });
+            });
         } else {
             self.error = Some(format!("Entity metadata not found for: {}", lookup.logical_name));
         }
     }
 
     /// Load solution details (components)
-    pub async fn load_solution_detail(&mut self, solution_id: &str) {
+    pub fn load_solution_detail(&mut self, solution_id: &str) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_solution_components(solution_id).await {
+        let client = self.client.clone();
+        let solution_id = solution_id.to_string();
+        let components = async move { client.get_solution_components(&solution_id).await };
+        self.spawn("Loading solution components", components, |app, result| match result {
             Ok(components) => {
-                self.solution_components = components;
-                self.filter_solution_components();
-                self.component_index = 0;
-                self.state = AppState::Ready;
+                app.solution_components = components;
+                app.filter_solution_components();
+                app.component_index = 0;
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load solution components: {}", e));
-                self.state = AppState::Error;
+                app.error = Some(app.describe_error("Failed to load solution components", &e));
+                app.state = AppState::Error;
             }
-        }
+        });
     }
 
     /// Get selected solution component
@@ -1208,7 +1531,7 @@ impl App {
     }
 
     /// Jump to the selected component if possible
-    pub async fn jump_to_component(&mut self) -> bool {
+    pub fn jump_to_component(&mut self) -> bool {
         let Some(comp) = self.get_selected_solution_component().cloned() else { return false; };
         match comp.get_component_type() {
             ComponentType::Entity => {
@@ -1219,10 +1542,10 @@ impl App {
                     .map(|e| e.logical_name.clone());
                 
                 if let Some(logical_name) = entity_logical_name {
-                    self.load_entity_detail(&logical_name).await;
                     self.selected_entity = self.entities.iter().find(|e| e.logical_name == logical_name).cloned();
                     self.view = View::EntityDetail;
                     self.entity_tab = EntityTab::Attributes;
+                    self.load_entity_detail(&logical_name);
                     return true;
                 }
             }
@@ -1256,21 +1579,23 @@ impl App {
     }
 
     /// Load global option sets
-    pub async fn load_global_optionsets(&mut self) {
+    pub fn load_global_optionsets(&mut self) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_global_option_sets().await {
+        let client = self.client.clone();
+        let optionsets = async move { client.get_global_option_sets().await };
+        self.spawn("Loading option sets", optionsets, |app, result| match result {
             Ok(optionsets) => {
-                self.global_optionsets = optionsets;
-                self.filter_optionsets();
-                self.state = AppState::Ready;
+                app.global_optionsets = optionsets;
+                app.filter_optionsets();
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load global option sets: {}", e));
-                self.state = AppState::Error;
+                app.error = Some(app.describe_error("Failed to load global option sets", &e));
+                app.state = AppState::Error;
             }
-        }
+        });
     }
 
     /// Filter global option sets
@@ -1330,26 +1655,41 @@ impl App {
             }
         }
 
+        // 4. Attributes of every entity in the metadata cache
+        for entity in &self.entities {
+            let Some(attributes) = self.attribute_cache.get(&entity.logical_name) else { continue; };
+            for attr in attributes {
+                if attr.logical_name.to_lowercase().contains(&query)
+                    || attr.get_display_name().to_lowercase().contains(&query)
+                {
+                    results.push(SearchResult::Attribute {
+                        entity: entity.logical_name.clone(),
+                        attribute: attr.logical_name.clone(),
+                    });
+                }
+            }
+        }
+
         self.global_search_results = results;
         self.global_search_index = 0;
         self.view = View::GlobalSearch;
     }
 
     /// Enter the selected search result
-    pub async fn enter_search_result(&mut self) {
+    pub fn enter_search_result(&mut self) {
         let Some(result) = self.global_search_results.get(self.global_search_index).cloned() else { return; };
         match result {
             SearchResult::Entity(idx) => {
                 let logical_name = self.entities[idx].logical_name.clone();
-                self.load_entity_detail(&logical_name).await;
                 self.selected_entity = Some(self.entities[idx].clone());
                 self.view = View::EntityDetail;
+                self.load_entity_detail(&logical_name);
             }
             SearchResult::Solution(idx) => {
                 let solution_id = self.solutions[idx].solution_id.clone();
-                self.load_solution_detail(&solution_id).await;
                 self.selected_solution = Some(self.solutions[idx].clone());
                 self.view = View::SolutionDetail;
+                self.load_solution_detail(&solution_id);
             }
             SearchResult::OptionSet(idx) => {
                 self.optionset_index = idx;
@@ -1364,6 +1704,16 @@ impl App {
                 }
                 self.view = View::OptionSets;
             }
+            SearchResult::Attribute { entity, attribute } => {
+                let Some(selected) = self.entities.iter().find(|e| e.logical_name == entity).cloned() else { return; };
+                self.selected_entity = Some(selected);
+                self.view = View::EntityDetail;
+                self.load_entity_detail_then(&entity, move |app| {
+                    if let Some(index) = app.entity_attributes.iter().position(|a| a.logical_name == attribute) {
+                        app.attribute_index = index;
+                    }
+                });
+            }
         }
     }
 
@@ -1386,6 +1736,12 @@ impl App {
                 self.view = View::EntityDetail;
                 self.selected_record_index = None;
             }
+            View::SavedQueries | View::History | View::SchemaDiff => {
+                self.view = View::Entities;
+            }
+            View::RelationshipGraph => {
+                self.view = self.relationship_graph.opened_from;
+            }
             View::SolutionLayers => {
                 // Return to whatever made sense before.
                 // If we have a selected solution detail, go there.
@@ -1403,7 +1759,7 @@ impl App {
     }
 
     /// Build and execute query from guided selections
-    pub async fn execute_guided_query(&mut self) {
+    pub fn execute_guided_query(&mut self) {
         let Some(entity) = &self.selected_entity else {
             self.query_result.error = Some("No entity selected".to_string());
             return;
@@ -1458,166 +1814,240 @@ impl App {
 
         self.state = AppState::Loading;
         self.error = None;
+        let started = Instant::now();
 
-        match self.client.execute_query(&url).await {
-            Ok(json) => {
-                self.query_result = QueryResult::from_json(&json);
-                self.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
-                self.query_result_index = 0;
-                self.query_mode = QueryMode::Results;
-                self.state = AppState::Ready;
-            }
-            Err(e) => {
-                self.query_result.error = Some(format!("Query failed: {}", e));
-                self.state = AppState::Ready;
-            }
-        }
+        let client = self.client.clone();
+        let request = url.clone();
+        self.spawn("Running query", async move { client.execute_query(&request).await }, move |app, result| {
+            let error = match result {
+                Ok(json) => {
+                    app.query_result = QueryResult::from_json(&json);
+                    app.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
+                    app.query_result_index = 0;
+                    app.query_mode = QueryMode::Results;
+                    app.state = AppState::Ready;
+                    None
+                }
+                Err(e) => {
+                    app.query_result.error = Some(app.describe_error("Query failed", &e));
+                    app.state = AppState::Ready;
+                    Some(e.to_string())
+                }
+            };
+            let entity = app.selected_entity.as_ref().map(|e| e.logical_name.clone());
+            app.record_history(HistoryKind::Guided, url, entity, started, error);
+        });
     }
 
-    /// Execute SQL query from the console
-    pub async fn execute_sql_query(&mut self) {
+    /// Byte offset of the SQL console cursor
+    pub fn sql_byte_index(&self) -> usize {
+        self.sql_query
+            .char_indices()
+            .nth(self.sql_cursor)
+            .map_or(self.sql_query.len(), |(i, _)| i)
+    }
+
+    /// Execute SQL query from the console and record it in the history
+    pub fn execute_sql_query(&mut self) {
+        self.history_recall = None;
         if self.sql_query.trim().is_empty() {
-             self.error = Some("Empty query".to_string());
-             return;
+            self.sql_error = Some(crate::sql::SqlError::new("Empty query", 0));
+            return;
         }
 
-        self.state = AppState::Loading;
-        self.error = None;
-        self.message = Some("Executing SQL query...".to_string());
+        self.run_sql_query(self.sql_query.clone(), Instant::now());
+    }
+
+    fn run_sql_query(&mut self, sql: String, started: Instant) {
+        self.sql_error = None;
 
-        let odata_query = match self.translate_sql_to_odata(&self.sql_query) {
-            Ok(query) => query,
+        let stmt = match crate::sql::parse(&sql) {
+            Ok(stmt) => stmt,
             Err(e) => {
-                self.error = Some(format!("SQL Error: {}", e));
-                self.state = AppState::Ready;
+                self.sql_error = Some(e);
+                self.finish_sql_query(sql, None, started);
                 return;
             }
         };
 
-        match self.client.execute_query(&odata_query).await {
-            Ok(json) => {
-                self.query_result = QueryResult::from_json(&json);
-                self.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
-                self.query_result_index = 0;
-                self.query_mode = QueryMode::Results;
-                self.state = AppState::Ready;
-                self.message = None;
-            }
+        let logical_names: Vec<String> = match crate::sql::resolve_entities(&stmt, &self.entities) {
+            Ok(entities) => entities.iter().map(|e| e.logical_name.clone()).collect(),
             Err(e) => {
-                self.query_result.error = Some(format!("Query failed: {}", e));
-                self.state = AppState::Ready;
-                self.message = None;
+                self.sql_error = Some(e);
+                self.finish_sql_query(sql, None, started);
+                return;
             }
-        }
-    }
+        };
 
-    /// Basic SQL to OData translator
-    fn translate_sql_to_odata(&self, sql: &str) -> anyhow::Result<String> {
-        let sql = sql.trim();
-        let tokens: Vec<&str> = sql.split_whitespace().collect();
-        
-        if tokens.len() < 4 || tokens[0].to_lowercase() != "select" {
-            return Err(anyhow::anyhow!("Invalid SQL. Must start with SELECT."));
-        }
+        self.state = AppState::Loading;
+        self.error = None;
+        self.message = Some("Executing SQL query...".to_string());
 
-        // Extremely basic parser for: SELECT [cols] FROM [entity] WHERE [filter]
-        let mut i = 1;
-        let mut select_cols = Vec::new();
-        
-        // Parse SELECT columns
-        while i < tokens.len() && tokens[i].to_lowercase() != "from" {
-            let col = tokens[i].trim_matches(',');
-            if !col.is_empty() {
-                select_cols.push(col.to_string());
+        let entity = logical_names.first().cloned();
+        let joins = logical_names.len() > 1;
+        self.load_query_metadata(logical_names, joins, move |app, loaded| {
+            if let Err((logical_name, e)) = loaded {
+                app.query_result.error = Some(app.describe_error(&format!("Failed to load metadata for {}", logical_name), &e));
+                app.state = AppState::Ready;
+                app.message = None;
+                app.finish_sql_query(sql, entity, started);
+                return;
             }
-            i += 1;
-        }
-
-        if i >= tokens.len() || tokens[i].to_lowercase() != "from" {
-            return Err(anyhow::anyhow!("Missing FROM clause"));
-        }
-        i += 1;
 
-        if i >= tokens.len() {
-            return Err(anyhow::anyhow!("Missing entity name after FROM"));
-        }
-        
-        let entity_name = tokens[i];
-        let entity_name_lower = entity_name.to_lowercase();
-        i += 1;
-
-        // Try to find entity set name if it's the logical name
-        let entity_set = self.entities.iter()
-            .find(|e| e.logical_name == entity_name_lower || e.get_display_name().to_lowercase() == entity_name_lower)
-            .and_then(|e| e.entity_set_name.clone())
-            .unwrap_or_else(|| {
-                // Heuristic: append 's'
-                format!("{}s", entity_name_lower)
+            let schema = crate::sql::Schema {
+                entities: &app.entities,
+                attributes: &app.attribute_cache,
+                relationships: &app.relationship_cache,
+            };
+            let compiled = match crate::sql::compile(&stmt, &schema) {
+                Ok(compiled) => compiled,
+                Err(e) => {
+                    app.sql_error = Some(e);
+                    app.state = AppState::Ready;
+                    app.message = None;
+                    app.finish_sql_query(sql, entity, started);
+                    return;
+                }
+            };
+
+            let client = app.client.clone();
+            let query = async move {
+                match compiled {
+                    crate::sql::CompiledQuery::OData(query) => {
+                        client.execute_query(&query.build_url()).await.map(|json| {
+                            let mut result = QueryResult::from_json(&json);
+                            result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
+                            result
+                        })
+                    }
+                    crate::sql::CompiledQuery::FetchXml { entity_set_name, fetch_xml } => {
+                        client.execute_fetch_xml(&entity_set_name, &fetch_xml).await
+                    }
+                }
+            };
+            app.spawn("Running SQL query", query, move |app, result| {
+                match result {
+                    Ok(result) => {
+                        app.query_result = result;
+                        app.query_result_index = 0;
+                        app.query_mode = QueryMode::Results;
+                    }
+                    Err(e) => {
+                        app.query_result.error = Some(app.describe_error("Query failed", &e));
+                    }
+                }
+                app.state = AppState::Ready;
+                app.message = None;
+                app.finish_sql_query(sql, entity, started);
             });
+        });
+    }
 
-        let mut parts = Vec::new();
-        
-        // Handle SELECT *
-        if select_cols.len() == 1 && select_cols[0] == "*" {
-            // Include nothing in $select to get all
-        } else if !select_cols.is_empty() {
-            parts.push(format!("$select={}", select_cols.join(",")));
-        }
+    /// Record a finished SQL query in the history under its `FROM` entity
+    ///
+    /// Reopens the editor when the statement itself needs fixing.
+    fn finish_sql_query(&mut self, sql: String, entity: Option<String>, started: Instant) {
+        let error = self.sql_error.as_ref().map(|e| e.to_string()).or_else(|| self.query_result.error.clone());
+        self.record_history(HistoryKind::Sql, sql, entity, started, error);
 
-        // Parse WHERE
-        if i < tokens.len() && tokens[i].to_lowercase() == "where" {
-            i += 1;
-            let filter_content = tokens[i..].join(" ");
-            
-            // Translate common SQL operators to OData
-            let filter = filter_content
-                .replace(" = ", " eq ")
-                .replace(" != ", " ne ")
-                .replace(" <> ", " ne ")
-                .replace(" > ", " gt ")
-                .replace(" >= ", " ge ")
-                .replace(" < ", " lt ")
-                .replace(" <= ", " le ")
-                .replace(" LIKE ", " contains ") // Simple approximation
-                .replace(" AND ", " and ")
-                .replace(" OR ", " or ")
-                .replace(" NOT ", " not ");
-                
-            parts.push(format!("$filter={}", filter));
+        if self.sql_error.is_some() && self.query_mode == QueryMode::Sql && self.input_mode == InputMode::Normal {
+            self.input_mode = InputMode::SQLQuery;
         }
+    }
 
-        if parts.is_empty() {
-            Ok(entity_set)
-        } else {
-            Ok(format!("{}?{}", entity_set, parts.join("&")))
-        }
+    /// Fetch attribute metadata missing from the cache, and relationships
+    /// too if `relationships` is set, then continue with `then`
+    ///
+    /// `then` receives the entity that failed to load, if any.
+    fn load_query_metadata<F>(&mut self, logical_names: Vec<String>, relationships: bool, then: F)
+    where
+        F: FnOnce(&mut App, Result<(), (String, anyhow::Error)>) + Send + 'static,
+    {
+        let missing_attributes: Vec<String> = logical_names
+            .iter()
+            .filter(|name| !self.attribute_cache.contains_key(*name))
+            .cloned()
+            .collect();
+        let missing_relationships: Vec<String> = logical_names
+            .into_iter()
+            .filter(|name| relationships && !self.relationship_cache.contains_key(name))
+            .collect();
+
+        let client = self.client.clone();
+        let fetch = async move {
+            let mut attributes = Vec::new();
+            for name in missing_attributes {
+                match client.get_entity_attributes(&name).await {
+                    Ok(attrs) => attributes.push((name, attrs)),
+                    Err(e) => return Err((name, e)),
+                }
+            }
+            let mut relationships = Vec::new();
+            for name in missing_relationships {
+                let rels = tokio::try_join!(
+                    client.get_entity_many_to_one(&name),
+                    client.get_entity_one_to_many(&name),
+                );
+                match rels {
+                    Ok((mut many_to_one, one_to_many)) => {
+                        many_to_one.extend(one_to_many);
+                        relationships.push((name, many_to_one));
+                    }
+                    Err(e) => return Err((name, e)),
+                }
+            }
+            Ok((attributes, relationships))
+        };
+
+        self.spawn("Loading metadata", fetch, move |app, result| match result {
+            Ok((attributes, relationships)) => {
+                app.attribute_cache.extend(attributes);
+                app.relationship_cache.extend(relationships);
+                then(app, Ok(()));
+            }
+            Err(failed) => then(app, Err(failed)),
+        });
     }
 
     /// Load next page of query results
-    pub async fn load_next_page(&mut self) {
-        let Some(next_link) = self.query_result.next_link.clone() else {
+    ///
+    /// OData queries follow `@odata.nextLink`; FetchXML queries are re-sent
+    /// with the next page number and paging cookie.
+    pub fn load_next_page(&mut self) {
+        if !self.query_result.has_more() {
             return;
-        };
+        }
 
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.execute_query(&next_link).await {
-            Ok(json) => {
-                let next_result = QueryResult::from_json(&json);
-                
-                // Append new rows
-                self.query_result.rows.extend(next_result.rows);
-                self.query_result.next_link = next_result.next_link;
-                self.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
-                
-                self.state = AppState::Ready;
+        let client = self.client.clone();
+        let next_link = self.query_result.next_link.clone();
+        let paging = self.query_result.fetch_paging.clone();
+        let next = async move {
+            if let Some(next_link) = next_link {
+                client.execute_query(&next_link).await.map(|json| {
+                    let mut result = QueryResult::from_json(&json);
+                    result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
+                    result
+                })
+            } else {
+                let paging = paging.context("No further page to load")?;
+                let fetch_xml = crate::fetchxml::set_paging(&paging.fetch_xml, paging.page, paging.paging_cookie.as_deref())?;
+                client.execute_fetch_xml(&paging.entity_set_name, &fetch_xml).await
+            }
+        };
+
+        self.spawn("Loading next page", next, |app, result| match result {
+            Ok(next_result) => {
+                app.query_result.append(next_result);
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load next page: {}", e));
-                self.state = AppState::Ready;
+                app.query_result.error = Some(app.describe_error("Failed to load next page", &e));
+                app.state = AppState::Ready;
             }
-        }
+        });
     }
 
     /// Toggle column selection at current index
@@ -1721,67 +2151,105 @@ impl App {
         self.message = None;
     }
 
+    /// Message for a failed operation, keeping the full error for the details popup
+    pub fn describe_error(&mut self, context: &str, e: &anyhow::Error) -> String {
+        let mut details = vec![context.to_string(), String::new()];
+        match e.downcast_ref::<crate::api::DataverseError>() {
+            Some(error) => {
+                details.push(format!("Status:  {}", error.status));
+                if let Some(code) = &error.code {
+                    details.push(format!("Code:    {}", code));
+                }
+                details.push(format!("Summary: {}", error.summary()));
+                details.push(String::new());
+                details.push(error.message.clone());
+                if let Some(inner) = &error.inner {
+                    details.push(String::new());
+                    details.push("Inner exception:".to_string());
+                    details.push(inner.clone());
+                }
+            }
+            None => details.extend(e.chain().map(|cause| cause.to_string())),
+        }
+        self.error_details = Some(details.join("\n"));
+        self.error_details_scroll = 0;
+        format!("{}: {} (press ! for details)", context, e)
+    }
+
     /// Switch to a different environment
-    pub async fn switch_environment(&mut self, url: &str) -> anyhow::Result<()> {
+    pub fn switch_environment(&mut self, url: &str) {
         self.state = AppState::Loading;
         self.error = None;
         self.message = Some(format!("Connecting to {}...", url));
 
-        let authenticator = std::sync::Arc::new(
-            crate::auth::AzureAuthenticator::new(url)
-                .await
-                .context("Failed to create Azure authenticator")?,
-        );
-
-        if let Err(e) = authenticator.test_connection().await {
-            self.error = Some(format!("Connection failed: {}", e));
-            self.state = AppState::Error;
-            return Err(anyhow::anyhow!("Connection failed: {}", e));
-        }
-
-        self.client = std::sync::Arc::new(crate::api::DataverseClient::new(authenticator));
-        
-        // Update config
-        self.config.current_env = Some(url.to_string());
-        let _ = self.config.save();
-
-        // Reload data
-        self.load_entities().await;
-        self.view = View::Entities;
-        self.message = Some(format!("Switched to {}", url));
-        self.state = AppState::Ready;
-        
-        Ok(())
-    }
+        let settings = self.config.auth_for(url);
+        let url = url.to_string();
+        let connect = {
+            let url = url.clone();
+            async move {
+                let authenticator = crate::auth::AzureAuthenticator::with_settings(url, &settings)
+                    .context("Failed to create Azure authenticator")?;
+                authenticator.test_connection().await?;
+                anyhow::Ok(authenticator)
+            }
+        };
 
-    /// Add a new environment
-    pub async fn add_new_environment(&mut self, url: String) -> anyhow::Result<()> {
-        self.config.add_environment(url.clone());
-        let _ = self.config.save();
-        self.switch_environment(&url).await
+        self.spawn("Connecting", connect, move |app, result| {
+            let authenticator = match result {
+                Ok(authenticator) => authenticator,
+                Err(e) => {
+                    app.error = Some(app.describe_error("Connection failed", &e));
+                    app.state = AppState::Error;
+                    return;
+                }
+            };
+
+            app.client = std::sync::Arc::new(
+                crate::api::DataverseClient::new(std::sync::Arc::new(authenticator))
+                    .with_retry_policy(app.config.retry),
+            );
+            app.attribute_cache.clear();
+            app.relationship_cache.clear();
+            app.relationships_of = None;
+            app.relationship_graph = RelationshipGraph::default();
+
+            // Update config
+            app.config.current_env = Some(url.clone());
+            let _ = app.config.save();
+            let profile = app.config.current_profile();
+            if !app.vim_flag {
+                app.key_bindings = profile.and_then(|p| p.key_bindings).unwrap_or(KeyBindings::Arrows);
+            }
+            let name = profile.map_or(url.as_str(), |p| p.display_name()).to_string();
+
+            // Reload data
+            app.view = View::Entities;
+            app.message = Some(format!("Switched to {}", name));
+            app.load_entities();
+        });
     }
 
     /// Discover available environments using Global Discovery Service
-    pub async fn discover_environments(&mut self) -> anyhow::Result<()> {
+    pub fn discover_environments(&mut self) {
         self.state = AppState::Loading;
         self.message = Some("Discovering environments...".to_string());
         
-        match self.client.discover_environments().await {
+        let client = self.client.clone();
+        let instances = async move { client.discover_environments().await };
+        self.spawn("Discovering environments", instances, |app, result| match result {
             Ok(instances) => {
-                self.discovered_environments = instances;
-                self.filter_discovered_environments();
-                self.view = View::EnvironmentDiscovery;
-                self.discovery_index = 0;
-                self.state = AppState::Ready;
-                self.message = None;
-                Ok(())
+                app.discovered_environments = instances;
+                app.filter_discovered_environments();
+                app.view = View::EnvironmentDiscovery;
+                app.discovery_index = 0;
+                app.state = AppState::Ready;
+                app.message = None;
             }
             Err(e) => {
-                self.error = Some(format!("Discovery failed: {}", e));
-                self.state = AppState::Ready;
-                Err(e)
+                app.error = Some(app.describe_error("Discovery failed", &e));
+                app.state = AppState::Ready;
             }
-        }
+        });
     }
 
     /// Filter discovered environments
@@ -1806,94 +2274,782 @@ impl App {
     }
 
     /// Add selected discovered environment to config and switch
-    pub async fn add_selected_discovery(&mut self) -> anyhow::Result<()> {
+    pub fn add_selected_discovery(&mut self) {
         let Some(idx) = self.filtered_discovery_results.get(self.discovery_index) else {
-            return Ok(());
+            return;
         };
         
         let instance = &self.discovered_environments[*idx];
         let url = instance.url.trim_end_matches('/').to_string();
-        
-        self.add_new_environment(url).await
+        let name = instance.friendly_name.clone();
+
+        self.config.add_environment(url.clone());
+        if let Some(profile) = self.config.profile_mut(&url) {
+            profile.name.get_or_insert(name);
+        }
+        let _ = self.config.save();
+        self.switch_environment(&url)
     }
 
     /// Load solution layers for the current component
-    pub async fn load_solution_layers(&mut self, component_id: &str, component_type: i32) {
+    pub fn load_solution_layers(&mut self, component_id: &str, component_type: i32) {
         self.state = AppState::Loading;
         self.error = None;
 
-        match self.client.get_solution_layers(component_id, component_type).await {
+        let client = self.client.clone();
+        let component_id = component_id.to_string();
+        let layers = async move { client.get_solution_layers(&component_id, component_type).await };
+        self.spawn("Loading solution layers", layers, |app, result| match result {
             Ok(layers) => {
-                self.solution_layers = layers;
-                self.solution_layers_index = 0;
-                self.view = View::SolutionLayers;
-                self.state = AppState::Ready;
+                app.solution_layers = layers;
+                app.solution_layers_index = 0;
+                app.view = View::SolutionLayers;
+                app.state = AppState::Ready;
             }
             Err(e) => {
-                self.error = Some(format!("Failed to load solution layers: {}", e));
-                self.state = AppState::Ready;
+                app.error = Some(app.describe_error("Failed to load solution layers", &e));
+                app.state = AppState::Ready;
             }
-        }
+        });
     }
 
-    /// Execute the FetchXML query currently in the input
-    pub async fn execute_fetch_xml_query(&mut self) {
-        if self.fetchxml_query.is_empty() {
+    /// Compare the schema of the current environment with another
+    /// environment or a snapshot file
+    ///
+    /// Live environments are read in full first, which takes a while on
+    /// large environments.
+    pub fn compare_with(&mut self, other: CompareSource) {
+        self.state = AppState::Loading;
+        self.error = None;
+        self.message = Some("Reading both schemas...".to_string());
+
+        let client = self.client.clone();
+        let settings = match &other {
+            CompareSource::Environment(url) => Some(self.config.auth_for(url)),
+            CompareSource::Snapshot(_) => None,
+        };
+        let retry = self.config.retry;
+        let compare = async move {
+            let current = async {
+                match client.snapshot() {
+                    Some(snapshot) => Ok(snapshot.clone()),
+                    None => Snapshot::capture(&client).await,
+                }
+            };
+            let other = async {
+                match other {
+                    CompareSource::Snapshot(path) => Snapshot::load(&path),
+                    CompareSource::Environment(url) => {
+                        let authenticator =
+                            crate::auth::AzureAuthenticator::with_settings(url, &settings.unwrap_or_default())
+                                .context("Failed to create Azure authenticator")?;
+                        let client = DataverseClient::new(Arc::new(authenticator)).with_retry_policy(retry);
+                        Snapshot::capture(&client).await
+                    }
+                }
+            };
+            let (current, other) = tokio::try_join!(current, other)?;
+            anyhow::Ok(SchemaDiff::compare(&current, &other))
+        };
+
+        self.spawn("Comparing schemas", compare, |app, result| match result {
+            Ok(diff) => {
+                app.message = Some(format!("{} differences", diff.change_count()));
+                app.schema_diff = Some(diff);
+                app.schema_diff_index = 0;
+                app.view = View::SchemaDiff;
+                app.state = AppState::Ready;
+            }
+            Err(e) => {
+                app.error = Some(app.describe_error("Schema comparison failed", &e));
+                app.state = AppState::Ready;
+            }
+        });
+    }
+
+    /// Export the schema diff as Markdown and JSON
+    pub fn export_schema_diff(&mut self) {
+        let Some(diff) = &self.schema_diff else {
             return;
+        };
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let path = format!("exports/schema_diff_{}", timestamp);
+        let write = || -> anyhow::Result<()> {
+            std::fs::create_dir_all("exports")?;
+            std::fs::write(format!("{}.md", path), diff.to_markdown())?;
+            std::fs::write(format!("{}.json", path), serde_json::to_string_pretty(diff)?)?;
+            Ok(())
+        };
+        match write() {
+            Ok(()) => self.message = Some(format!("Exported to {}.md and {}.json", path, path)),
+            Err(e) => self.message = Some(format!("Export failed: {}", e)),
         }
+    }
+
+    /// Write relationship diagrams of the selected entity, or of the entities
+    /// in the open solution, in every format
+    pub fn export_erd(&mut self) {
+        let (name, solution_id) = match self.view {
+            View::Entities => match self.get_selected_entity() {
+                Some(entity) => (entity.logical_name.clone(), None),
+                None => return,
+            },
+            View::EntityDetail => match &self.selected_entity {
+                Some(entity) => (entity.logical_name.clone(), None),
+                None => return,
+            },
+            View::SolutionDetail => match &self.selected_solution {
+                Some(solution) => (solution.unique_name.clone(), Some(solution.solution_id.clone())),
+                None => return,
+            },
+            _ => return,
+        };
+
+        let client = self.client.clone();
+        let settings = self.config.erd.clone();
+        let path = format!("exports/erd_{}_{}", name, chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        let draw = async move {
+            let roots = match solution_id {
+                Some(id) => erd::solution_entities(&client, &id).await?,
+                None => vec![name],
+            };
+            let diagram = Erd::collect(&client, &roots, &settings).await?;
+            std::fs::create_dir_all("exports")?;
+            for format in ErdFormat::ALL {
+                std::fs::write(format!("{}.{}", path, format.extension()), diagram.render(format))?;
+            }
+            anyhow::Ok((diagram.entities.len(), path))
+        };
+        self.spawn_background("Drawing relationship diagram", draw, |app, result| match result {
+            Ok((count, path)) => {
+                app.message = Some(format!("Wrote {} entities to {}.mmd, .dot and .puml", count, path))
+            }
+            Err(e) => app.message = Some(app.describe_error("Failed to draw relationship diagram", &e)),
+        });
+    }
+
+    /// Open the relationship graph on the selected entity
+    pub fn open_relationship_graph(&mut self) {
+        let entity = match self.view {
+            View::Entities => self.get_selected_entity().map(|e| e.logical_name.clone()),
+            View::EntityDetail => {
+                // Reuse the open entity's relationships once they have loaded;
+                // until then the lists hold the previous entity's
+                let entity = self.selected_entity.as_ref().map(|e| e.logical_name.clone());
+                let loaded = !self.tasks.is_running("Loading entity details")
+                    && entity.is_some()
+                    && self.relationships_of == entity;
+                if let (true, Some(name)) = (loaded, &entity) {
+                    self.relationship_graph.loaded.insert(name.clone(), EntityRelationships {
+                        one_to_many: self.one_to_many.clone(),
+                        many_to_one: self.many_to_one.clone(),
+                        many_to_many: self.many_to_many.clone(),
+                    });
+                }
+                entity
+            }
+            _ => None,
+        };
+        let Some(entity) = entity else {
+            return;
+        };
+        self.relationship_graph.trail.clear();
+        self.relationship_graph.opened_from = self.view;
+        self.view = View::RelationshipGraph;
+        self.center_graph(&entity, false);
+    }
 
+    /// Centre the relationship graph on an entity, loading its relationships
+    /// the first time
+    fn center_graph(&mut self, entity: &str, remember: bool) {
+        if self.relationship_graph.loaded.contains_key(entity) {
+            self.relationship_graph.center_on(entity, remember);
+            return;
+        }
         self.state = AppState::Loading;
         self.error = None;
 
-        // Extract entity name from FetchXML (simple heuristic)
-        let entity_name = if let Some(start) = self.fetchxml_query.find("<entity name=\"") {
-            let rest = &self.fetchxml_query[start + 14..];
-            if let Some(end) = rest.find("\"") {
-                Some(&rest[..end])
-            } else {
-                None
+        let client = self.client.clone();
+        let entity = entity.to_string();
+        let load = {
+            let entity = entity.clone();
+            async move {
+                let (one_to_many, many_to_one, many_to_many) = tokio::try_join!(
+                    client.get_entity_one_to_many(&entity),
+                    client.get_entity_many_to_one(&entity),
+                    client.get_entity_many_to_many(&entity),
+                )?;
+                anyhow::Ok(EntityRelationships { one_to_many, many_to_one, many_to_many })
             }
-        } else if let Some(start) = self.fetchxml_query.find("<entity name='") {
-            let rest = &self.fetchxml_query[start + 14..];
-            if let Some(end) = rest.find("'") {
-                Some(&rest[..end])
-            } else {
-                None
+        };
+        self.spawn("Loading relationships", load, move |app, result| match result {
+            Ok(relationships) => {
+                app.relationship_graph.loaded.insert(entity.clone(), relationships);
+                app.relationship_graph.center_on(&entity, remember);
+                app.state = AppState::Ready;
+            }
+            Err(e) => {
+                app.error = Some(app.describe_error("Failed to load relationships", &e));
+                app.state = AppState::Ready;
+            }
+        });
+    }
+
+    /// Re-centre the relationship graph on the selected neighbour
+    pub fn graph_enter(&mut self) {
+        if let Some(neighbour) = self.relationship_graph.selected() {
+            let entity = neighbour.entity.clone();
+            self.center_graph(&entity, true);
+        }
+    }
+
+    /// Return to the entity centred before the current one
+    pub fn graph_back(&mut self) {
+        if let Some(previous) = self.relationship_graph.trail.pop() {
+            self.relationship_graph.center_on(&previous, false);
+        }
+    }
+
+    /// Open the details of the entity at the centre of the graph
+    pub fn open_graph_center(&mut self) {
+        let center = self.relationship_graph.center.clone();
+        let Some(entity) = self.entities.iter().find(|e| e.logical_name == center).cloned() else {
+            return;
+        };
+        self.selected_entity = Some(entity);
+        self.view = View::EntityDetail;
+        self.load_entity_detail(&center);
+    }
+
+    /// Execute the FetchXML query currently in the editor and record it in the history
+    pub fn execute_fetch_xml_query(&mut self) {
+        self.history_recall = None;
+        if self.fetchxml_editor.is_empty() {
+            return;
+        }
+
+        self.run_fetch_xml_query(self.fetchxml_editor.text(), Instant::now());
+    }
+
+    fn run_fetch_xml_query(&mut self, fetch_xml: String, started: Instant) {
+        self.fetchxml_error = None;
+        self.query_result.error = None;
+
+        // Pre-flight: the document must be well-formed and use known names
+        let doc = match crate::fetchxml::parse(&fetch_xml) {
+            Ok(doc) => doc,
+            Err(e) => {
+                self.fetchxml_error = Some(e);
+                self.finish_fetch_xml_query(fetch_xml, started);
+                return;
             }
-        } else {
-            None
         };
 
-        let Some(logical_name) = entity_name else {
-            self.error = Some("Could not find <entity name='...'> in FetchXML".to_string());
+        self.state = AppState::Loading;
+        self.error = None;
+
+        // Resolve every entity strictly from metadata, never by guessing plurals
+        let lookups: Vec<(String, Option<EntityMetadata>)> = doc
+            .entities
+            .iter()
+            .map(|node| {
+                let known = self.entities.iter().find(|e| e.logical_name == node.name.name).cloned();
+                (node.name.name.clone(), known)
+            })
+            .collect();
+        // Only a not-found answer means the name is wrong; other failures are
+        // reported as errors. A snapshot's entity list is already complete.
+        let client = self.client.clone();
+        let lookup = async move {
+            let mut resolved = Vec::new();
+            for (logical_name, known) in lookups {
+                resolved.push(match known {
+                    Some(entity) => Some(entity),
+                    None if client.snapshot().is_some() => None,
+                    None => match client.get_entity(&logical_name).await {
+                        Ok(entity) => Some(entity),
+                        Err(e) if is_not_found(&e) => None,
+                        Err(e) => return Err((logical_name, e)),
+                    },
+                });
+            }
+            Ok(resolved)
+        };
+
+        self.spawn("Resolving entities", lookup, move |app, resolved| {
+            let resolved = match resolved {
+                Ok(resolved) => resolved,
+                Err((logical_name, e)) => {
+                    app.query_result.error = Some(app.describe_error(&format!("Failed to look up entity {}", logical_name), &e));
+                    app.state = AppState::Ready;
+                    app.finish_fetch_xml_query(fetch_xml, started);
+                    return;
+                }
+            };
+            let mut entities = Vec::new();
+            for (node, entity) in doc.entities.iter().zip(resolved) {
+                let Some(entity) = entity else {
+                    app.fetchxml_error = Some(crate::fetchxml::FetchXmlError {
+                        message: format!("Unknown entity '{}'", node.name.name),
+                        line: node.name.line,
+                        column: node.name.column,
+                    });
+                    app.state = AppState::Ready;
+                    app.finish_fetch_xml_query(fetch_xml, started);
+                    return;
+                };
+                entities.push(entity);
+            }
+
+            let logical_names = entities.iter().map(|e| e.logical_name.clone()).collect();
+            app.load_query_metadata(logical_names, false, move |app, loaded| {
+                if let Err((logical_name, e)) = loaded {
+                    app.query_result.error = Some(app.describe_error(&format!("Failed to load attributes for {}", logical_name), &e));
+                    app.state = AppState::Ready;
+                    app.finish_fetch_xml_query(fetch_xml, started);
+                    return;
+                }
+                app.send_fetch_xml_query(doc, entities, fetch_xml, started);
+            });
+        });
+    }
+
+    /// Validate a FetchXML query against the loaded metadata and send it
+    fn send_fetch_xml_query(
+        &mut self,
+        doc: crate::fetchxml::FetchDocument,
+        entities: Vec<EntityMetadata>,
+        fetch_xml: String,
+        started: Instant,
+    ) {
+        if let Err(e) = crate::fetchxml::validate(&doc, &entities, &self.attribute_cache) {
+            self.fetchxml_error = Some(e);
+            self.state = AppState::Ready;
+            self.finish_fetch_xml_query(fetch_xml, started);
+            return;
+        }
+
+        let Some(entity_set_name) = entities[0].entity_set_name.clone() else {
+            let root = doc.root_entity();
+            self.fetchxml_error = Some(crate::fetchxml::FetchXmlError {
+                message: format!("Entity '{}' cannot be queried through the Web API", root.name),
+                line: root.line,
+                column: root.column,
+            });
             self.state = AppState::Ready;
+            self.finish_fetch_xml_query(fetch_xml, started);
             return;
         };
 
-        // We need the entity set name. If we don't have it, we might need to fetch it or guess it.
-        // For now, let's try to find it from our cached entities.
-        let entity_set_name = self.entities.iter()
-            .find(|e| e.logical_name == logical_name)
-            .and_then(|e| e.entity_set_name.clone())
-            .unwrap_or_else(|| format!("{}s", logical_name));
+        let client = self.client.clone();
+        let request = fetch_xml.clone();
+        let query = async move { client.execute_fetch_xml(&entity_set_name, &request).await };
+        self.spawn("Running FetchXML query", query, move |app, result| {
+            match result {
+                Ok(result) => {
+                    app.query_result = result;
+                    app.view = View::EntityDetail;
+                    app.entity_tab = EntityTab::Query;
+                    app.query_mode = QueryMode::Results;
+                    app.input_mode = InputMode::Normal;
+                }
+                Err(e) => {
+                    app.query_result.error = Some(app.describe_error("FetchXML execution failed", &e));
+                }
+            }
+            app.state = AppState::Ready;
+            app.finish_fetch_xml_query(fetch_xml, started);
+        });
+    }
+
+    /// Record a finished FetchXML query in the history
+    ///
+    /// Reopens the editor when the query failed, so it can be fixed in place.
+    fn finish_fetch_xml_query(&mut self, fetch_xml: String, started: Instant) {
+        let failed = self.fetchxml_error.is_some() || self.query_result.error.is_some();
+        let error = self.fetchxml_error.as_ref().map(|e| e.to_string()).or_else(|| self.query_result.error.clone());
+        let entity = crate::fetchxml::parse(&fetch_xml).ok().map(|doc| doc.root_entity().name.clone());
+        self.record_history(HistoryKind::FetchXml, fetch_xml, entity, started, error);
 
-        match self.client.execute_fetch_xml(&entity_set_name, &self.fetchxml_query).await {
-            Ok(result) => {
-                self.query_result = result;
-                self.view = View::EntityDetail;
-                self.entity_tab = EntityTab::Query;
-                self.query_mode = QueryMode::Results;
-                self.state = AppState::Ready;
+        if failed && self.view == View::FetchXML && self.input_mode == InputMode::Normal {
+            self.input_mode = InputMode::FetchXML;
+        }
+    }
+
+    /// Indices into the saved query store for the current environment
+    pub fn saved_query_indices(&self) -> Vec<usize> {
+        self.saved_queries.for_environment(&self.client.environment_url())
+    }
+
+    /// Capture the query being edited and ask for a name to save it under
+    ///
+    /// The FetchXML console saves its text, the SQL tab saves the statement and
+    /// the other Query tab modes save the guided builder selections.
+    pub fn prompt_save_query(&mut self) {
+        let entity = self.selected_entity.as_ref().map(|e| e.logical_name.clone());
+        let (entity, query) = match (self.view, self.query_mode) {
+            (View::FetchXML, _) => {
+                if self.fetchxml_editor.is_empty() {
+                    self.message = Some("Nothing to save".to_string());
+                    return;
+                }
+                (None, SavedQueryBody::FetchXml { fetch_xml: self.fetchxml_editor.text() })
             }
-            Err(e) => {
-                self.error = Some(format!("FetchXML execution failed: {}", e));
-                self.state = AppState::Ready;
+            (View::EntityDetail, QueryMode::Sql) => {
+                if self.sql_query.trim().is_empty() {
+                    self.message = Some("Nothing to save".to_string());
+                    return;
+                }
+                (entity, SavedQueryBody::Sql { sql: self.sql_query.clone() })
+            }
+            (View::EntityDetail, _) if entity.is_some() => {
+                let columns = self.query_selected_columns
+                    .iter()
+                    .zip(&self.entity_attributes)
+                    .filter(|(selected, _)| **selected)
+                    .map(|(_, attr)| attr.logical_name.clone())
+                    .collect();
+                let order_by = self.query_order_by
+                    .and_then(|i| self.entity_attributes.get(i))
+                    .map(|attr| attr.logical_name.clone());
+                let query = SavedQueryBody::Guided {
+                    columns,
+                    filters: self.query_filters.clone(),
+                    order_by,
+                    order_desc: self.query_order_desc,
+                    top: self.query_top,
+                };
+                (entity, query)
             }
+            _ => return,
+        };
+
+        self.query_name_prompt = Some(QueryNamePrompt {
+            name: String::new(),
+            target: QueryNameTarget::Save { entity, query },
+            return_mode: self.input_mode,
+        });
+        self.input_mode = InputMode::QueryName;
+    }
+
+    /// Ask for a new name for the selected saved query
+    pub fn prompt_rename_saved_query(&mut self) {
+        let Some(&index) = self.saved_query_indices().get(self.saved_query_index) else {
+            return;
+        };
+        self.query_name_prompt = Some(QueryNamePrompt {
+            name: self.saved_queries.queries[index].name.clone(),
+            target: QueryNameTarget::Rename(index),
+            return_mode: self.input_mode,
+        });
+        self.input_mode = InputMode::QueryName;
+    }
+
+    /// Save or rename using the name entered in the prompt
+    pub fn confirm_query_name(&mut self) {
+        let Some(prompt) = &self.query_name_prompt else {
+            return;
+        };
+        let name = prompt.name.trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+        let Some(prompt) = self.query_name_prompt.take() else {
+            return;
+        };
+        self.input_mode = prompt.return_mode;
+
+        match prompt.target {
+            QueryNameTarget::Save { entity, query } => {
+                self.saved_queries.upsert(SavedQuery {
+                    name: name.clone(),
+                    environment: self.client.environment_url(),
+                    entity,
+                    query,
+                });
+            }
+            QueryNameTarget::Rename(index) => {
+                self.saved_queries.rename(index, &name);
+                // Keep the cursor on the renamed query after re-sorting
+                let indices = self.saved_query_indices();
+                self.saved_query_index = indices
+                    .iter()
+                    .position(|&i| self.saved_queries.queries[i].name == name)
+                    .unwrap_or(0);
+            }
+        }
+
+        self.message = Some(match self.saved_queries.save() {
+            Ok(()) => format!("Saved query '{}'", name),
+            Err(e) => format!("Failed to save queries: {}", e),
+        });
+    }
+
+    pub fn cancel_query_name(&mut self) {
+        if let Some(prompt) = self.query_name_prompt.take() {
+            self.input_mode = prompt.return_mode;
         }
     }
 
+    /// Delete the selected saved query
+    pub fn delete_saved_query(&mut self) {
+        let indices = self.saved_query_indices();
+        let Some(&index) = indices.get(self.saved_query_index) else {
+            return;
+        };
+        let removed = self.saved_queries.remove(index);
+        if self.saved_query_index + 1 >= indices.len() {
+            self.saved_query_index = self.saved_query_index.saturating_sub(1);
+        }
+        self.message = Some(match self.saved_queries.save() {
+            Ok(()) => format!("Deleted query '{}'", removed.name),
+            Err(e) => format!("Failed to save queries: {}", e),
+        });
+    }
+
+    /// Load the selected saved query into its editor and run it
+    pub fn run_saved_query(&mut self) {
+        let Some(&index) = self.saved_query_indices().get(self.saved_query_index) else {
+            return;
+        };
+        let saved = self.saved_queries.queries[index].clone();
+
+        if let SavedQueryBody::FetchXml { fetch_xml } = saved.query {
+            self.fetchxml_editor.set_text(&fetch_xml);
+            self.fetchxml_error = None;
+            self.view = View::FetchXML;
+            // Leave the editor open if the query needs fixing
+            self.input_mode = InputMode::FetchXML;
+            self.execute_fetch_xml_query();
+            return;
+        }
+
+        let Some(entity) = saved.entity.as_deref() else {
+            self.message = Some(format!("Query '{}' has no entity", saved.name));
+            return;
+        };
+
+        self.open_entity_query(entity, move |app| match saved.query {
+            SavedQueryBody::Guided { columns, filters, order_by, order_desc, top } => {
+                for (selected, attr) in app.query_selected_columns.iter_mut().zip(&app.entity_attributes) {
+                    *selected = columns.contains(&attr.logical_name);
+                }
+                app.query_filters = filters;
+                app.query_order_by = order_by
+                    .and_then(|name| app.entity_attributes.iter().position(|a| a.logical_name == name));
+                app.query_order_desc = order_desc;
+                app.query_top = top;
+                app.execute_guided_query();
+            }
+            SavedQueryBody::Sql { sql } => {
+                app.sql_cursor = sql.chars().count();
+                app.sql_query = sql;
+                app.query_mode = QueryMode::Sql;
+                app.execute_sql_query();
+                if app.sql_error.is_some() {
+                    app.input_mode = InputMode::SQLQuery;
+                }
+            }
+            SavedQueryBody::FetchXml { .. } => {}
+        });
+    }
+
+    /// Open the Query tab of an entity by logical name, then continue with
+    /// `then` once its details have loaded
+    fn open_entity_query(&mut self, logical_name: &str, then: impl FnOnce(&mut App) + Send + 'static) {
+        let Some(entity) = self.entities.iter().find(|e| e.logical_name == logical_name).cloned() else {
+            self.message = Some(format!("Entity '{}' not found", logical_name));
+            return;
+        };
+        self.selected_entity = Some(entity);
+        self.view = View::EntityDetail;
+        self.search_query.clear();
+        self.load_entity_detail_then(logical_name, move |app| {
+            app.entity_tab = EntityTab::Query;
+            then(app);
+        });
+    }
+
+    /// Run the selected view's FetchXML and show it with the view's columns
+    pub fn run_entity_view(&mut self) {
+        let Some(view) = self.entity_views.get(self.entity_view_index).cloned() else {
+            return;
+        };
+        let Some(entity_set_name) = self.selected_entity.as_ref().and_then(|e| e.entity_set_name.clone()) else {
+            return;
+        };
+
+        self.entity_tab = EntityTab::Query;
+        self.query_mode = QueryMode::Results;
+        self.query_result_index = 0;
+        let Some(fetch_xml) = view.fetch_xml.as_deref() else {
+            self.query_result = QueryResult::default();
+            self.query_result.error = Some(format!("View '{}' has no FetchXML", view.get_display_name()));
+            return;
+        };
+
+        self.state = AppState::Loading;
+        self.error = None;
+        let started = Instant::now();
+
+        let client = self.client.clone();
+        let fetch_xml = fetch_xml.to_string();
+        let request = fetch_xml.clone();
+        let query = async move { client.execute_fetch_xml(&entity_set_name, &request).await };
+        self.spawn("Running view", query, move |app, result| {
+            let error = match result {
+                Ok(mut result) => {
+                    // Fall back to the returned columns if the layout is unreadable
+                    let layout = view
+                        .layout_xml
+                        .as_deref()
+                        .and_then(|xml| crate::fetchxml::parse_layout(xml).ok())
+                        .unwrap_or_default();
+                    if !layout.is_empty() {
+                        result.apply_layout(layout);
+                    }
+                    app.query_result = result;
+                    app.message = Some(format!("View: {}", view.get_display_name()));
+                    None
+                }
+                Err(e) => {
+                    app.query_result = QueryResult::default();
+                    app.query_result.error = Some(app.describe_error("View query failed", &e));
+                    Some(e.to_string())
+                }
+            };
+            app.state = AppState::Ready;
+            let entity = app.selected_entity.as_ref().map(|e| e.logical_name.clone());
+            app.record_history(HistoryKind::FetchXml, fetch_xml, entity, started, error);
+        });
+    }
+
+    /// Append an executed query to the history
+    fn record_history(
+        &mut self,
+        kind: HistoryKind,
+        text: String,
+        entity: Option<String>,
+        started: Instant,
+        error: Option<String>,
+    ) {
+        let entry = HistoryEntry {
+            kind,
+            text,
+            entity,
+            environment: self.client.environment_url(),
+            timestamp: chrono::Utc::now(),
+            rows: error.is_none().then_some(self.query_result.rows.len()),
+            duration_ms: started.elapsed().as_millis() as u64,
+            error,
+        };
+        let _ = self.history.record(entry);
+    }
+
+    /// Step through earlier (`older`) or later queries of one kind
+    ///
+    /// Returns the text to put in the console, restoring the draft that was
+    /// being edited when stepping past the newest entry.
+    pub fn recall_history(&mut self, kind: HistoryKind, current: &str, older: bool) -> Option<String> {
+        let texts = self.history.texts(kind);
+        let next = match (self.history_recall, older) {
+            (None, true) => 0,
+            (Some(i), true) => i + 1,
+            (Some(0), false) => {
+                self.history_recall = None;
+                return Some(std::mem::take(&mut self.history_draft));
+            }
+            (Some(i), false) => i - 1,
+            (None, false) => return None,
+        };
+        let text = texts.get(next)?.to_string();
+        if self.history_recall.is_none() {
+            self.history_draft = current.to_string();
+        }
+        self.history_recall = Some(next);
+        Some(text)
+    }
+
+    /// Filter the history view by the search query, newest first
+    pub fn filter_history(&mut self) {
+        let query = self.search_query.to_lowercase();
+        self.filtered_history = (0..self.history.entries.len())
+            .rev()
+            .filter(|&i| {
+                let entry = &self.history.entries[i];
+                query.is_empty()
+                    || entry.text.to_lowercase().contains(&query)
+                    || entry.entity.as_deref().is_some_and(|e| e.contains(&query))
+            })
+            .collect();
+        self.history_index = 0;
+    }
+
+    /// Load the selected history entry back into its console
+    ///
+    /// SQL and FetchXML are opened for editing; guided queries are re-run
+    /// from their recorded URL.
+    pub fn open_history_entry(&mut self) {
+        let Some(entry) = self.filtered_history.get(self.history_index).map(|&i| self.history.entries[i].clone()) else {
+            return;
+        };
+
+        match entry.kind {
+            HistoryKind::FetchXml => {
+                self.fetchxml_editor.set_text(&entry.text);
+                self.fetchxml_error = None;
+                self.view = View::FetchXML;
+                self.input_mode = InputMode::FetchXML;
+            }
+            HistoryKind::Sql | HistoryKind::Guided => {
+                let Some(entity) = entry.entity.clone() else {
+                    self.message = Some("History entry has no entity".to_string());
+                    return;
+                };
+                self.open_entity_query(&entity, move |app| {
+                    if entry.kind == HistoryKind::Sql {
+                        app.sql_cursor = entry.text.chars().count();
+                        app.sql_query = entry.text;
+                        app.sql_error = None;
+                        app.query_mode = QueryMode::Sql;
+                        app.input_mode = InputMode::SQLQuery;
+                    } else {
+                        app.execute_history_url(entry.text);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Re-run a guided query from its recorded OData URL
+    fn execute_history_url(&mut self, url: String) {
+        self.state = AppState::Loading;
+        let started = Instant::now();
+        let client = self.client.clone();
+        let request = url.clone();
+        self.spawn("Running query", async move { client.execute_query(&request).await }, move |app, result| {
+            let error = match result {
+                Ok(json) => {
+                    app.query_result = QueryResult::from_json(&json);
+                    app.query_result.raw_json = Some(serde_json::to_string_pretty(&json).unwrap_or_default());
+                    None
+                }
+                Err(e) => {
+                    app.query_result = QueryResult::default();
+                    app.query_result.error = Some(app.describe_error("Query failed", &e));
+                    Some(e.to_string())
+                }
+            };
+            app.query_result_index = 0;
+            app.query_mode = QueryMode::Results;
+            app.state = AppState::Ready;
+            let entity = app.selected_entity.as_ref().map(|e| e.logical_name.clone());
+            app.record_history(HistoryKind::Guided, url, entity, started, error);
+        });
+    }
+
     /// Show usage statistics for the selected attribute
-    pub async fn show_attribute_usage(&mut self) {
+    pub fn show_attribute_usage(&mut self) {
         let Some(entity) = &self.selected_entity else { return; };
         
         let (logical_name, _metadata_id) = if let Some(attr) = self.get_selected_attribute() {
@@ -1908,13 +3064,107 @@ impl App {
         
         self.message = Some(format!("Calculating usage for {}...", logical_name));
         
-        match self.client.get_attribute_count(&entity_set_name, &logical_name).await {
+        let client = self.client.clone();
+        let attribute = logical_name.clone();
+        let count = async move { client.get_attribute_count(&entity_set_name, &attribute).await };
+        self.spawn_background("Calculating usage", count, move |app, result| match result {
             Ok(count) => {
-                self.message = Some(format!("Attribute '{}' is populated in {} records", logical_name, count));
+                app.message = Some(format!("Attribute '{}' is populated in {} records", logical_name, count));
             }
             Err(e) => {
-                self.error = Some(format!("Failed to calculate usage: {}", e));
+                app.error = Some(app.describe_error("Failed to calculate usage", &e));
             }
-        }
+        });
     }
 }
+
+/// Whether a request failed because what it asked for does not exist
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<DataverseError>()
+        .is_some_and(|e| e.kind() == DataverseErrorKind::RecordNotFound)
+}
+
+/// Metadata shown on the entity detail tabs
+struct EntityDetail {
+    attributes: Vec<AttributeMetadata>,
+    one_to_many: Vec<RelationshipMetadata>,
+    many_to_one: Vec<RelationshipMetadata>,
+    many_to_many: Vec<RelationshipMetadata>,
+    /// Whether every relationship request succeeded; failed ones are empty
+    relationships_loaded: bool,
+    views: Vec<EntityView>,
+}
+
+/// Fetch attributes, relationships and views for an entity, all at once
+///
+/// Only the attributes are required; relationships and views that fail to
+/// load are left empty. Attributes already in the cache are not fetched.
+async fn fetch_entity_detail(
+    client: &DataverseClient,
+    logical_name: &str,
+    cached_attributes: Option<Vec<AttributeMetadata>>,
+) -> anyhow::Result<EntityDetail> {
+    let attributes = async {
+        match cached_attributes {
+            Some(attributes) => Ok(attributes),
+            None => client.get_entity_attributes(logical_name).await,
+        }
+    };
+    let (attributes, one_to_many, many_to_one, many_to_many, system_views, personal_views) = tokio::join!(
+        attributes,
+        client.get_entity_one_to_many(logical_name),
+        client.get_entity_many_to_one(logical_name),
+        client.get_entity_many_to_many(logical_name),
+        client.get_system_views(logical_name),
+        client.get_personal_views(logical_name),
+    );
+
+    let mut views = system_views.unwrap_or_default();
+    views.extend(personal_views.unwrap_or_default());
+
+    Ok(EntityDetail {
+        attributes: attributes?,
+        relationships_loaded: one_to_many.is_ok() && many_to_one.is_ok() && many_to_many.is_ok(),
+        one_to_many: one_to_many.unwrap_or_default(),
+        many_to_one: many_to_one.unwrap_or_default(),
+        many_to_many: many_to_many.unwrap_or_default(),
+        views,
+    })
+}
+
+/// Fetch a user's direct roles, teams and the roles inherited from those teams
+async fn fetch_user_detail(
+    client: &DataverseClient,
+    user_id: &str,
+) -> Result<(Vec<SecurityRole>, Vec<Team>, Vec<RoleAssignment>), (&'static str, anyhow::Error)> {
+    let (direct_roles, teams) = tokio::join!(client.get_user_roles(user_id), client.get_user_teams(user_id));
+    let direct_roles = direct_roles.map_err(|e| ("Failed to load user roles", e))?;
+    let teams = teams.map_err(|e| ("Failed to load user teams", e))?;
+
+    // Roles of every team in one $batch call, or a bounded number of separate
+    // requests at a time if the batch itself fails
+    let team_ids: Vec<String> = teams.iter().map(|team| team.id.clone()).collect();
+    let roles_per_team: Vec<Vec<SecurityRole>> = match client.get_roles_of_teams(&team_ids).await {
+        Ok(roles) => roles.into_iter().map(|r| r.unwrap_or_default()).collect(),
+        Err(_) => {
+            futures::stream::iter(team_ids)
+                .map(|team_id| async move { client.get_team_roles(&team_id).await.unwrap_or_default() })
+                .buffered(MAX_CONCURRENT_REQUESTS)
+                .collect()
+                .await
+        }
+    };
+    let team_roles = teams
+        .iter()
+        .zip(roles_per_team)
+        .flat_map(|(team, roles)| {
+            roles.into_iter().map(|role| RoleAssignment {
+                role,
+                source: RoleSource::Team(team.name.clone()),
+            })
+        })
+        .collect();
+
+    Ok((direct_roles, teams, team_roles))
+}
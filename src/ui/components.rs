@@ -4,9 +4,14 @@ use ratatui::prelude::{Alignment, Constraint, Direction, Layout, Rect, Line, Spa
 use ratatui::style::{Color, Style};
 use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Tabs, Wrap};
 use ratatui::Frame;
+use unicode_width::UnicodeWidthStr;
 
 use super::app::{App, AppState, EntityTab, QueryMode, SearchResult, UserTab, View};
+use super::editor::highlight_xml;
+use super::graph::Side;
 use super::input::InputMode;
+use crate::diff::Change;
+use crate::config::{EnvColor, EnvironmentProfile};
 use crate::models::{ComponentType, RoleSource};
 
 /// Render the complete UI
@@ -28,6 +33,14 @@ pub fn render(frame: &mut Frame, app: &mut App) {
     if app.input_mode == InputMode::Search {
         render_search_popup(frame, app);
     }
+
+    if app.input_mode == InputMode::QueryName {
+        render_query_name_popup(frame, app);
+    }
+
+    if app.input_mode == InputMode::ErrorDetails {
+        render_error_details_popup(frame, app);
+    }
 }
 
 /// Render the header with navigation tabs
@@ -53,10 +66,24 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
         View::FetchXML => 0, // FetchXML is a sub-view of Entities for now
         View::RecordDetail => 0, // RecordDetail is a sub-view of Entities for now
         View::EnvironmentDiscovery => 6,
+        View::SavedQueries | View::History => 0, // Queries open in the entity Query tab
+        View::SchemaDiff => 6,
+        View::RelationshipGraph => 0,
     };
 
+    let mut title = vec![Span::raw(" Rynamo ")];
+    if let Some(snapshot) = app.client.snapshot() {
+        title.push(Span::styled(
+            format!("[SNAPSHOT] {} ", snapshot.environment),
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ));
+    } else if let Some(profile) = app.config.current_profile() {
+        title.extend(profile_spans(profile));
+        title.push(Span::raw(" "));
+    }
+
     let tabs = Tabs::new(titles)
-        .block(Block::default().borders(Borders::ALL).title(" Rynamo "))
+        .block(Block::default().borders(Borders::ALL).title(Line::from(title)))
         .select(selected_index)
         .style(Style::default().fg(Color::White))
         .highlight_style(
@@ -68,11 +95,40 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(tabs, area);
 }
 
+fn env_color(color: EnvColor) -> Color {
+    match color {
+        EnvColor::Red => Color::Red,
+        EnvColor::Yellow => Color::Yellow,
+        EnvColor::Green => Color::Green,
+        EnvColor::Blue => Color::Blue,
+        EnvColor::Magenta => Color::Magenta,
+        EnvColor::Cyan => Color::Cyan,
+    }
+}
+
+/// Environment name in its colour tag, with a badge when read-only
+fn profile_spans(profile: &EnvironmentProfile) -> Vec<Span<'_>> {
+    let style = match profile.color {
+        Some(color) => Style::default().fg(Color::Black).bg(env_color(color)),
+        None => Style::default().fg(Color::Cyan),
+    };
+    let mut spans = vec![Span::styled(
+        format!(" {} ", profile.display_name()),
+        style.add_modifier(Modifier::BOLD),
+    )];
+    if profile.read_only {
+        spans.push(Span::styled(" READ-ONLY ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+    }
+    spans
+}
+
 /// Render the main content area
 fn render_content(frame: &mut Frame, app: &mut App, area: Rect) {
+    // Views without a request in flight stay browsable while others load
     match app.state {
-        AppState::Loading => {
-            let loading = Paragraph::new("Loading...")
+        _ if app.tasks.is_loading(app.view) => {
+            let (spinner, label) = app.tasks.status().unwrap_or(('⠋', "Loading"));
+            let loading = Paragraph::new(format!("{} {}...  (Esc to cancel)", spinner, label))
                 .style(Style::default().fg(Color::Yellow))
                 .block(Block::default().borders(Borders::ALL));
             frame.render_widget(loading, area);
@@ -85,7 +141,7 @@ fn render_content(frame: &mut Frame, app: &mut App, area: Rect) {
                 .wrap(Wrap { trim: true });
             frame.render_widget(error, area);
         }
-        AppState::Ready => match app.view {
+        AppState::Loading | AppState::Ready => match app.view {
             View::Entities => render_entity_list(frame, app, area),
             View::EntityDetail => render_entity_detail(frame, app, area),
             View::Solutions => render_solution_list(frame, app, area),
@@ -101,6 +157,10 @@ fn render_content(frame: &mut Frame, app: &mut App, area: Rect) {
             View::SystemJobs => render_system_job_list(frame, app, area),
             View::SystemJobDetail => render_system_job_detail(frame, app, area),
             View::EnvironmentDiscovery => render_environment_discovery(frame, app, area),
+            View::SavedQueries => render_saved_queries(frame, app, area),
+            View::History => render_history(frame, app, area),
+            View::SchemaDiff => render_schema_diff(frame, app, area),
+            View::RelationshipGraph => render_relationship_graph(frame, app, area),
         },
     }
 }
@@ -143,7 +203,7 @@ fn render_entity_list(frame: &mut Frame, app: &mut App, area: Rect) {
             Block::default()
                 .borders(Borders::ALL)
                 .title(title)
-                .title_bottom(" ↑↓ Navigate │ Enter: Details │ /: Search │ q: Quit "),
+                .title_bottom(" ↑↓ Navigate │ Enter: Details │ x: Diagram │ /: Search │ q: Quit "),
         )
         .highlight_style(
             Style::default()
@@ -193,12 +253,14 @@ fn render_entity_detail(frame: &mut Frame, app: &mut App, area: Rect) {
         ),
         "Metadata".to_string(),
         "Query".to_string(),
+        format!("Views ({})", app.entity_views.len()),
     ];
     let selected_tab = match app.entity_tab {
         EntityTab::Attributes => 0,
         EntityTab::Relationships => 1,
         EntityTab::Metadata => 2,
         EntityTab::Query => 3,
+        EntityTab::Views => 4,
     };
 
     let tabs = Tabs::new(tab_titles)
@@ -214,6 +276,7 @@ fn render_entity_detail(frame: &mut Frame, app: &mut App, area: Rect) {
         EntityTab::Relationships => render_relationships(frame, app, chunks[2]),
         EntityTab::Metadata => render_entity_metadata(frame, app, chunks[2]),
         EntityTab::Query => render_query_tab(frame, app, chunks[2]),
+        EntityTab::Views => render_entity_views(frame, app, chunks[2]),
     }
 }
 
@@ -325,7 +388,7 @@ fn render_relationships(frame: &mut Frame, app: &mut App, area: Rect) {
             Block::default()
                 .borders(Borders::ALL)
                 .title(" Relationships ")
-                .title_bottom(" ←→ Tabs │ Esc: Back "),
+                .title_bottom(" x: Diagram │ ←→ Tabs │ Esc: Back "),
         )
         .highlight_style(
             Style::default()
@@ -339,6 +402,60 @@ fn render_relationships(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
+/// Render system and personal views for the entity
+fn render_entity_views(frame: &mut Frame, app: &mut App, area: Rect) {
+    let header = Row::new(vec!["Name", "Type", "Owner", "Default"])
+        .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .bottom_margin(1);
+
+    let rows: Vec<Row> = app
+        .entity_views
+        .iter()
+        .map(|view| {
+            let style = if view.personal {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                view.get_display_name().to_string(),
+                view.query_type_label().to_string(),
+                if view.personal { "Personal" } else { "System" }.to_string(),
+                if view.is_default.unwrap_or(false) { "✓" } else { "" }.to_string(),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Percentage(50),
+        Constraint::Percentage(20),
+        Constraint::Percentage(15),
+        Constraint::Percentage(15),
+    ];
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Views ")
+                .title_bottom(" Enter: Run │ ←→ Tabs │ Esc: Back "),
+        )
+        .row_highlight_style(
+            Style::default()
+                .bg(Color::Rgb(50, 50, 80))
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+
+    let mut table_state = TableState::default();
+    if !app.entity_views.is_empty() {
+        table_state.select(Some(app.entity_view_index));
+    }
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
 /// Render entity metadata
 fn render_entity_metadata(frame: &mut Frame, app: &App, area: Rect) {
     let Some(entity) = &app.selected_entity else {
@@ -505,7 +622,7 @@ fn render_solution_detail(frame: &mut Frame, app: &App, area: Rect) {
         .collect();
 
     let title = format!(
-        " Components (Showing {} of {}) - Enter: Drill Down / Esc: Back ",
+        " Components (Showing {} of {}) - Enter: Drill Down / x: Diagram / Esc: Back ",
         app.filtered_components.len(),
         app.solution_components.len()
     );
@@ -829,10 +946,13 @@ fn render_user_info(frame: &mut Frame, app: &App, area: Rect) {
 /// Render the status bar
 fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     let env = app.client.environment_url();
-    let state_indicator = match app.state {
-        AppState::Loading => Span::styled(" ● Loading ", Style::default().fg(Color::Yellow)),
-        AppState::Ready => Span::styled(" ● Connected ", Style::default().fg(Color::Green)),
-        AppState::Error => Span::styled(" ● Error ", Style::default().fg(Color::Red)),
+    let state_indicator = match (app.tasks.status(), app.state) {
+        (Some((spinner, label)), _) => {
+            Span::styled(format!(" {} {} ", spinner, label), Style::default().fg(Color::Yellow))
+        }
+        (None, AppState::Loading) => Span::styled(" ● Loading ", Style::default().fg(Color::Yellow)),
+        (None, AppState::Ready) => Span::styled(" ● Connected ", Style::default().fg(Color::Green)),
+        (None, AppState::Error) => Span::styled(" ● Error ", Style::default().fg(Color::Red)),
     };
 
     let search_hint = if !app.search_query.is_empty() {
@@ -847,10 +967,24 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         String::new()
     };
 
+    // Sign-in instructions stay until the sign-in ends, unlike messages
+    let sign_in_text = match &app.sign_in_prompt {
+        Some(prompt) => format!(" │ 🔑 {} ", prompt),
+        None => String::new(),
+    };
+
+    let throttle_indicator = if app.client.is_throttled() {
+        Span::styled("│ ⏳ Throttled ", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+    } else {
+        Span::raw("")
+    };
+
     let status = Line::from(vec![
         state_indicator,
+        throttle_indicator,
         Span::raw(format!("│ {} ", env)),
         Span::styled(search_hint, Style::default().fg(Color::Magenta)),
+        Span::styled(sign_in_text, Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
         Span::styled(message_text, Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)),
     ]);
 
@@ -882,6 +1016,56 @@ fn render_search_popup(frame: &mut Frame, app: &App) {
     ));
 }
 
+/// Render the name prompt for saving or renaming a query
+fn render_query_name_popup(frame: &mut Frame, app: &App) {
+    let Some(prompt) = &app.query_name_prompt else {
+        return;
+    };
+    let area = centered_rect(50, 3, frame.area());
+
+    frame.render_widget(Clear, area);
+
+    let input = Paragraph::new(prompt.name.as_str())
+        .style(Style::default().fg(Color::White))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Query name (Enter to save, Esc to cancel) ")
+                .style(Style::default().fg(Color::Cyan)),
+        );
+
+    frame.render_widget(input, area);
+
+    frame.set_cursor_position((
+        area.x + prompt.name.chars().count() as u16 + 1,
+        area.y + 1,
+    ));
+}
+
+/// Render the full details of the last error
+fn render_error_details_popup(frame: &mut Frame, app: &App) {
+    let Some(details) = &app.error_details else {
+        return;
+    };
+    let height = frame.area().height.saturating_sub(6).max(3);
+    let area = centered_rect(80, height, frame.area());
+
+    frame.render_widget(Clear, area);
+
+    let paragraph = Paragraph::new(details.as_str())
+        .style(Style::default().fg(Color::White))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Error details (↑/↓ to scroll, Esc to close) ")
+                .style(Style::default().fg(Color::Red)),
+        )
+        .wrap(Wrap { trim: false })
+        .scroll((app.error_details_scroll, 0));
+
+    frame.render_widget(paragraph, area);
+}
+
 /// Create a centered rectangle
 fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
@@ -959,7 +1143,7 @@ fn render_query_builder(frame: &mut Frame, app: &App, area: Rect) {
 
     // Help text
     let help = match app.query_mode {
-        QueryMode::Columns => "Tab: Next │ Enter: Filter by │ Space: Toggle │ a: All │ c: Clear │ F5: Run",
+        QueryMode::Columns => "Tab: Next │ Enter: Filter by │ Space: Toggle │ a: All │ c: Clear │ s: Save │ F5: Run",
         QueryMode::Filter => "Tab: Next │ Enter: Add │ d: Delete │ o/O: Op │ Backspace: Pop │ F5: Run",
         QueryMode::Options | QueryMode::OrderBy => "Tab: Next │ Enter: Edit │ F5: Run",
         QueryMode::Sql => "Tab: Next │ Enter: Execute │ SQL Query Console",
@@ -1104,22 +1288,45 @@ fn render_sql_console(frame: &mut Frame, app: &App, area: Rect) {
         .alignment(Alignment::Center);
     frame.render_widget(title, chunks[0]);
 
-    let input = Paragraph::new(app.sql_query.as_str())
+    // Highlight the character an error points at
+    let query_line = match &app.sql_error {
+        Some(err) => {
+            let before: String = app.sql_query.chars().take(err.position).collect();
+            let at: String = app.sql_query.chars().skip(err.position).take(1).collect();
+            let after: String = app.sql_query.chars().skip(err.position + 1).collect();
+            let at = if at.is_empty() { " ".to_string() } else { at };
+            Line::from(vec![
+                Span::raw(before),
+                Span::styled(at, Style::default().fg(Color::White).bg(Color::Red)),
+                Span::raw(after),
+            ])
+        }
+        None => Line::from(app.sql_query.as_str()),
+    };
+
+    let input = Paragraph::new(query_line)
         .block(Block::default().borders(Borders::ALL).title(" SELECT ... FROM ... WHERE ... "))
-        .style(Style::default().fg(Color::Yellow));
+        .style(Style::default().fg(Color::Yellow))
+        .wrap(Wrap { trim: false });
     frame.render_widget(input, chunks[1]);
 
-    // Rough cursor simulation
+    // Rough cursor simulation, in display columns
     if app.input_mode == crate::ui::InputMode::SQLQuery {
-        let cursor_x = chunks[1].x + 1 + (app.sql_cursor % (chunks[1].width as usize - 2)) as u16;
-        let cursor_y = chunks[1].y + 1 + (app.sql_cursor / (chunks[1].width as usize - 2)) as u16;
+        let column = app.sql_query[..app.sql_byte_index()].width();
+        let cursor_x = chunks[1].x + 1 + (column % (chunks[1].width as usize - 2)) as u16;
+        let cursor_y = chunks[1].y + 1 + (column / (chunks[1].width as usize - 2)) as u16;
         frame.set_cursor_position((cursor_x, cursor_y));
     }
 
-    let info = Paragraph::new(" Enter: Execute │ Esc: Back │ Tab: Switch Mode │ SQL -> OData translation ")
-        .block(Block::default().borders(Borders::ALL))
-        .alignment(Alignment::Center)
-        .style(Style::default().fg(Color::DarkGray));
+    let info = match &app.sql_error {
+        Some(err) => Paragraph::new(format!(" {} ", err))
+            .block(Block::default().borders(Borders::ALL).title(" SQL Error "))
+            .style(Style::default().fg(Color::Red)),
+        None => Paragraph::new(" Enter: Execute │ ↑↓: History │ Ctrl+S: Save │ Esc: Back │ Tab: Switch Mode ")
+            .block(Block::default().borders(Borders::ALL))
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(Color::DarkGray)),
+    };
     frame.render_widget(info, chunks[2]);
 }
 
@@ -1153,15 +1360,17 @@ fn render_query_results(frame: &mut Frame, app: &mut App, area: Rect) {
         Row::new(row.clone())
     }).collect();
 
-    // Calculate column widths (equal distribution)
+    // Use the view's column widths when laid out from a view, else share equally
     let col_count = app.query_result.columns.len();
-    let widths: Vec<Constraint> = if col_count > 0 {
+    let widths: Vec<Constraint> = if !app.query_result.layout.is_empty() {
+        app.query_result.layout.iter().map(|cell| Constraint::Fill(cell.width)).collect()
+    } else if col_count > 0 {
         vec![Constraint::Percentage((100 / col_count as u16).max(1)); col_count]
     } else {
         vec![]
     };
 
-    let has_more = app.query_result.next_link.is_some();
+    let has_more = app.query_result.has_more();
     let title = format!(
         " Results ({} rows){} {} ",
         app.query_result.rows.len(),
@@ -1389,6 +1598,19 @@ fn render_global_search(frame: &mut Frame, app: &mut App, area: Rect) {
                         Span::styled(format!(" ({})", os.name), Style::default().fg(Color::DarkGray)),
                     ]))
                 }
+                SearchResult::Attribute { entity, attribute } => {
+                    let display_name = app
+                        .attribute_cache
+                        .get(entity)
+                        .and_then(|attrs| attrs.iter().find(|a| &a.logical_name == attribute))
+                        .map(|a| a.get_display_name())
+                        .unwrap_or_else(|| attribute.clone());
+                    ListItem::new(Line::from(vec![
+                        Span::styled(" [Column]   ", Style::default().fg(Color::Magenta)),
+                        Span::styled(display_name, Style::default().add_modifier(Modifier::BOLD)),
+                        Span::styled(format!(" ({}.{})", entity, attribute), Style::default().fg(Color::DarkGray)),
+                    ]))
+                }
             }
         })
         .collect();
@@ -1416,25 +1638,28 @@ fn render_global_search(frame: &mut Frame, app: &mut App, area: Rect) {
 
 /// Render environment switcher
 fn render_environment_switcher(frame: &mut Frame, app: &mut App, area: Rect) {
+    let current = app.config.current_profile().map(|p| p.url.as_str());
     let items: Vec<ListItem> = app.config.environments
         .iter()
-        .map(|url| {
-            let is_current = app.config.current_env.as_ref().map_or(false, |curr| curr == url);
+        .map(|profile| {
+            let is_current = current == Some(profile.url.as_str());
             let prefix = if is_current { "● " } else { "○ " };
             let style = if is_current {
                 Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
             } else {
                 Style::default()
             };
-            
-            ListItem::new(Line::from(vec![
-                Span::styled(prefix, style),
-                Span::styled(url, style),
-            ]))
+
+            let mut spans = vec![Span::styled(prefix, style)];
+            spans.extend(profile_spans(profile));
+            if profile.name.is_some() {
+                spans.push(Span::styled(format!(" {}", profile.url), Style::default().fg(Color::DarkGray)));
+            }
+            ListItem::new(Line::from(spans))
         })
         .collect();
 
-    let title = " Switch Environment - Enter: Select / Esc: Back ";
+    let title = " Switch Environment - Enter: Select / c: Compare schema / Esc: Back ";
     let list = List::new(items)
         .block(Block::default().borders(Borders::ALL).title(title))
         .highlight_style(
@@ -1451,41 +1676,319 @@ fn render_environment_switcher(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_stateful_widget(list, area, &mut list_state);
 }
 
+/// Render the relationship graph: N:1 neighbours left, the centre and N:N
+/// neighbours in the middle, 1:N neighbours right
+fn render_relationship_graph(frame: &mut Frame, app: &mut App, area: Rect) {
+    let graph = &app.relationship_graph;
+    let mut path: Vec<&str> = graph.trail.iter().map(String::as_str).collect();
+    path.push(&graph.center);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" Relationship Graph: {} ", path.join(" → ")))
+        .title_bottom(" ↑↓←→ Move │ Enter: Centre │ Backspace: Previous │ o: Open entity │ Esc: Back ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(33), Constraint::Percentage(34), Constraint::Percentage(33)])
+        .split(inner);
+    let middle = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(0)])
+        .split(columns[1]);
+
+    let display_name = app
+        .entities
+        .iter()
+        .find(|e| e.logical_name == graph.center)
+        .map(|e| e.get_display_name())
+        .unwrap_or_default();
+    let center = Paragraph::new(vec![
+        Line::from(Span::styled(
+            graph.center.clone(),
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(Span::styled(display_name, Style::default().fg(Color::DarkGray))),
+    ])
+    .alignment(Alignment::Center)
+    .block(Block::default().borders(Borders::ALL).border_style(Style::default().fg(Color::Cyan)));
+    frame.render_widget(center, middle[0]);
+
+    let sides = [
+        (Side::Parents, " ◀ Looks up (N:1) ", columns[0]),
+        (Side::Peers, " ◆ Many-to-many (N:N) ", middle[1]),
+        (Side::Children, " Looked up by (1:N) ▶ ", columns[2]),
+    ];
+    for (side, title, area) in sides {
+        let neighbours = graph.neighbours(side);
+        let items: Vec<ListItem> = neighbours
+            .iter()
+            .map(|n| {
+                let via = n.attribute.as_deref().unwrap_or(&n.relationship);
+                ListItem::new(Line::from(vec![
+                    Span::styled(n.entity.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("  {}", via), Style::default().fg(Color::DarkGray)),
+                ]))
+            })
+            .collect();
+        let active = graph.side == side && !neighbours.is_empty();
+        let border = if active { Color::Yellow } else { Color::DarkGray };
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(border))
+                    .title(format!("{}({}) ", title, neighbours.len())),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Rgb(50, 50, 80))
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+
+        let mut list_state = ListState::default();
+        if active {
+            list_state.select(Some(graph.index));
+        }
+        frame.render_stateful_widget(list, area, &mut list_state);
+    }
+}
+
+/// Render the differences between two schemas
+fn render_schema_diff(frame: &mut Frame, app: &mut App, area: Rect) {
+    let Some(diff) = &app.schema_diff else {
+        return;
+    };
+    let rows = diff.rows();
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|row| {
+            let indent = "  ".repeat(row.depth.saturating_sub(1));
+            let line = match row.change {
+                Some(change) => {
+                    let color = match change {
+                        Change::Added => Color::Green,
+                        Change::Removed => Color::Red,
+                        Change::Changed => Color::Yellow,
+                    };
+                    Line::from(vec![
+                        Span::raw(indent),
+                        Span::styled(format!("{} {}", change.symbol(), row.text), Style::default().fg(color)),
+                    ])
+                }
+                None if row.depth == 0 => Line::from(Span::styled(
+                    row.text.clone(),
+                    Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+                )),
+                None => Line::from(vec![
+                    Span::raw(indent),
+                    Span::styled(format!("  {}", row.text), Style::default().fg(Color::DarkGray)),
+                ]),
+            };
+            ListItem::new(line)
+        })
+        .collect();
+
+    let title = format!(" Schema Diff: {} → {} ({} differences) ", diff.left, diff.right, diff.change_count());
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .title_bottom(" e: Export Markdown and JSON │ Esc: Back "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(50, 50, 80))
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+
+    let mut list_state = ListState::default();
+    if !rows.is_empty() {
+        list_state.select(Some(app.schema_diff_index));
+    }
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// Render the saved queries for the current environment
+fn render_saved_queries(frame: &mut Frame, app: &mut App, area: Rect) {
+    let indices = app.saved_query_indices();
+    let items: Vec<ListItem> = indices
+        .iter()
+        .map(|&i| {
+            let saved = &app.saved_queries.queries[i];
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<40} ", saved.name), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{:<10}", saved.query.label()), Style::default().fg(Color::Cyan)),
+                Span::styled(saved.entity.as_deref().unwrap_or("-"), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Saved Queries ({}) ", indices.len()))
+                .title_bottom(" Enter: Run │ r: Rename │ d: Delete │ Esc: Back "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(50, 50, 80))
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+
+    let mut list_state = ListState::default();
+    if !indices.is_empty() {
+        list_state.select(Some(app.saved_query_index));
+    }
+    frame.render_stateful_widget(list, area, &mut list_state);
+}
+
+/// Render executed queries, newest first
+fn render_history(frame: &mut Frame, app: &mut App, area: Rect) {
+    let header = Row::new(vec!["When", "Kind", "Entity", "Rows", "Time", "Query"])
+        .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+        .bottom_margin(1);
+
+    let rows: Vec<Row> = app
+        .filtered_history
+        .iter()
+        .map(|&i| {
+            let entry = &app.history.entries[i];
+            let (rows, style) = match (&entry.error, entry.rows) {
+                (Some(_), _) => ("error".to_string(), Style::default().fg(Color::Red)),
+                (None, rows) => (rows.map(|r| r.to_string()).unwrap_or_default(), Style::default()),
+            };
+            // Show multi-line queries on one line
+            let text = entry.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            Row::new(vec![
+                entry.timestamp.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string(),
+                entry.kind.label().to_string(),
+                entry.entity.clone().unwrap_or_default(),
+                rows,
+                format!("{} ms", entry.duration_ms),
+                text,
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let widths = [
+        Constraint::Length(16),
+        Constraint::Length(8),
+        Constraint::Length(24),
+        Constraint::Length(6),
+        Constraint::Length(9),
+        Constraint::Min(0),
+    ];
+
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" Query History ({}/{}) ", app.filtered_history.len(), app.history.entries.len()))
+                .title_bottom(" Enter: Open │ /: Search │ Esc: Back "),
+        )
+        .row_highlight_style(
+            Style::default()
+                .bg(Color::Rgb(50, 50, 80))
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+
+    let mut table_state = TableState::default();
+    if !app.filtered_history.is_empty() {
+        table_state.select(Some(app.history_index));
+    }
+    frame.render_stateful_widget(table, area, &mut table_state);
+}
+
 /// Render FetchXML Console
 fn render_fetchxml_console(frame: &mut Frame, app: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3),  // Input
-            Constraint::Min(0),     // Results
+            Constraint::Percentage(60), // Editor
+            Constraint::Length(3),      // Status
+            Constraint::Min(0),         // Results
         ])
         .split(area);
 
-    // Input area
+    let editing = matches!(app.input_mode, InputMode::FetchXML);
+    let (cursor_row, cursor_col) = app.fetchxml_editor.cursor();
+    let error_line = app.fetchxml_error.as_ref().map(|e| e.line);
+
     let input_block = Block::default()
         .borders(Borders::ALL)
-        .title("FetchXML Query (Press 'Enter' to execute, 'Esc' to exit)")
-        .style(if matches!(app.input_mode, InputMode::FetchXML) {
+        .title(" FetchXML Query ")
+        .title_bottom(" F5: Execute │ Ctrl+S: Save │ Ctrl+Z: Undo │ ↑↓ at ends: History │ Esc: Exit ")
+        .border_style(if editing {
             Style::default().fg(Color::Yellow)
         } else {
             Style::default()
         });
+    let inner = input_block.inner(chunks[0]);
+    frame.render_widget(input_block, chunks[0]);
 
-    let input = Paragraph::new(app.fetchxml_query.as_str())
-        .block(input_block)
-        .wrap(Wrap { trim: false });
-    frame.render_widget(input, chunks[0]);
+    let lines = app.fetchxml_editor.lines();
+    let gutter_width = lines.len().to_string().len().max(3);
+    let [gutter_area, text_area] = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(gutter_width as u16 + 1), Constraint::Min(0)])
+        .areas(inner);
+
+    // Keep the cursor visible, scrolling by display width so wide characters
+    // do not push it off screen
+    let inner_height = inner.height as usize;
+    let scroll = cursor_row.saturating_sub(inner_height.saturating_sub(1));
+    let cursor_x = app.fetchxml_editor.cursor_display_col();
+    let hscroll = cursor_x.saturating_sub((text_area.width as usize).saturating_sub(1));
+
+    let gutter: Vec<Line> = (scroll..lines.len())
+        .take(inner_height)
+        .map(|i| {
+            let gutter_style = if error_line == Some(i + 1) {
+                Style::default().fg(Color::White).bg(Color::Red)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            Line::from(Span::styled(format!("{:>width$} ", i + 1, width = gutter_width), gutter_style))
+        })
+        .collect();
+    let text: Vec<Line> = highlight_xml(lines)
+        .into_iter()
+        .skip(scroll)
+        .take(inner_height)
+        .collect();
+    frame.render_widget(Paragraph::new(gutter), gutter_area);
+    frame.render_widget(Paragraph::new(text).scroll((0, hscroll as u16)), text_area);
 
     // Position cursor
-    if matches!(app.input_mode, InputMode::FetchXML) {
-        frame.set_cursor_position(Position::new(
-            chunks[0].x + app.fetchxml_cursor as u16 + 1,
-            chunks[0].y + 1,
-        ));
+    if editing {
+        let x = text_area.x + (cursor_x - hscroll) as u16;
+        let y = text_area.y + (cursor_row - scroll) as u16;
+        frame.set_cursor_position(Position::new(x, y));
     }
 
+    // Validation status
+    let status = match &app.fetchxml_error {
+        Some(err) => Paragraph::new(format!(" {} ", err))
+            .block(Block::default().borders(Borders::ALL).title(" FetchXML Error "))
+            .style(Style::default().fg(Color::Red)),
+        None => Paragraph::new(format!(" Ln {}, Col {} ", cursor_row + 1, cursor_col + 1))
+            .block(Block::default().borders(Borders::ALL))
+            .style(Style::default().fg(Color::DarkGray)),
+    };
+    frame.render_widget(status, chunks[1]);
+
     // Results area
-    render_query_results(frame, app, chunks[1]);
+    render_query_results(frame, app, chunks[2]);
 }
 
 /// Render system job list
@@ -1671,7 +2174,7 @@ fn render_environment_discovery(frame: &mut Frame, app: &mut App, area: Rect) {
         .iter()
         .map(|&i| {
             let inst = &app.discovered_environments[i];
-            let is_added = app.config.environments.contains(&inst.url.trim_end_matches('/').to_string());
+            let is_added = app.config.profile(&inst.url).is_some();
             
             let name_style = if is_added {
                 Style::default().fg(Color::DarkGray)
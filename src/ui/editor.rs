@@ -0,0 +1,338 @@
+//! Multi-line text editor used by the FetchXML console
+
+use ratatui::prelude::{Line, Span};
+use ratatui::style::{Color, Style};
+use unicode_width::UnicodeWidthStr;
+
+/// Maximum number of undo snapshots kept
+const UNDO_LIMIT: usize = 200;
+
+/// A simple multi-line editor with a line/column cursor and undo
+///
+/// Columns are counted in characters, not bytes, so non-ASCII input is safe.
+/// Rendering uses `cursor_display_col` for the on-screen position.
+#[derive(Debug, Clone)]
+pub struct TextEditor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    undo_stack: Vec<(Vec<String>, usize, usize)>,
+}
+
+impl Default for TextEditor {
+    fn default() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            undo_stack: Vec::new(),
+        }
+    }
+}
+
+impl TextEditor {
+    /// Full text with lines joined by newlines
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|l| l.trim().is_empty())
+    }
+
+    /// Replace the whole text, keeping the previous text for undo
+    pub fn set_text(&mut self, text: &str) {
+        self.snapshot();
+        self.lines = text.split('\n').map(str::to_string).collect();
+        self.row = 0;
+        self.col = 0;
+    }
+
+    /// Cursor position as (row, column), both zero-based
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// Display width of the cursor line up to the cursor, for placing the
+    /// terminal cursor when lines contain wide or combining characters
+    pub fn cursor_display_col(&self) -> usize {
+        self.lines[self.row][..self.byte_index(self.row, self.col)].width()
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].chars().count()
+    }
+
+    /// Byte offset of a character column in a line
+    fn byte_index(&self, row: usize, col: usize) -> usize {
+        self.lines[row]
+            .char_indices()
+            .nth(col)
+            .map(|(i, _)| i)
+            .unwrap_or(self.lines[row].len())
+    }
+
+    fn snapshot(&mut self) {
+        if self.undo_stack.len() >= UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push((self.lines.clone(), self.row, self.col));
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        self.snapshot();
+        let idx = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert(idx, c);
+        self.col += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        self.snapshot();
+        let idx = self.byte_index(self.row, self.col);
+        self.lines[self.row].insert_str(idx, s);
+        self.col += s.chars().count();
+    }
+
+    /// Split the line at the cursor, keeping the current indentation
+    pub fn insert_newline(&mut self) {
+        self.snapshot();
+        let idx = self.byte_index(self.row, self.col);
+        let rest = self.lines[self.row].split_off(idx);
+        let indent: String = self.lines[self.row]
+            .chars()
+            .take_while(|c| *c == ' ')
+            .collect();
+        self.col = indent.chars().count();
+        self.row += 1;
+        self.lines.insert(self.row, format!("{}{}", indent, rest));
+    }
+
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.snapshot();
+            let idx = self.byte_index(self.row, self.col - 1);
+            self.lines[self.row].remove(idx);
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.snapshot();
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.snapshot();
+            let idx = self.byte_index(self.row, self.col);
+            self.lines[self.row].remove(idx);
+        } else if self.row + 1 < self.lines.len() {
+            self.snapshot();
+            let line = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.col = self.line_len(self.row);
+    }
+
+    /// Restore the text as it was before the last edit
+    pub fn undo(&mut self) -> bool {
+        match self.undo_stack.pop() {
+            Some((lines, row, col)) => {
+                self.lines = lines;
+                self.row = row;
+                self.col = col;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Syntax highlight XML, one `Line` per source line
+///
+/// Tags, attribute names, attribute values and comments get their own
+/// colours. Comments and tags may span several lines.
+pub fn highlight_xml(lines: &[String]) -> Vec<Line<'static>> {
+    let tag = Style::default().fg(Color::Cyan);
+    let attr_name = Style::default().fg(Color::LightBlue);
+    let attr_value = Style::default().fg(Color::Green);
+    let comment = Style::default().fg(Color::DarkGray);
+    let text = Style::default().fg(Color::White);
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Text,
+        Tag,
+        Value(char),
+        Comment,
+    }
+
+    let mut state = State::Text;
+    let mut out = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        let chars: Vec<char> = line.chars().collect();
+        let mut spans = Vec::new();
+        let mut current = String::new();
+        let mut i = 0;
+
+        let style_of = |state: State| match state {
+            State::Text => text,
+            State::Tag => attr_name,
+            State::Value(_) => attr_value,
+            State::Comment => comment,
+        };
+
+        while i < chars.len() {
+            let c = chars[i];
+            match state {
+                State::Text if c == '<' => {
+                    if !current.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut current), text));
+                    }
+                    if chars[i..].starts_with(&['<', '!', '-', '-']) {
+                        state = State::Comment;
+                        current.push_str("<!--");
+                        i += 4;
+                        continue;
+                    }
+                    // Tag name, including any leading '/' or '?'
+                    let mut name = String::from('<');
+                    i += 1;
+                    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '>' && chars[i] != '/' {
+                        name.push(chars[i]);
+                        i += 1;
+                    }
+                    if i < chars.len() && chars[i] == '/' && name == "<" {
+                        name.push('/');
+                        i += 1;
+                        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '>' {
+                            name.push(chars[i]);
+                            i += 1;
+                        }
+                    }
+                    spans.push(Span::styled(name, tag));
+                    state = State::Tag;
+                    continue;
+                }
+                State::Tag if c == '>' || (c == '/' && chars.get(i + 1) == Some(&'>')) => {
+                    if !current.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut current), attr_name));
+                    }
+                    let end: String = if c == '/' { "/>".to_string() } else { ">".to_string() };
+                    i += end.len();
+                    spans.push(Span::styled(end, tag));
+                    state = State::Text;
+                    continue;
+                }
+                State::Tag if c == '"' || c == '\'' => {
+                    if !current.is_empty() {
+                        spans.push(Span::styled(std::mem::take(&mut current), attr_name));
+                    }
+                    current.push(c);
+                    state = State::Value(c);
+                }
+                State::Value(quote) if c == quote => {
+                    current.push(c);
+                    spans.push(Span::styled(std::mem::take(&mut current), attr_value));
+                    state = State::Tag;
+                }
+                State::Comment if chars[i..].starts_with(&['-', '-', '>']) => {
+                    current.push_str("-->");
+                    spans.push(Span::styled(std::mem::take(&mut current), comment));
+                    state = State::Text;
+                    i += 3;
+                    continue;
+                }
+                _ => current.push(c),
+            }
+            i += 1;
+        }
+
+        if !current.is_empty() {
+            spans.push(Span::styled(current, style_of(state)));
+        }
+        out.push(Line::from(spans));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editing_non_ascii_and_undo() {
+        let mut editor = TextEditor::default();
+        editor.insert_str("<condition value='Ærø'/>");
+        for _ in 0..4 {
+            editor.move_left();
+        }
+        editor.backspace();
+        editor.insert_char('ö');
+        editor.insert_newline();
+        assert_eq!(editor.text(), "<condition value='Æö\nø'/>");
+        assert_eq!(editor.cursor(), (1, 0));
+
+        while editor.undo() {}
+        assert_eq!(editor.text(), "");
+        editor.insert_str("<condition value='Ærø'/>");
+        assert_eq!(editor.text(), "<condition value='Ærø'/>");
+    }
+
+    #[test]
+    fn test_cursor_display_col_counts_wide_characters() {
+        let mut editor = TextEditor::default();
+        editor.insert_str("名前='x'");
+        editor.move_home();
+        editor.move_right();
+        editor.move_right();
+        assert_eq!(editor.cursor(), (0, 2));
+        assert_eq!(editor.cursor_display_col(), 4);
+    }
+}
@@ -0,0 +1,194 @@
+//! Relationship graph centred on one entity
+//!
+//! Neighbours are grouped by the kind of relationship: entities the centre
+//! looks up (N:1) on the left, many-to-many partners in the middle and
+//! entities that look the centre up (1:N) on the right. The relationships of
+//! an entity are loaded the first time it is centred and kept afterwards.
+
+use super::app::View;
+use crate::models::RelationshipMetadata;
+use std::collections::HashMap;
+
+/// A column of neighbours, in the order they are drawn from left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Side {
+    #[default]
+    Parents,
+    Peers,
+    Children,
+}
+
+impl Side {
+    const ALL: [Side; 3] = [Side::Parents, Side::Peers, Side::Children];
+}
+
+/// An entity one relationship away from the centre
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbour {
+    pub entity: String,
+    pub relationship: String,
+    /// Lookup column of a 1:N or N:1 relationship
+    pub attribute: Option<String>,
+}
+
+/// One-to-many, many-to-one and many-to-many relationships of an entity
+#[derive(Debug, Clone, Default)]
+pub struct EntityRelationships {
+    pub one_to_many: Vec<RelationshipMetadata>,
+    pub many_to_one: Vec<RelationshipMetadata>,
+    pub many_to_many: Vec<RelationshipMetadata>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RelationshipGraph {
+    pub center: String,
+    pub parents: Vec<Neighbour>,
+    pub peers: Vec<Neighbour>,
+    pub children: Vec<Neighbour>,
+    pub side: Side,
+    pub index: usize,
+    /// Entities centred before the current one, for going back
+    pub trail: Vec<String>,
+    /// View the graph was opened from, returned to on Esc
+    pub opened_from: View,
+    /// Relationships loaded so far, keyed by entity logical name
+    pub loaded: HashMap<String, EntityRelationships>,
+}
+
+impl RelationshipGraph {
+    /// Centre the graph on an entity whose relationships are loaded
+    ///
+    /// The previous centre is remembered when `remember` is set, so going
+    /// back returns to it.
+    pub fn center_on(&mut self, entity: &str, remember: bool) {
+        let Some(relationships) = self.loaded.get(entity) else {
+            return;
+        };
+        let neighbours = |list: &[RelationshipMetadata], other: fn(&RelationshipMetadata) -> Option<&String>| {
+            let mut neighbours: Vec<Neighbour> = list
+                .iter()
+                .map(|r| Neighbour {
+                    // Self-referencing relationships fall back to the centre
+                    entity: other(r)
+                        .cloned()
+                        .or_else(|| r.get_related_entity(entity))
+                        .unwrap_or_else(|| entity.to_string()),
+                    relationship: r.get_name(),
+                    attribute: r.referencing_attribute.clone(),
+                })
+                .collect();
+            neighbours.sort_by(|a, b| (&a.entity, &a.relationship).cmp(&(&b.entity, &b.relationship)));
+            neighbours
+        };
+        let parents = neighbours(&relationships.many_to_one, |r| r.referenced_entity.as_ref());
+        let children = neighbours(&relationships.one_to_many, |r| r.referencing_entity.as_ref());
+        let peers = neighbours(&relationships.many_to_many, |_| None);
+
+        if remember && !self.center.is_empty() && self.center != entity {
+            self.trail.push(std::mem::take(&mut self.center));
+        }
+        self.center = entity.to_string();
+        self.parents = parents;
+        self.peers = peers;
+        self.children = children;
+        self.side = Side::ALL
+            .into_iter()
+            .find(|&side| !self.neighbours(side).is_empty())
+            .unwrap_or_default();
+        self.index = 0;
+    }
+
+    pub fn neighbours(&self, side: Side) -> &[Neighbour] {
+        match side {
+            Side::Parents => &self.parents,
+            Side::Peers => &self.peers,
+            Side::Children => &self.children,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Neighbour> {
+        self.neighbours(self.side).get(self.index)
+    }
+
+    pub fn move_up(&mut self) {
+        self.index = self.index.saturating_sub(1);
+    }
+
+    pub fn move_down(&mut self) {
+        if self.index + 1 < self.neighbours(self.side).len() {
+            self.index += 1;
+        }
+    }
+
+    /// Move to the nearest non-empty column to the left
+    pub fn move_left(&mut self) {
+        let position = Side::ALL.iter().position(|&s| s == self.side).unwrap_or(0);
+        let target = Side::ALL[..position].iter().rev().find(|&&s| !self.neighbours(s).is_empty());
+        self.move_to(target.copied());
+    }
+
+    /// Move to the nearest non-empty column to the right
+    pub fn move_right(&mut self) {
+        let position = Side::ALL.iter().position(|&s| s == self.side).unwrap_or(0);
+        let target = Side::ALL[position + 1..].iter().find(|&&s| !self.neighbours(s).is_empty());
+        self.move_to(target.copied());
+    }
+
+    fn move_to(&mut self, side: Option<Side>) {
+        if let Some(side) = side {
+            self.side = side;
+            self.index = self.index.min(self.neighbours(side).len() - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn relationships(value: serde_json::Value) -> Vec<RelationshipMetadata> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_center_and_move_across_columns() {
+        let mut graph = RelationshipGraph::default();
+        graph.loaded.insert("contact".to_string(), EntityRelationships {
+            one_to_many: relationships(json!([
+                { "SchemaName": "contact_opportunities", "ReferencedEntity": "contact",
+                  "ReferencingEntity": "opportunity", "ReferencingAttribute": "parentcontactid" },
+            ])),
+            many_to_one: relationships(json!([
+                { "SchemaName": "contact_customer_accounts", "ReferencedEntity": "account",
+                  "ReferencingEntity": "contact", "ReferencingAttribute": "parentcustomerid" },
+                { "SchemaName": "contact_master_contact", "ReferencedEntity": "contact",
+                  "ReferencingEntity": "contact", "ReferencingAttribute": "masterid" },
+            ])),
+            many_to_many: Vec::new(),
+        });
+        graph.loaded.insert("account".to_string(), EntityRelationships::default());
+
+        graph.center_on("contact", true);
+        assert_eq!(graph.side, Side::Parents);
+        graph.move_down();
+        assert_eq!(graph.selected().unwrap().entity, "contact");
+        assert_eq!(graph.selected().unwrap().attribute.as_deref(), Some("masterid"));
+
+        // The empty many-to-many column is skipped
+        graph.move_right();
+        assert_eq!(graph.side, Side::Children);
+        assert_eq!(graph.selected().unwrap().entity, "opportunity");
+        graph.move_right();
+        assert_eq!(graph.side, Side::Children);
+        graph.move_left();
+        assert_eq!(graph.selected().unwrap().entity, "account");
+
+        graph.center_on("account", true);
+        assert_eq!(graph.center, "account");
+        assert_eq!(graph.trail, ["contact"]);
+        assert!(graph.selected().is_none());
+        graph.center_on("opportunity", true);
+        assert_eq!(graph.center, "account");
+    }
+}
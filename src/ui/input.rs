@@ -1,9 +1,11 @@
 //! Input handling and key bindings
 
 use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
 
 /// Whether vim-style keybindings are enabled
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum KeyBindings {
     /// Arrow keys for navigation (default)
     Arrows,
@@ -29,6 +31,10 @@ pub enum InputMode {
     FetchXML,
     /// SQL console input mode
     SQLQuery,
+    /// Name prompt for saving or renaming a query
+    QueryName,
+    /// Popup with the full details of the last error
+    ErrorDetails,
 }
 
 impl KeyBindings {
@@ -2,7 +2,10 @@
 
 pub mod components;
 mod app;
+mod editor;
+mod graph;
 mod input;
+mod tasks;
 
-pub use app::{App, AppState, View, EntityTab, QueryMode};
+pub use app::{App, AppState, View, EntityTab, QueryMode, FilterCondition};
 pub use input::{InputMode, KeyBindings};
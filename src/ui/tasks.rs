@@ -0,0 +1,162 @@
+//! Background tasks for API calls
+//!
+//! Requests run on spawned tokio tasks so the event loop keeps drawing and
+//! handling keys while they are in flight. A finished task sends back a
+//! closure that applies its result to the `App`; `run_app` drains these
+//! between frames.
+
+use super::app::{App, View};
+use std::future::Future;
+use std::time::Instant;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::AbortHandle;
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Applies the result of a finished task to the app
+pub type Update = Box<dyn FnOnce(&mut App) + Send>;
+
+struct RunningTask {
+    id: u64,
+    label: &'static str,
+    /// View that shows a loading placeholder until the task finishes
+    view: Option<View>,
+    started: Instant,
+    handle: AbortHandle,
+}
+
+/// Tasks in flight and the channel their results come back on
+pub struct Tasks {
+    sender: UnboundedSender<(u64, Update)>,
+    receiver: UnboundedReceiver<(u64, Update)>,
+    running: Vec<RunningTask>,
+    next_id: u64,
+}
+
+impl Default for Tasks {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver,
+            running: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl Tasks {
+    /// Run `future` on a tokio task and apply its output with `apply`
+    ///
+    /// A running task with the same label is cancelled first, so repeating
+    /// an action only keeps the latest request.
+    pub fn spawn<T, F, A>(&mut self, label: &'static str, view: Option<View>, future: F, apply: A)
+    where
+        T: Send + 'static,
+        F: Future<Output = T> + Send + 'static,
+        A: FnOnce(&mut App, T) + Send + 'static,
+    {
+        self.cancel(label);
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let sender = self.sender.clone();
+        let handle = tokio::spawn(async move {
+            let output = future.await;
+            let update: Update = Box::new(move |app| apply(app, output));
+            let _ = sender.send((id, update));
+        });
+
+        self.running.push(RunningTask {
+            id,
+            label,
+            view,
+            started: Instant::now(),
+            handle: handle.abort_handle(),
+        });
+    }
+
+    fn cancel(&mut self, label: &str) {
+        self.running.retain(|task| {
+            if task.label == label {
+                task.handle.abort();
+            }
+            task.label != label
+        });
+    }
+
+    /// Cancel the tasks loading a view, returning whether any was running
+    ///
+    /// Background tasks such as the metadata cache refresh keep running.
+    pub fn cancel_foreground(&mut self) -> bool {
+        let before = self.running.len();
+        self.running.retain(|task| {
+            if task.view.is_some() {
+                task.handle.abort();
+            }
+            task.view.is_none()
+        });
+        self.running.len() != before
+    }
+
+    pub fn is_running(&self, label: &str) -> bool {
+        self.running.iter().any(|task| task.label == label)
+    }
+
+    /// Whether a running task is loading the contents of this view
+    pub fn is_loading(&self, view: View) -> bool {
+        self.running.iter().any(|task| task.view == Some(view))
+    }
+
+    /// Spinner frame and label of the most recently started task
+    pub fn status(&self) -> Option<(char, &'static str)> {
+        let task = self.running.last()?;
+        let frame = (task.started.elapsed().as_millis() / 100) as usize % SPINNER.len();
+        Some((SPINNER[frame], task.label))
+    }
+
+    /// Take the update of the next finished task, skipping cancelled ones
+    pub fn next_update(&mut self) -> Option<Update> {
+        while let Ok((id, update)) = self.receiver.try_recv() {
+            if let Some(index) = self.running.iter().position(|task| task.id == id) {
+                self.running.remove(index);
+                return Some(update);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_same_label_replaces_and_cancel_drops_results() {
+        let mut tasks = Tasks::default();
+        tasks.spawn("Loading entities", Some(View::Entities), std::future::pending::<()>(), |_, _| {});
+        tasks.spawn("Loading entities", Some(View::Entities), async {}, |_, _| {});
+        assert_eq!(tasks.running.len(), 1);
+        assert!(tasks.is_loading(View::Entities));
+        assert!(!tasks.is_loading(View::Solutions));
+
+        tokio::task::yield_now().await;
+        assert!(tasks.cancel_foreground());
+        assert!(tasks.running.is_empty());
+        assert!(tasks.next_update().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_foreground_keeps_background_tasks() {
+        let mut tasks = Tasks::default();
+        tasks.spawn("Loading entities", Some(View::Entities), std::future::pending::<()>(), |_, _| {});
+        tasks.spawn("Refreshing metadata cache", None, async {}, |_, _| {});
+
+        assert!(tasks.cancel_foreground());
+        assert!(!tasks.cancel_foreground());
+        assert!(tasks.is_running("Refreshing metadata cache"));
+
+        tokio::task::yield_now().await;
+        assert!(tasks.next_update().is_some());
+    }
+}